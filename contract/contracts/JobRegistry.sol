@@ -1,6 +1,11 @@
 // SPDX-License-Identifier: MIT
 pragma solidity ^0.8.19;
 
+interface IERC20 {
+    function transfer(address to, uint256 amount) external returns (bool);
+    function transferFrom(address from, address to, uint256 amount) external returns (bool);
+}
+
 contract JobRegistry {
     struct Job {
         string jobId;  
@@ -28,6 +33,8 @@ contract JobRegistry {
 
     mapping(address => Node) public providers;
     mapping(string => Job) public jobs;
+    // ERC-20 contract holding a job's bounty; zero for native currency.
+    mapping(string => address) public jobTokens;
     string[] public jobIds;
 
 function createJob(
@@ -57,6 +64,40 @@ function createJob(
     jobCount++;
 }
 
+function createTokenJob(
+    string memory jobId,
+    string memory datasetCID,
+    string memory containerCID,
+    uint256 deadline,
+    string memory requiredSpecs,
+    uint256 minMemory,
+    address token,
+    uint256 amount
+) external {
+    require(jobs[jobId].owner == address(0), "Job already exists");
+    require(token != address(0), "Token address required");
+    require(amount > 0, "Bounty required");
+
+    jobs[jobId] = Job({
+        jobId: jobId,
+        owner: msg.sender,
+        bounty: amount,
+        datasetCID: datasetCID,
+        containerCID: containerCID,
+        deadline: deadline,
+        provider: address(0),
+        resultHash: 0x0,
+        completed: false,
+        requiredSpecs: requiredSpecs,
+        minMemory: minMemory
+    });
+    jobTokens[jobId] = token;
+    jobIds.push(jobId);
+    jobCount++;
+
+    require(IERC20(token).transferFrom(msg.sender, address(this), amount), "Token transfer failed");
+}
+
     function getJobIds() external view returns (string[] memory) {
         return jobIds;
     }
@@ -102,6 +143,7 @@ function createJob(
         Job storage job = jobs[jobId];
         require(job.completed, "Job not completed yet");
         require(msg.sender == job.owner, "Only owner can release bounty");
+        require(jobTokens[jobId] == address(0), "Use releaseToken for token bounties");
 
         address payable provider = payable(job.provider);
         uint256 amount = job.bounty;
@@ -110,6 +152,19 @@ function createJob(
         provider.transfer(amount);
     }
 
+    function releaseToken(string memory jobId) external {
+        Job storage job = jobs[jobId];
+        address token = jobTokens[jobId];
+        require(token != address(0), "Not a token bounty");
+        require(job.completed, "Job not completed yet");
+        require(msg.sender == job.owner, "Only owner can release bounty");
+
+        uint256 amount = job.bounty;
+        job.bounty = 0;
+
+        require(IERC20(token).transfer(job.provider, amount), "Token transfer failed");
+    }
+
     function extendDeadline(string memory jobId, uint256 newDeadline) external {
         Job storage job = jobs[jobId];
         require(msg.sender == job.owner, "Only owner can extend deadline");
@@ -5,6 +5,12 @@ import { useEffect, useMemo, useState } from "react";
 import { useAppKitAccount } from "@reown/appkit/react";
 import { ethers } from "ethers";
 
+const ERC20_ABI = [
+  "function decimals() view returns (uint8)",
+  "function allowance(address owner, address spender) view returns (uint256)",
+  "function approve(address spender, uint256 amount) returns (bool)",
+];
+
 export const useJobRegistry = () => {
   const [signer, setSigner] = useState();
   const { isConnected } = useAppKitAccount();
@@ -57,6 +63,44 @@ export const useJobRegistry = () => {
     return tx.wait();
   };
 
+  // Lets the registry pull `amount` whole tokens from the signer, as
+  // createTokenJob does; returns the amount in the token's base units.
+  const approveToken = async (token, amount) => {
+    if (!contract) throw new Error("Contract not ready");
+    const erc20 = new ethers.Contract(token, ERC20_ABI, signer);
+    const units = ethers.parseUnits(amount, await erc20.decimals());
+    const allowance = await erc20.allowance(await signer.getAddress(), contractAddress);
+    if (allowance < units) {
+      const tx = await erc20.approve(contractAddress, units);
+      await tx.wait();
+    }
+    return units;
+  };
+
+  const createTokenJob = async ({
+    jobId,
+    datasetCID,
+    containerCID,
+    deadline,
+    requiredSpecs,
+    minMemory,
+    token,
+    amount,
+  }) => {
+    if (!contract) throw new Error("Contract not ready");
+    const tx = await contract.createTokenJob(
+      jobId,
+      datasetCID,
+      containerCID,
+      deadline,
+      requiredSpecs,
+      minMemory,
+      token,
+      amount
+    );
+    return tx.wait();
+  };
+
   const claimJob = async (jobId) => {
     if (!contract) throw new Error("Contract not ready");
     const tx = await contract.claimJob(jobId);
@@ -108,6 +152,8 @@ export const useJobRegistry = () => {
     signer,
     createNode,
     createJob,
+    approveToken,
+    createTokenJob,
     claimJob,
     assignProvider,
     submitResult,
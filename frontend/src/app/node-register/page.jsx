@@ -3,6 +3,7 @@ import React, { useState, useEffect } from "react";
 import axios from "axios";
 import { useAppKitAccount } from "@reown/appkit/react";
 import { useJobRegistry } from "../../../hooks/useJobRegistry";
+import { ownerHeaders } from "@/lib/ownerAuth";
 
 export default function NodeRegistration() {
   const [gpuSpecs, setGpuSpecs] = useState("");
@@ -12,7 +13,7 @@ export default function NodeRegistration() {
   const [notification, setNotification] = useState(null);
   const [nodes, setNodes] = useState([]);
   const { address, isConnected } = useAppKitAccount();
-  const { createNode } = useJobRegistry();
+  const { createNode, signer } = useJobRegistry();
 
   useEffect(() => {
     const fetchNodes = async () => {
@@ -40,15 +41,17 @@ export default function NodeRegistration() {
     try {
       const nodeId = generateNodeId();
 
+      const body = JSON.stringify({
+        nodeId,
+        gpuName,
+        gpuSpecs,
+        owner: address,
+        memoryAvailable: parseInt(memory),
+      });
       const res = await axios.post(
         `${process.env.NEXT_PUBLIC_BASE_URL}/nodes/register`,
-        {
-          nodeId,
-          gpuName,
-          gpuSpecs,
-          owner: address,
-          memoryAvailable: parseInt(memory),
-        }
+        body,
+        { headers: await ownerHeaders(signer, "POST", "/nodes/register", body) }
       );
 
       await createNode(gpuSpecs, memory, gpuName);
@@ -7,15 +7,17 @@ import { Label } from "@/components/ui/label";
 import { useAppKitAccount } from "@reown/appkit/react";
 import { useJobRegistry } from "../../hooks/useJobRegistry";
 import axios from "axios";
+import { ethers } from "ethers";
 
 export default function JobSubmissionForm() {
-  const { createJob } = useJobRegistry();
+  const { createJob, approveToken, createTokenJob } = useJobRegistry();
   const { isConnected, address } = useAppKitAccount();
 
   const [form, setForm] = useState({
     datasetCID: "",
     containerCID: "",
     bounty: "",
+    bountyToken: "",
     deadline: "",
     requiredSpecs: "",
     minMemory: "",
@@ -38,6 +40,8 @@ export default function JobSubmissionForm() {
       newErrors.containerCID = "Container CID is required";
     if (!form.bounty || parseFloat(form.bounty) <= 0)
       newErrors.bounty = "Bounty must be greater than 0";
+    if (form.bountyToken.trim() && !ethers.isAddress(form.bountyToken.trim()))
+      newErrors.bountyToken = "Token must be an ERC-20 contract address";
     if (!form.deadline) newErrors.deadline = "Deadline is required";
     if (!form.requiredSpecs)
       newErrors.requiredSpecs = "Required specs are required";
@@ -82,10 +86,15 @@ export default function JobSubmissionForm() {
       const timestamp = BigInt(
         Math.floor(new Date(form.deadline).getTime() / 1000)
       );
+      const token = form.bountyToken.trim();
+      // The scheduler checks the registry's allowance when it accepts a token
+      // job, so approve before posting it.
+      const amount = token ? await approveToken(token, form.bounty.toString()) : null;
       const job = {
         owner: address,
         jobId,
         bounty : form.bounty,
+        ...(token && { bountyToken: token }),
         deadline : form.deadline,
         dataset : form.datasetCID,
         containerCID: form.containerCID,
@@ -94,16 +103,18 @@ export default function JobSubmissionForm() {
         minMemory : parseInt(form.minMemory),
         completed: false,
       };
-      await postJob(job);
-      const response = await createJob({
+      if (!(await postJob(job))) throw new Error("Scheduler rejected the job");
+      const onChain = {
         jobId,
         datasetCID: form.datasetCID,
         containerCID: form.containerCID,
         deadline: timestamp,
         requiredSpecs: form.requiredSpecs,
         minMemory: parseInt(form.minMemory),
-        value: form.bounty.toString(),
-      });
+      };
+      const response = token
+        ? await createTokenJob({ ...onChain, token, amount })
+        : await createJob({ ...onChain, value: form.bounty.toString() });
 console.log("Job creation transaction:", response);
       setNotification({
         type: "success",
@@ -113,6 +124,7 @@ console.log("Job creation transaction:", response);
         datasetCID: "",
         containerCID: "",
         bounty: "",
+        bountyToken: "",
         deadline: "",
         requiredSpecs: "",
         minMemory: "",
@@ -135,7 +147,13 @@ console.log("Job creation transaction:", response);
             {[
               { label: "IPFS Dataset CID Link", name: "datasetCID", type: "text" },
               { label: "Docker Image name", name: "containerCID", type: "text" },
-              { label: "Bounty Amount (U2U)", name: "bounty", type: "number" },
+              { label: "Bounty Amount (U2U, or the token below)", name: "bounty", type: "number" },
+              {
+                label: "Bounty Token (ERC-20 address, blank for U2U)",
+                name: "bountyToken",
+                type: "text",
+                optional: true,
+              },
               { label: "Deadline", name: "deadline", type: "date" },
               { label: "Required Specs", name: "requiredSpecs", type: "text" },
               {
@@ -152,7 +170,7 @@ console.log("Job creation transaction:", response);
                   value={form[field.name]}
                   onChange={handleChange}
                   placeholder={field.label}
-                  required
+                  required={!field.optional}
                 />
                 {errors[field.name] && (
                   <p className="text-red-500 text-sm mt-1">
@@ -0,0 +1,19 @@
+import { ethers } from "ethers";
+
+// Headers the scheduler checks on owner-only requests: an EIP-191 signature
+// by the owner's wallet over the method, path, a unix timestamp and the
+// keccak256 of the body. Send `body` exactly as signed, e.g. the string from
+// JSON.stringify.
+export async function ownerHeaders(signer, method, path, body = "") {
+  if (!signer) throw new Error("Wallet not connected");
+  const timestamp = Math.floor(Date.now() / 1000);
+  const bodyHash = ethers.keccak256(ethers.toUtf8Bytes(body));
+  const signature = await signer.signMessage(
+    `gpu-scheduler:${method}:${path}:${timestamp}:${bodyHash}`
+  );
+  return {
+    "X-Owner-Signature": signature,
+    "X-Signature-Timestamp": String(timestamp),
+    "Content-Type": "application/json",
+  };
+}
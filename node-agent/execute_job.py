@@ -1,42 +1,161 @@
+import base64
 import subprocess
 import hashlib
+import json
 import os
 import shutil
+import sys
 import tempfile
+import time
 import requests
 
 BASE_DIR = os.path.dirname(os.path.abspath(__file__))
 IPFS_PATH = os.path.join(BASE_DIR, "job_data")
+DATASET_CACHE = os.path.join(BASE_DIR, "dataset_cache")
 
-def download_dataset(dataset_cid, job_folder):
-    if dataset_cid.startswith("http"):
-        try:
-            local_file = os.path.join(job_folder, "dataset_file")
-            print(f"[+] Downloading dataset from {dataset_cid}...")
-            r = requests.get(dataset_cid)
+IPFS_GATEWAY = os.getenv("IPFS_GATEWAY", "https://ipfs.io")
+
+
+def dataset_url(dataset):
+    """Resolves a scheduler dataset reference to an HTTP(S) URL."""
+    if isinstance(dataset, str):
+        # Schedulers that predate typed datasets send a bare URL or CID.
+        return dataset if dataset.startswith("http") else f"{IPFS_GATEWAY}/ipfs/{dataset}"
+    kind = dataset.get("type")
+    if kind == "ipfs":
+        gateway = dataset.get("gateway") or IPFS_GATEWAY
+        return f"{gateway}/ipfs/{dataset['cid']}{dataset.get('path') or ''}"
+    if kind == "https":
+        return dataset["url"]
+    if kind == "s3":
+        # Public objects only; path-style works for AWS and S3-compatible stores.
+        region = dataset.get("region")
+        endpoint = dataset.get("endpoint") or (
+            f"https://s3.{region}.amazonaws.com" if region else "https://s3.amazonaws.com"
+        )
+        return f"{endpoint}/{dataset['bucket']}/{dataset['key']}"
+    raise ValueError(f"unsupported dataset type: {kind}")
+
+
+def dataset_uri(dataset):
+    """The single-string form the scheduler accepts in a cache report; None
+    for inline data, which is never cached."""
+    if isinstance(dataset, str):
+        return dataset
+    kind = dataset.get("type")
+    if kind == "ipfs":
+        return f"ipfs://{dataset['cid']}{dataset.get('path') or ''}"
+    if kind == "https":
+        return dataset["url"]
+    if kind == "s3":
+        return f"s3://{dataset['bucket']}/{dataset['key']}"
+    return None
+
+
+def cache_path(uri):
+    return os.path.join(DATASET_CACHE, hashlib.sha256(uri.encode()).hexdigest())
+
+
+def cached_datasets():
+    """Datasets kept in the local cache, as reported to the scheduler."""
+    try:
+        with open(os.path.join(DATASET_CACHE, "index.json")) as f:
+            return json.load(f)
+    except (OSError, ValueError):
+        return []
+
+
+def cache_dataset(uri, content):
+    os.makedirs(DATASET_CACHE, exist_ok=True)
+    with open(cache_path(uri), "wb") as f:
+        f.write(content)
+    uris = cached_datasets()
+    if uri not in uris:
+        uris.append(uri)
+        with open(os.path.join(DATASET_CACHE, "index.json"), "w") as f:
+            json.dump(uris, f)
+
+
+def download_dataset(dataset, job_folder):
+    local_file = os.path.join(job_folder, "dataset_file")
+    try:
+        uri = dataset_uri(dataset)
+        if isinstance(dataset, dict) and dataset.get("type") == "inline":
+            content = base64.b64decode(dataset["data"])
+        elif uri in cached_datasets() and os.path.exists(cache_path(uri)):
+            print(f"[+] Using cached dataset {uri}")
+            with open(cache_path(uri), "rb") as f:
+                content = f.read()
+        else:
+            url = dataset_url(dataset)
+            print(f"[+] Downloading dataset from {url}...")
+            r = requests.get(url)
             r.raise_for_status()
-            with open(local_file, "wb") as f:
-                f.write(r.content)
-            print(f"[+] Dataset downloaded to {local_file}")
-            return True, None
-        except Exception as e:
-            logs = f"Failed to download dataset from URL: {e}"
+            content = r.content
+
+        expected = dataset.get("sha256") if isinstance(dataset, dict) else None
+        if expected and hashlib.sha256(content).hexdigest() != expected:
+            logs = "Dataset checksum does not match the expected sha256"
             print("[-]", logs)
             return False, logs
-    else:
-        # IPFS CLI is skipped entirely for now
-        logs = "Skipping IPFS CLI because it's not installed"
-        print("[!] IPFS CLI not available, skipping download")
+
+        with open(local_file, "wb") as f:
+            f.write(content)
+        if uri:
+            cache_dataset(uri, content)
+        print(f"[+] Dataset downloaded to {local_file}")
+        return True, None
+    except Exception as e:
+        logs = f"Failed to download dataset: {e}"
+        print("[-]", logs)
         return False, logs
 
 
 def run_docker_container(job_folder, container_image, job_id):
+    """Returns (success, logs, usage) where usage covers only the job's own
+    process."""
     print(f"[!] Skipping Docker execution for testing. Job folder: {job_folder}")
-    # Just read the dataset file for testing
+    # Stand in for the container with a child process that prints the
+    # dataset, so what it uses is measured apart from the agent.
     dataset_file = os.path.join(job_folder, "dataset_file")
-    with open(dataset_file, "r") as f:
-        print(f"[+] Dataset content:\n{f.read()}")
-    return True, "Docker skipped for testing"
+    code = "import sys; print(open(sys.argv[1]).read())"
+    returncode, output, usage = run_measured([sys.executable, "-c", code, dataset_file])
+    print(f"[+] Dataset content:\n{output}")
+    if returncode != 0:
+        return False, output, usage
+    return True, "Docker skipped for testing", usage
+
+
+def run_measured(cmd):
+    """Runs `cmd` and returns (returncode, output, usage). The usage is that
+    process's alone, from wait4 and GPU memory sampled while it ran: average
+    busy cores, peak resident memory and peak GPU memory."""
+    started = time.monotonic()
+    peak_gpu = None
+    with tempfile.TemporaryFile() as out:
+        proc = subprocess.Popen(cmd, stdout=out, stderr=subprocess.STDOUT)
+        while True:
+            pid, status, rusage = os.wait4(proc.pid, os.WNOHANG)
+            if pid:
+                break
+            gpu = gpu_memory_gb(proc.pid)
+            if gpu is not None:
+                peak_gpu = max(peak_gpu or 0.0, gpu)
+            time.sleep(0.2)
+        proc.returncode = os.waitstatus_to_exitcode(status)
+        out.seek(0)
+        output = out.read().decode(errors="replace")
+    elapsed = time.monotonic() - started
+    # ru_maxrss is KiB on Linux and bytes on macOS.
+    peak_rss = rusage.ru_maxrss * (1 if sys.platform == "darwin" else 1024)
+    cpu_secs = rusage.ru_utime + rusage.ru_stime
+    usage = {
+        "cpuCores": round(cpu_secs / elapsed, 3) if elapsed > 0 else 0.0,
+        "memoryGb": round(peak_rss / (1024 ** 3), 6),
+    }
+    if peak_gpu is not None:
+        usage["gpuMemoryGb"] = round(peak_gpu, 6)
+    return proc.returncode, output, usage
 
 
 def compute_result_hash(job_folder):
@@ -51,9 +170,39 @@ def compute_result_hash(job_folder):
     print(f"[+] Result hash: {result_hash}")
     return result_hash
 
+def folder_size_gb(folder):
+    total = 0
+    for root, _, files in os.walk(folder):
+        for file in files:
+            total += os.path.getsize(os.path.join(root, file))
+    return total / (1024 ** 3)
+
+
+def gpu_memory_gb(pid):
+    """GPU memory held by process `pid` according to nvidia-smi, or None on
+    machines without it."""
+    try:
+        out = subprocess.run(
+            ["nvidia-smi", "--query-compute-apps=pid,used_memory",
+             "--format=csv,noheader,nounits"],
+            capture_output=True, text=True, timeout=5, check=True,
+        ).stdout
+    except (OSError, subprocess.SubprocessError):
+        return None
+    used_mib = 0
+    for line in out.splitlines():
+        app_pid, _, used = line.partition(",")
+        if app_pid.strip() == str(pid):
+            used_mib += int(used.strip() or 0)
+    return used_mib / 1024
+
+
 def execute_job(job):
+    """Runs a job and returns (result_hash, logs, usage); usage follows the
+    scheduler's resource fields so it can be checked against job['limits']."""
+    started = time.monotonic()
     job_id = job['jobId']
-    dataset_cid = job['dataset']
+    dataset = job['dataset']
     container_image = job['containerCID']
 
     print(f"[+] Executing job {job_id}...")
@@ -63,19 +212,30 @@ def execute_job(job):
         shutil.rmtree(job_folder)
     os.makedirs(job_folder, exist_ok=True)
 
-    success, logs = download_dataset(dataset_cid, job_folder)
+    # CPU, memory and GPU figures come from the job's own process; the
+    # download done by the agent beforehand only counts towards its runtime.
+    def usage(measured=None):
+        report = {
+            "runtimeSecs": round(time.monotonic() - started, 3),
+            "diskGb": round(folder_size_gb(job_folder), 6),
+        }
+        report.update(measured or {})
+        return report
+
+    success, logs = download_dataset(dataset, job_folder)
     if not success:
-        return None, logs
+        return None, logs, usage()
 
-    success, logs = run_docker_container(job_folder, container_image, job_id)
+    success, logs, measured = run_docker_container(job_folder, container_image, job_id)
     if not success:
-        return None, logs
+        return None, logs, usage(measured)
 
     result_hash = compute_result_hash(job_folder)
+    job_usage = usage(measured)
     result_hash = "0x" + result_hash  # Prefix with 0x for hex representation
 
     if os.path.exists(job_folder):
         shutil.rmtree(job_folder)
         print(f"[+] Cleaned up job folder: {job_folder}")
 
-    return result_hash, logs
+    return result_hash, logs, job_usage
@@ -1,8 +1,13 @@
 import requests
 import time
 import hashlib
+import json
 import os
-from execute_job import execute_job
+import subprocess
+import threading
+from eth_account.messages import encode_defunct
+from eth_utils import keccak
+from execute_job import cached_datasets, execute_job
 from config import config
 
 w3 = config["w3"]
@@ -11,49 +16,128 @@ account = config["account"]
 wallet_address = config["wallet_address"]
 
 SCHEDULER_URL = "http://localhost:3000"
-POLL_INTERVAL = 10  # seconds
+POLL_INTERVAL = 10  # seconds, after a failed lease request
+LEASE_WAIT = 30  # seconds the scheduler may hold a lease request open
+HEARTBEAT_INTERVAL = 60  # seconds
+MAX_CACHE_ENTRIES = 1000  # the scheduler's limit per list
 MY_GPU = "Apple M3 GPU"
 NODE_ID = "598b6167-112b-44d7-874f-bcd79c319b4e"
 MY_ADDRESS = "0x1F1f090EEAF77Faae3D626fF7847682B7f66Fc8f"  
+GPU_SPECS = os.getenv("GPU_SPECS", MY_GPU)
+MEMORY_GB = int(os.getenv("MEMORY_GB", "8"))
 
-def assign_provider(job_id, wallet_address):
-    payload = {
-        "job_id": job_id,
-        "address": wallet_address
+
+def owner_headers(method, path, body=b""):
+    """Signs the request as the node's owner, the wallet of PRIVATE_KEY. The
+    signature covers the exact body bytes sent, so send `body` as is."""
+    timestamp = int(time.time())
+    body_hash = "0x" + keccak(body).hex()
+    message = encode_defunct(
+        text=f"gpu-scheduler:{method}:{path}:{timestamp}:{body_hash}"
+    )
+    signature = account.sign_message(message).signature.hex()
+    return {
+        "X-Owner-Signature": signature,
+        "X-Signature-Timestamp": str(timestamp),
+        "Content-Type": "application/json",
     }
-    API_URL = f"{SCHEDULER_URL}/nodes/assign-provider"
-    print(API_URL)
-    print(payload)
 
-    
-    try:
-        response = requests.post(API_URL, json=payload, timeout=10)
-        response.raise_for_status()
-        data = response.json()
-        print(f"[+] Job assigned successfully: {data}")
-        return data
-    except requests.exceptions.HTTPError as errh:
-        print(f"[!] HTTP error: {errh} - {response.text}")
-    except requests.exceptions.ConnectionError as errc:
-        print(f"[!] Connection error: {errc}")
-    except requests.exceptions.Timeout as errt:
-        print(f"[!] Timeout error: {errt}")
-    except requests.exceptions.RequestException as err:
-        print(f"[!] Something went wrong: {err}")
-
-
-def get_assigned_jobs():
+
+def cached_images():
+    """Images in the local Docker store; none when Docker is unavailable."""
     try:
-        res = requests.get(f"{SCHEDULER_URL}/nodes/{NODE_ID}/jobs")
-        print("response" , res.json())
-        if res.status_code == 200:
-            return res.json()
-        else:
-            print("[-] Failed to fetch jobs:", res.text)
-            return []
-    except Exception as e:
-        print("[-] Error fetching jobs:", e)
+        out = subprocess.run(
+            ["docker", "image", "ls", "--format", "{{.Repository}}:{{.Tag}}"],
+            capture_output=True, text=True, timeout=10, check=True,
+        ).stdout
+    except (OSError, subprocess.SubprocessError):
         return []
+    return [line for line in out.splitlines() if "<none>" not in line]
+
+
+def cache_report():
+    """What this node has cached, so the scheduler can place jobs near it."""
+    return {
+        "datasets": cached_datasets()[-MAX_CACHE_ENTRIES:],
+        "images": cached_images()[:MAX_CACHE_ENTRIES],
+    }
+
+
+def heartbeat():
+    try:
+        res = requests.post(
+            f"{SCHEDULER_URL}/nodes/{NODE_ID}/heartbeat",
+            json={"cache": cache_report()},
+            timeout=10,
+        )
+        res.raise_for_status()
+    except requests.exceptions.RequestException as e:
+        print(f"[-] Heartbeat failed: {e}")
+
+
+def register_node():
+    path = "/nodes/register"
+    payload = {
+        "nodeId": NODE_ID,
+        "gpuName": MY_GPU,
+        "gpuSpecs": GPU_SPECS,
+        "owner": wallet_address,
+        "memoryAvailable": MEMORY_GB,
+        "cache": cache_report(),
+    }
+    body = json.dumps(payload).encode()
+    try:
+        res = requests.post(
+            f"{SCHEDULER_URL}{path}",
+            data=body,
+            headers=owner_headers("POST", path, body),
+            timeout=30,
+        )
+        res.raise_for_status()
+        print(f"[+] Registered node {NODE_ID}")
+    except requests.exceptions.RequestException as e:
+        print(f"[-] Node registration failed: {e}")
+
+def acquire_lease():
+    """Waits for the scheduler to assign this node a job and returns the lease,
+    or None when nothing was assigned before the wait ran out."""
+    try:
+        res = requests.post(
+            f"{SCHEDULER_URL}/nodes/{NODE_ID}/lease",
+            json={"wait_secs": LEASE_WAIT},
+            timeout=LEASE_WAIT + 30,
+        )
+    except requests.exceptions.RequestException as e:
+        print("[-] Error requesting a lease:", e)
+        time.sleep(POLL_INTERVAL)
+        return None
+    if res.status_code == 204:
+        return None
+    if res.status_code != 200:
+        # The scheduler could not assign the job it picked (e.g. the chain
+        # call failed) and has put it back in the queue; nothing to run.
+        print("[-] Lease request failed:", res.status_code, res.text)
+        time.sleep(POLL_INTERVAL)
+        return None
+    return res.json()
+
+
+def keep_renewing(lease, stop, lost):
+    """Renews the lease until `stop` is set; sets `lost` if the scheduler no
+    longer recognises it, meaning the job was requeued."""
+    interval = max(lease["ttl_secs"] / 3, 1)
+    url = f"{SCHEDULER_URL}/nodes/{NODE_ID}/lease/{lease['lease_id']}/renew"
+    while not stop.wait(interval):
+        try:
+            res = requests.post(url, timeout=10)
+        except requests.exceptions.RequestException as e:
+            print("[-] Error renewing lease:", e)
+            continue
+        if res.status_code in (404, 410):
+            print(f"[-] Lease {lease['lease_id']} lost:", res.text)
+            lost.set()
+            return
+
 
 # def execute_job(job):
 #     print(f"[+] Executing job {job['id']}...")
@@ -62,7 +146,7 @@ def get_assigned_jobs():
 #     logs = f"Job {job['id']} executed successfully"
 #     return result_hash, logs
 
-def submit_result(job_id, result_hash, logs):
+def submit_result(job_id, result_hash, logs, usage=None):
     global NODE_ID
     if NODE_ID is None:
         print("[-] Cannot submit result, NODE_ID unknown")
@@ -70,8 +154,11 @@ def submit_result(job_id, result_hash, logs):
     payload = {
         "node_id": NODE_ID,
         "result_hash": result_hash,
-        "logs": logs
+        "logs": logs,
+        "usage": usage,
     }
+    if result_hash is None:
+        payload["error"] = logs or "Job failed without a result"
     print(payload)
     try:
         res = requests.post(f"{SCHEDULER_URL}/nodes/{job_id}/result", json=payload)
@@ -83,21 +170,35 @@ def submit_result(job_id, result_hash, logs):
     except Exception as e:
         print("[-] Error submitting result:", e)
 
+def run_leased_job(lease):
+    job = lease["job"]
+    print(f"[*] Leased job {job['jobId']} until {lease['expires_at']}")
+    stop, lost = threading.Event(), threading.Event()
+    renewer = threading.Thread(target=keep_renewing, args=(lease, stop, lost), daemon=True)
+    renewer.start()
+    try:
+        result_hash, logs, usage = execute_job(job)
+    finally:
+        stop.set()
+        renewer.join()
+    if lost.is_set():
+        print(f"[-] Dropping result of job {job['jobId']}: its lease was lost")
+        return
+    print(f"[*] Job {job['jobId']} completed with result hash {result_hash}")
+    submit_result(job["jobId"], result_hash, logs, usage)
+
+
 def main():
+    register_node()
+    last_heartbeat = time.monotonic()
     while True:
-        print("[*] Polling for assigned jobs...")   
-        jobs = get_assigned_jobs()
-        for job in jobs:
-            print(f"[*] Found job: {job['jobId']} with status {job['status']}")
-            if job["status"] == "pending" or job["status"] == "failed":
-                print("[*] Attempting to assign self as provider...")
-                assign_provider(job["jobId"], MY_ADDRESS)
-                print(f"[*] Starting execution of job {job['jobId']}")
-                result_hash, logs = execute_job(job)
-                print(f"[*] Job {job['jobId']} completed with result hash {result_hash}")
-                submit_result(job["jobId"], result_hash, logs)
-                print(f"[*] Submitted result for job {job['jobId']}")
-        time.sleep(POLL_INTERVAL)
+        if time.monotonic() - last_heartbeat >= HEARTBEAT_INTERVAL:
+            heartbeat()
+            last_heartbeat = time.monotonic()
+        print("[*] Waiting for a job lease...")
+        lease = acquire_lease()
+        if lease is not None:
+            run_leased_job(lease)
 
 if __name__ == "__main__":
     main()
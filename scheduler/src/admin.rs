@@ -0,0 +1,627 @@
+use crate::allocation;
+use crate::auth::verify_admin;
+use crate::history::{JobEvent, JobEventKind};
+use crate::models::{Job, Lease, Node};
+use crate::nodes::holds_node;
+use crate::state::AppState;
+use crate::store::{Snapshot, Store};
+use crate::workflow;
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fmt;
+use tracing::{info, warn};
+
+/// Snapshots hold the whole store, well past the default JSON body limit.
+const IMPORT_LIMIT_BYTES: usize = 256 * 1024 * 1024;
+
+/// Why an operator action was refused.
+#[derive(Debug)]
+pub enum AdminError {
+    NotFound(String),
+    Conflict(String),
+}
+
+impl fmt::Display for AdminError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdminError::NotFound(e) | AdminError::Conflict(e) => f.write_str(e),
+        }
+    }
+}
+
+impl AdminError {
+    fn into_response(self) -> HttpResponse {
+        match self {
+            AdminError::NotFound(e) => HttpResponse::NotFound().json(json!({ "error": e })),
+            AdminError::Conflict(e) => HttpResponse::Conflict().json(json!({ "error": e })),
+        }
+    }
+}
+
+/// What an operator action changed. The caller records `events`, publishes
+/// the changed jobs and nodes, and saves the store.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Changes {
+    #[serde(skip)]
+    pub events: Vec<JobEvent>,
+    /// Ids of jobs whose status or placement changed.
+    #[serde(default)]
+    pub jobs: Vec<String>,
+    /// Ids of nodes whose status or allocations changed.
+    #[serde(default)]
+    pub nodes: Vec<String>,
+    #[serde(rename = "removedNodes", default)]
+    pub removed_nodes: Vec<Node>,
+    #[serde(rename = "releasedLeases", default)]
+    pub released_leases: usize,
+    /// Anything the operator should follow up on, such as escrowed bounties.
+    #[serde(default)]
+    pub notes: Vec<String>,
+}
+
+impl Changes {
+    fn job(&mut self, job_id: &str) {
+        if !self.jobs.iter().any(|j| j == job_id) {
+            self.jobs.push(job_id.to_string());
+        }
+    }
+
+    fn node(&mut self, node_id: &str) {
+        if !self.nodes.iter().any(|n| n == node_id) {
+            self.nodes.push(node_id.to_string());
+        }
+    }
+
+    fn event(&mut self, event: JobEvent) {
+        self.job(&event.job_id.clone());
+        self.events.push(event);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+            && self.nodes.is_empty()
+            && self.removed_nodes.is_empty()
+            && self.released_leases == 0
+    }
+}
+
+fn find_job<'a>(jobs: &'a mut [Job], job_id: &str) -> Result<&'a mut Job, AdminError> {
+    jobs.iter_mut()
+        .find(|j| j.job_id == job_id)
+        .ok_or_else(|| AdminError::NotFound(format!("Job {} not found", job_id)))
+}
+
+/// Frees whatever `job` holds on its node and drops its leases.
+fn unplace(job: &Job, nodes: &mut [Node], leases: &mut Vec<Lease>, changes: &mut Changes) {
+    if let Some(node) = job
+        .assigned_node
+        .as_deref()
+        .and_then(|id| nodes.iter_mut().find(|n| n.node_id == id))
+    {
+        if allocation::release(node, &job.job_id) {
+            changes.node(&node.node_id);
+        }
+    }
+    let before = leases.len();
+    leases.retain(|l| l.job_id != job.job_id);
+    changes.released_leases += before - leases.len();
+}
+
+/// Returns a placed job to the pending queue as though it had never run.
+fn reset(job: &mut Job) {
+    job.status = "pending".to_string();
+    job.assigned_node = None;
+    job.provider_address = None;
+    job.started_at = None;
+    job.completed_at = None;
+    job.completed = false;
+    job.gpu_hours = None;
+    job.error = None;
+    job.usage = None;
+    job.overruns.clear();
+    job.retries = job.retries.saturating_add(1);
+}
+
+/// Jobs still holding `node_id`.
+fn held_jobs(jobs: &[Job], node_id: &str) -> Vec<String> {
+    jobs.iter()
+        .filter(|j| holds_node(j) && j.assigned_node.as_deref() == Some(node_id))
+        .map(|j| j.job_id.clone())
+        .collect()
+}
+
+/// Cancels a job that has not finished, freeing its node and cancelling the
+/// jobs blocked on it.
+pub fn cancel_job(
+    jobs: &mut [Job],
+    nodes: &mut [Node],
+    leases: &mut Vec<Lease>,
+    job_id: &str,
+    reason: Option<&str>,
+) -> Result<Changes, AdminError> {
+    let job = find_job(jobs, job_id)?;
+    match job.status.as_str() {
+        "completed" | "failed" | "cancelled" => {
+            return Err(AdminError::Conflict(format!(
+                "Job {} is already {}",
+                job_id, job.status
+            )))
+        }
+        "assigning" => {
+            return Err(AdminError::Conflict(format!(
+                "Job {} is being assigned on-chain; retry once the transaction settles",
+                job_id
+            )))
+        }
+        _ => {}
+    }
+
+    let mut changes = Changes::default();
+    let node_id = job.assigned_node.clone().filter(|_| holds_node(job));
+    unplace(job, nodes, leases, &mut changes);
+    job.status = "cancelled".to_string();
+    job.completed_at = Some(Utc::now());
+    let mut message = "Cancelled by operator".to_string();
+    if let Some(reason) = reason {
+        message = format!("{}: {}", message, reason);
+    }
+    let mut event = JobEvent::new(job_id, JobEventKind::Cancelled, message);
+    if let Some(node_id) = &node_id {
+        event = event.node(node_id);
+        changes.notes.push(format!(
+            "Node {} may still be running job {}; its result will be rejected",
+            node_id, job_id
+        ));
+    }
+    changes.event(event);
+    changes.notes.push(format!(
+        "The bounty of job {} stays escrowed: the contract has no cancel or refund",
+        job_id
+    ));
+
+    for event in workflow::cascade_cancel(jobs, job_id) {
+        changes.event(event);
+    }
+    info!("Job {} cancelled by operator", job_id);
+    Ok(changes)
+}
+
+/// Puts a failed, cancelled or placed job back in the pending queue, or
+/// blocks it again if its parents have not all completed.
+pub fn requeue_job(
+    jobs: &mut [Job],
+    nodes: &mut [Node],
+    leases: &mut Vec<Lease>,
+    job_id: &str,
+) -> Result<Changes, AdminError> {
+    let job = find_job(jobs, job_id)?;
+    match job.status.as_str() {
+        "failed" | "cancelled" | "assigned" | "running" => {}
+        "assigning" => {
+            return Err(AdminError::Conflict(format!(
+                "Job {} is being assigned on-chain; retry once the transaction settles",
+                job_id
+            )))
+        }
+        status => {
+            return Err(AdminError::Conflict(format!(
+                "Job {} is {} and cannot be requeued",
+                job_id, status
+            )))
+        }
+    }
+
+    let mut changes = Changes::default();
+    if holds_node(job) {
+        unplace(job, nodes, leases, &mut changes);
+        if let Some(node_id) = &job.assigned_node {
+            changes.notes.push(format!(
+                "Node {} may still be running job {}; its result will be rejected",
+                node_id, job_id
+            ));
+        }
+    }
+    reset(job);
+    let retries = job.retries;
+    changes.event(JobEvent::new(
+        job_id,
+        JobEventKind::Requeued,
+        format!("Requeued by operator (retry {})", retries),
+    ));
+    if let Some(i) = jobs.iter().position(|j| j.job_id == job_id) {
+        if !workflow::parents_complete(&jobs[i], jobs) {
+            jobs[i].status = "blocked".to_string();
+            changes.event(JobEvent::new(
+                job_id,
+                JobEventKind::Blocked,
+                format!("Waiting on {}", jobs[i].depends_on.join(", ")),
+            ));
+        }
+    }
+
+    let cancelled_downstream = jobs
+        .iter()
+        .filter(|j| j.status == "cancelled" && j.depends_on.iter().any(|d| d == job_id))
+        .count();
+    if cancelled_downstream > 0 {
+        changes.notes.push(format!(
+            "{} dependents of job {} stay cancelled; submit them again if needed",
+            cancelled_downstream, job_id
+        ));
+    }
+    info!("Job {} requeued by operator", job_id);
+    Ok(changes)
+}
+
+/// Stops placing new jobs on a node. Jobs already on it run to completion.
+pub fn drain_node(jobs: &[Job], nodes: &mut [Node], node_id: &str) -> Result<Changes, AdminError> {
+    let node = nodes
+        .iter_mut()
+        .find(|n| n.node_id == node_id)
+        .ok_or_else(|| AdminError::NotFound(format!("Node {} not found", node_id)))?;
+    let mut changes = Changes::default();
+    if node.active {
+        node.active = false;
+        changes.node(node_id);
+        info!("Node {} drained by operator", node_id);
+    } else {
+        changes
+            .notes
+            .push(format!("Node {} was already inactive", node_id));
+    }
+    let held = held_jobs(jobs, node_id);
+    if !held.is_empty() {
+        changes
+            .notes
+            .push(format!("Node {} still holds {}", node_id, held.join(", ")));
+    }
+    Ok(changes)
+}
+
+/// Removes a node. With `force`, jobs still on it are requeued; otherwise
+/// the node must hold none.
+pub fn remove_node(
+    jobs: &mut [Job],
+    nodes: &mut Vec<Node>,
+    leases: &mut Vec<Lease>,
+    node_id: &str,
+    force: bool,
+) -> Result<Changes, AdminError> {
+    let idx = nodes
+        .iter()
+        .position(|n| n.node_id == node_id)
+        .ok_or_else(|| AdminError::NotFound(format!("Node {} not found", node_id)))?;
+    let held = held_jobs(jobs, node_id);
+    if !held.is_empty() && !force {
+        return Err(AdminError::Conflict(format!(
+            "Node {} holds {}; drain it first or remove it with force",
+            node_id,
+            held.join(", ")
+        )));
+    }
+
+    let mut changes = Changes::default();
+    for job in jobs.iter_mut().filter(|j| held.contains(&j.job_id)) {
+        if job.status == "assigning" {
+            changes.notes.push(format!(
+                "Job {} was being assigned on-chain to node {}; check its transaction",
+                job.job_id, node_id
+            ));
+        }
+        reset(job);
+        changes.event(
+            JobEvent::new(
+                &job.job_id,
+                JobEventKind::Requeued,
+                format!(
+                    "Node {} removed by operator (retry {})",
+                    node_id, job.retries
+                ),
+            )
+            .node(node_id),
+        );
+    }
+    let before = leases.len();
+    leases.retain(|l| l.node_id != node_id);
+    changes.released_leases += before - leases.len();
+    changes.removed_nodes.push(nodes.remove(idx));
+    info!("Node {} removed by operator", node_id);
+    Ok(changes)
+}
+
+/// Repairs placements that disagree with each other: jobs on nodes that no
+/// longer exist are requeued, allocations and leases of jobs no longer on
+/// their node are dropped, and missing allocations are re-created.
+pub fn reconcile(jobs: &mut [Job], nodes: &mut [Node], leases: &mut Vec<Lease>) -> Changes {
+    let mut changes = Changes::default();
+
+    for job in jobs.iter_mut().filter(|j| holds_node(j)) {
+        let on_known_node = job
+            .assigned_node
+            .as_deref()
+            .is_some_and(|id| nodes.iter().any(|n| n.node_id == id));
+        if on_known_node {
+            if job.status == "assigning" {
+                changes.notes.push(format!(
+                    "Job {} is still being assigned on-chain; check its transaction",
+                    job.job_id
+                ));
+            }
+            continue;
+        }
+        let node_id = job.assigned_node.clone().unwrap_or_default();
+        warn!(
+            "Job {} was on missing node {:?}; requeueing",
+            job.job_id, node_id
+        );
+        reset(job);
+        changes.event(JobEvent::new(
+            &job.job_id,
+            JobEventKind::Requeued,
+            format!(
+                "Node {:?} no longer exists (retry {})",
+                node_id, job.retries
+            ),
+        ));
+    }
+
+    for node in nodes.iter_mut() {
+        let before = serde_json::to_value(&*node).ok();
+        let node_id = node.node_id.clone();
+        node.allocations.retain(|a| {
+            jobs.iter().any(|j| {
+                j.job_id == a.job_id
+                    && holds_node(j)
+                    && j.assigned_node.as_deref() == Some(node_id.as_str())
+            })
+        });
+        allocation::reconcile(jobs, std::slice::from_mut(node));
+        if serde_json::to_value(&*node).ok() != before {
+            changes.node(&node.node_id);
+        }
+    }
+
+    let before = leases.len();
+    leases.retain(|l| {
+        jobs.iter().any(|j| {
+            j.job_id == l.job_id
+                && j.status == "running"
+                && j.assigned_node.as_deref() == Some(l.node_id.as_str())
+        })
+    });
+    changes.released_leases += before - leases.len();
+
+    info!(
+        "Reconciled store: {} jobs, {} nodes and {} leases changed",
+        changes.jobs.len(),
+        changes.nodes.len(),
+        changes.released_leases
+    );
+    changes
+}
+
+/// Records, publishes and saves what an action changed in the running state.
+fn commit(
+    data: &web::Data<AppState>,
+    jobs: &[Job],
+    nodes: &[Node],
+    leases: &[Lease],
+    mut changes: Changes,
+) -> HttpResponse {
+    for event in changes.events.drain(..) {
+        data.history.record(event);
+    }
+    for job in jobs.iter().filter(|j| changes.jobs.contains(&j.job_id)) {
+        data.events.publish_job(job);
+    }
+    for node in nodes.iter().filter(|n| changes.nodes.contains(&n.node_id)) {
+        data.events.publish_node(node);
+    }
+    for node in &changes.removed_nodes {
+        data.events.publish_node_removed(node);
+    }
+    if !changes.is_empty() {
+        crate::helper::save_jobs(jobs);
+        crate::nodes::save_nodes(nodes);
+        crate::helper::save_leases(leases);
+    }
+    HttpResponse::Ok().json(changes)
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct CancelRequest {
+    reason: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ForceQuery {
+    #[serde(default)]
+    force: bool,
+}
+
+async fn cancel(
+    http_req: HttpRequest,
+    job_id: web::Path<String>,
+    req: Option<web::Json<CancelRequest>>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    if let Err(resp) = verify_admin(&http_req, &data.cfg) {
+        return *resp;
+    }
+    let reason = req.and_then(|r| r.into_inner().reason);
+    let mut jobs = data.jobs.lock().unwrap();
+    let mut nodes = data.nodes.lock().unwrap();
+    let mut leases = data.leases.lock().unwrap();
+    match cancel_job(
+        &mut jobs,
+        &mut nodes,
+        &mut leases,
+        &job_id,
+        reason.as_deref(),
+    ) {
+        Ok(changes) => commit(&data, &jobs, &nodes, &leases, changes),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn requeue(
+    http_req: HttpRequest,
+    job_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    if let Err(resp) = verify_admin(&http_req, &data.cfg) {
+        return *resp;
+    }
+    let mut jobs = data.jobs.lock().unwrap();
+    let mut nodes = data.nodes.lock().unwrap();
+    let mut leases = data.leases.lock().unwrap();
+    match requeue_job(&mut jobs, &mut nodes, &mut leases, &job_id) {
+        Ok(changes) => commit(&data, &jobs, &nodes, &leases, changes),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn drain(
+    http_req: HttpRequest,
+    node_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    if let Err(resp) = verify_admin(&http_req, &data.cfg) {
+        return *resp;
+    }
+    let jobs = data.jobs.lock().unwrap();
+    let mut nodes = data.nodes.lock().unwrap();
+    let leases = data.leases.lock().unwrap();
+    match drain_node(&jobs, &mut nodes, &node_id) {
+        Ok(changes) => commit(&data, &jobs, &nodes, &leases, changes),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn remove(
+    http_req: HttpRequest,
+    node_id: web::Path<String>,
+    query: web::Query<ForceQuery>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    if let Err(resp) = verify_admin(&http_req, &data.cfg) {
+        return *resp;
+    }
+    let mut jobs = data.jobs.lock().unwrap();
+    let mut nodes = data.nodes.lock().unwrap();
+    let mut leases = data.leases.lock().unwrap();
+    match remove_node(&mut jobs, &mut nodes, &mut leases, &node_id, query.force) {
+        Ok(changes) => commit(&data, &jobs, &nodes, &leases, changes),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn reconcile_state(http_req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    if let Err(resp) = verify_admin(&http_req, &data.cfg) {
+        return *resp;
+    }
+    let mut jobs = data.jobs.lock().unwrap();
+    let mut nodes = data.nodes.lock().unwrap();
+    let mut leases = data.leases.lock().unwrap();
+    let changes = reconcile(&mut jobs, &mut nodes, &mut leases);
+    commit(&data, &jobs, &nodes, &leases, changes)
+}
+
+async fn export(http_req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    if let Err(resp) = verify_admin(&http_req, &data.cfg) {
+        return *resp;
+    }
+    let store = {
+        let jobs = data.jobs.lock().unwrap();
+        let nodes = data.nodes.lock().unwrap();
+        let leases = data.leases.lock().unwrap();
+        let schedules = data.schedules.lock().unwrap();
+        Store {
+            jobs: jobs.clone(),
+            nodes: nodes.clone(),
+            leases: leases.clone(),
+            schedules: schedules.clone(),
+        }
+    };
+    HttpResponse::Ok().json(store.snapshot(data.history.all()))
+}
+
+/// Replaces the whole running state with a snapshot. The body is only read
+/// once the admin token checks out, so anonymous clients cannot make the
+/// scheduler buffer snapshot-sized uploads.
+async fn import(
+    http_req: HttpRequest,
+    body: web::Payload,
+    query: web::Query<ForceQuery>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    if let Err(resp) = verify_admin(&http_req, &data.cfg) {
+        return *resp;
+    }
+    let bytes = match body.to_bytes_limited(IMPORT_LIMIT_BYTES).await {
+        Ok(Ok(bytes)) => bytes,
+        Ok(Err(e)) => return HttpResponse::BadRequest().json(json!({ "error": e.to_string() })),
+        Err(_) => {
+            return HttpResponse::PayloadTooLarge()
+                .json(json!({ "error": "Snapshot exceeds the import size limit" }))
+        }
+    };
+    let snapshot: Snapshot = match serde_json::from_slice(&bytes) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            return HttpResponse::BadRequest()
+                .json(json!({ "error": format!("Invalid snapshot: {}", e) }))
+        }
+    };
+    if let Err(e) = snapshot.check() {
+        return HttpResponse::BadRequest().json(json!({ "error": e }));
+    }
+    let Snapshot { store, history, .. } = snapshot;
+
+    let mut jobs = data.jobs.lock().unwrap();
+    let mut nodes = data.nodes.lock().unwrap();
+    let mut leases = data.leases.lock().unwrap();
+    let mut schedules = data.schedules.lock().unwrap();
+    if !query.force && (!jobs.is_empty() || !nodes.is_empty()) {
+        return HttpResponse::Conflict().json(
+            json!({ "error": "The scheduler already holds jobs or nodes; import with force" }),
+        );
+    }
+
+    let mut imported = store;
+    allocation::reconcile(&imported.jobs, &mut imported.nodes);
+    imported.save();
+    if let Err(e) = data.history.replace(history) {
+        warn!("Failed to restore job history: {}", e);
+    }
+    *jobs = imported.jobs;
+    *nodes = imported.nodes;
+    *leases = imported.leases;
+    *schedules = imported.schedules;
+    for node in nodes.iter() {
+        data.events.publish_node(node);
+    }
+    info!("Imported {} jobs and {} nodes", jobs.len(), nodes.len());
+    HttpResponse::Ok().json(json!({
+        "success": true,
+        "jobs": jobs.len(),
+        "nodes": nodes.len(),
+        "leases": leases.len(),
+        "schedules": schedules.len(),
+    }))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin")
+            .route("/jobs/{id}/cancel", web::post().to(cancel))
+            .route("/jobs/{id}/requeue", web::post().to(requeue))
+            .route("/nodes/{id}/drain", web::post().to(drain))
+            .route("/nodes/{id}", web::delete().to(remove))
+            .route("/reconcile", web::post().to(reconcile_state))
+            .route("/export", web::get().to(export))
+            .route("/import", web::post().to(import)),
+    );
+}
@@ -0,0 +1,315 @@
+use crate::models::{GpuAllocation, GpuDevice, Job, Node};
+use crate::nodes::holds_node;
+use crate::state::AppState;
+use actix_web::{web, HttpResponse, Responder};
+use serde_json::json;
+use tracing::warn;
+
+/// Nodes registered before devices were reported are treated as a single GPU
+/// holding all of the node's memory.
+pub fn ensure_devices(node: &mut Node) {
+    if node.devices.is_empty() {
+        node.devices.push(GpuDevice {
+            index: 0,
+            name: node
+                .gpu_name
+                .clone()
+                .unwrap_or_else(|| node.gpu_specs.clone()),
+            memory: node.memory,
+        });
+    }
+}
+
+pub fn allocated_memory(node: &Node, device: u32) -> u64 {
+    node.allocations
+        .iter()
+        .filter(|a| a.device == device)
+        .map(|a| a.memory)
+        .sum()
+}
+
+pub fn free_memory(node: &Node, device: &GpuDevice) -> u64 {
+    device
+        .memory
+        .saturating_sub(allocated_memory(node, device.index))
+}
+
+pub fn total_free_memory(node: &Node) -> u64 {
+    node.devices.iter().map(|d| free_memory(node, d)).sum()
+}
+
+/// Chooses devices for `job` without reserving them. A memory slice goes to
+/// the device it fits most tightly; a GPU-count request takes the smallest
+/// untouched devices that meet the job's minimum memory.
+pub fn plan(node: &Node, job: &Job) -> Option<Vec<GpuAllocation>> {
+    let allocation = |device: &GpuDevice, memory: u64| GpuAllocation {
+        job_id: job.job_id.clone(),
+        device: device.index,
+        memory,
+    };
+
+    match job.gpu_memory {
+        Some(slice) => node
+            .devices
+            .iter()
+            .filter(|d| d.memory >= job.min_memory && free_memory(node, d) >= slice)
+            .min_by_key(|d| free_memory(node, d))
+            .map(|d| vec![allocation(d, slice)]),
+        None => {
+            let mut unused: Vec<&GpuDevice> = node
+                .devices
+                .iter()
+                .filter(|d| d.memory >= job.min_memory && allocated_memory(node, d.index) == 0)
+                .collect();
+            if unused.len() < job.gpu_count as usize {
+                return None;
+            }
+            unused.sort_by_key(|d| d.memory);
+            Some(
+                unused
+                    .into_iter()
+                    .take(job.gpu_count as usize)
+                    .map(|d| allocation(d, d.memory))
+                    .collect(),
+            )
+        }
+    }
+}
+
+/// GPUs held by `job_id`, counting a memory slice as its fraction of the device.
+pub fn gpu_share(node: &Node, job_id: &str) -> f64 {
+    node.allocations
+        .iter()
+        .filter(|a| a.job_id == job_id)
+        .map(|a| {
+            node.devices
+                .iter()
+                .find(|d| d.index == a.device && d.memory > 0)
+                .map(|d| a.memory as f64 / d.memory as f64)
+                .unwrap_or(1.0)
+        })
+        .sum()
+}
+
+pub fn fits(node: &Node, job: &Job) -> bool {
+    plan(node, job).is_some()
+}
+
+/// Reserves devices on `node` for `job`. A job that already holds an
+/// allocation on the node keeps it.
+pub fn allocate(node: &mut Node, job: &Job) -> bool {
+    if node.allocations.iter().any(|a| a.job_id == job.job_id) {
+        return true;
+    }
+    match plan(node, job) {
+        Some(allocations) => {
+            node.allocations.extend(allocations);
+            refresh_status(node);
+            true
+        }
+        None => false,
+    }
+}
+
+pub fn release(node: &mut Node, job_id: &str) -> bool {
+    let before = node.allocations.len();
+    node.allocations.retain(|a| a.job_id != job_id);
+    refresh_status(node);
+    node.allocations.len() != before
+}
+
+/// "idle" with nothing allocated, "busy" once no device has memory left and
+/// "partial" in between.
+pub fn refresh_status(node: &mut Node) {
+    node.status = if node.allocations.is_empty() {
+        "idle"
+    } else if total_free_memory(node) == 0 {
+        "busy"
+    } else {
+        "partial"
+    }
+    .to_string();
+}
+
+/// Fills in devices for legacy nodes and re-creates allocations for jobs
+/// that were placed before allocations were persisted.
+pub fn reconcile(jobs: &[Job], nodes: &mut [Node]) {
+    for node in nodes.iter_mut() {
+        ensure_devices(node);
+        let node_id = node.node_id.clone();
+        for job in jobs
+            .iter()
+            .filter(|j| holds_node(j) && j.assigned_node.as_deref() == Some(node_id.as_str()))
+        {
+            if !allocate(node, job) {
+                warn!(
+                    "Job {} no longer fits on node {}",
+                    job.job_id, node.node_id
+                );
+            }
+        }
+        refresh_status(node);
+    }
+}
+
+pub async fn get_node_capacity(
+    node_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let nodes = data.nodes.lock().unwrap();
+    let node = match nodes.iter().find(|n| n.node_id == *node_id) {
+        Some(n) => n,
+        None => return HttpResponse::NotFound().json(json!({ "error": "Node not found" })),
+    };
+
+    let devices: Vec<_> = node
+        .devices
+        .iter()
+        .map(|d| {
+            let jobs: Vec<&str> = node
+                .allocations
+                .iter()
+                .filter(|a| a.device == d.index)
+                .map(|a| a.job_id.as_str())
+                .collect();
+            json!({
+                "index": d.index,
+                "name": d.name,
+                "memory": d.memory,
+                "allocated": allocated_memory(node, d.index),
+                "free": free_memory(node, d),
+                "jobs": jobs,
+            })
+        })
+        .collect();
+
+    HttpResponse::Ok().json(json!({
+        "node_id": node.node_id,
+        "status": node.status,
+        "free_memory": total_free_memory(node),
+        "devices": devices,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::TokenAmount;
+    use ethers::types::U256;
+
+    fn node(device_memory: &[u64]) -> Node {
+        let devices: Vec<_> = device_memory
+            .iter()
+            .enumerate()
+            .map(|(i, memory)| serde_json::json!({ "index": i, "memory": memory }))
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "nodeId": "node-1",
+            "gpuName": null,
+            "gpuSpecs": "A100",
+            "owner": null,
+            "memoryAvailable": device_memory.iter().sum::<u64>(),
+            "devices": devices,
+        }))
+        .unwrap()
+    }
+
+    fn job(job_id: &str, min_memory: u64, gpu_count: u32, gpu_memory: Option<u64>) -> Job {
+        let mut job = Job::new(
+            job_id.to_string(),
+            "0x0".to_string(),
+            None,
+            "train:1".to_string(),
+            TokenAmount::native(U256::one()),
+            "2999-01-01".to_string(),
+            "A100".to_string(),
+            min_memory,
+        );
+        job.gpu_count = gpu_count;
+        job.gpu_memory = gpu_memory;
+        job
+    }
+
+    fn devices(allocations: &[GpuAllocation]) -> Vec<(u32, u64)> {
+        allocations.iter().map(|a| (a.device, a.memory)).collect()
+    }
+
+    #[test]
+    fn whole_gpus_take_the_smallest_devices_that_qualify() {
+        let node = node(&[80, 24, 40, 24]);
+        let plan = plan(&node, &job("a", 32, 2, None)).unwrap();
+        assert_eq!(devices(&plan), vec![(2, 40), (0, 80)]);
+    }
+
+    #[test]
+    fn whole_gpus_need_enough_untouched_devices() {
+        let mut node = node(&[40, 40]);
+        assert!(allocate(&mut node, &job("slice", 1, 1, Some(10))));
+        assert!(plan(&node, &job("pair", 1, 2, None)).is_none());
+        assert!(plan(&node, &job("one", 1, 1, None)).is_some());
+        assert!(plan(&node, &job("big", 48, 1, None)).is_none());
+    }
+
+    #[test]
+    fn slices_pack_onto_the_tightest_device() {
+        let mut node = node(&[80, 40]);
+        assert!(allocate(&mut node, &job("a", 1, 1, Some(30))));
+        assert_eq!(devices(&node.allocations), vec![(1, 30)]);
+        assert!(allocate(&mut node, &job("b", 1, 1, Some(10))));
+        assert_eq!(devices(&node.allocations), vec![(1, 30), (1, 10)]);
+        assert!(allocate(&mut node, &job("c", 1, 1, Some(10))));
+        assert_eq!(node.allocations[2].device, 0);
+        assert!(!allocate(&mut node, &job("d", 1, 1, Some(80))));
+    }
+
+    #[test]
+    fn zero_devices_fit_nothing() {
+        let mut node = node(&[]);
+        assert!(plan(&node, &job("a", 1, 1, None)).is_none());
+        assert!(plan(&node, &job("b", 1, 1, Some(1))).is_none());
+        assert!(!allocate(&mut node, &job("a", 1, 1, None)));
+    }
+
+    #[test]
+    fn allocate_keeps_an_existing_reservation() {
+        let mut node = node(&[40]);
+        let job = job("a", 1, 1, None);
+        assert!(allocate(&mut node, &job));
+        assert!(allocate(&mut node, &job));
+        assert_eq!(node.allocations.len(), 1);
+    }
+
+    #[test]
+    fn status_follows_free_memory() {
+        let mut node = node(&[40, 40]);
+        refresh_status(&mut node);
+        assert_eq!(node.status, "idle");
+        allocate(&mut node, &job("a", 1, 1, None));
+        assert_eq!(node.status, "partial");
+        allocate(&mut node, &job("b", 1, 1, Some(40)));
+        assert_eq!(node.status, "busy");
+        assert!(release(&mut node, "a"));
+        assert!(!release(&mut node, "a"));
+        assert_eq!(node.status, "partial");
+    }
+
+    #[test]
+    fn gpu_share_counts_slices_as_fractions() {
+        let mut node = node(&[40, 40, 80]);
+        allocate(&mut node, &job("whole", 1, 2, None));
+        allocate(&mut node, &job("slice", 1, 1, Some(20)));
+        assert_eq!(gpu_share(&node, "whole"), 2.0);
+        assert_eq!(gpu_share(&node, "slice"), 0.25);
+        assert_eq!(gpu_share(&node, "none"), 0.0);
+    }
+
+    #[test]
+    fn legacy_nodes_get_one_device_with_all_their_memory() {
+        let mut node = node(&[]);
+        node.memory = 24;
+        ensure_devices(&mut node);
+        assert_eq!(node.devices.len(), 1);
+        assert_eq!(node.devices[0].memory, 24);
+        assert_eq!(node.devices[0].name, "A100");
+    }
+}
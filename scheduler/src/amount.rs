@@ -0,0 +1,366 @@
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::cmp::Ordering;
+
+/// Decimals of the chain's native currency.
+pub const NATIVE_DECIMALS: u8 = 18;
+const NATIVE_SYMBOL: &str = "ETH";
+
+/// Currency an amount is denominated in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Token {
+    pub symbol: String,
+    pub decimals: u8,
+    /// ERC-20 contract; None for the chain's native currency.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<Address>,
+}
+
+impl Token {
+    pub fn native() -> Self {
+        Token {
+            symbol: NATIVE_SYMBOL.to_string(),
+            decimals: NATIVE_DECIMALS,
+            address: None,
+        }
+    }
+
+    pub fn is_native(&self) -> bool {
+        self.address.is_none()
+    }
+}
+
+impl Default for Token {
+    fn default() -> Self {
+        Token::native()
+    }
+}
+
+/// An exact amount in a token's smallest unit (wei for the native currency),
+/// kept as a decimal string in JSON so no client has to round it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenAmount {
+    #[serde(
+        rename = "amount",
+        serialize_with = "serialize_units",
+        deserialize_with = "deserialize_units"
+    )]
+    pub units: U256,
+    #[serde(default)]
+    pub token: Token,
+}
+
+pub fn serialize_units<S: Serializer>(units: &U256, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&units.to_string())
+}
+
+pub fn deserialize_units<'de, D: Deserializer<'de>>(deserializer: D) -> Result<U256, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    U256::from_dec_str(value.trim()).map_err(serde::de::Error::custom)
+}
+
+/// Splits a plain decimal such as "12.50" into its digits and the number of
+/// places after the point, ignoring trailing zeros.
+fn split_decimal(value: &str) -> Result<(String, usize), String> {
+    let value = value.trim();
+    let (whole, frac) = value.split_once('.').unwrap_or((value, ""));
+    let frac = frac.trim_end_matches('0');
+    let digits_only = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    if (whole.is_empty() && frac.is_empty()) || !digits_only(whole) || !digits_only(frac) {
+        return Err("must be a decimal number such as \"0.1\"".to_string());
+    }
+    let digits = format!("{}{}", whole, frac);
+    let digits = digits.trim_start_matches('0');
+    Ok((if digits.is_empty() { "0" } else { digits }.to_string(), frac.len()))
+}
+
+fn scale(units: U256, places: usize) -> Option<U256> {
+    U256::from(10u8)
+        .checked_pow(U256::from(places))
+        .and_then(|factor| units.checked_mul(factor))
+}
+
+/// Checks that `value` is a decimal `parse_decimal` could read for some token.
+pub fn check_decimal(value: &str) -> Result<(), String> {
+    split_decimal(value).map(|_| ())
+}
+
+/// Converts a decimal in whole tokens to base units exactly, refusing more
+/// places than the token has.
+pub fn parse_decimal(value: &str, decimals: u8) -> Result<U256, String> {
+    let (digits, places) = split_decimal(value)?;
+    if places > decimals as usize {
+        return Err(format!("must have at most {} decimal places", decimals));
+    }
+    U256::from_dec_str(&digits)
+        .ok()
+        .and_then(|units| scale(units, decimals as usize - places))
+        .ok_or_else(|| "is too large".to_string())
+}
+
+/// Base units as a decimal in whole tokens, without trailing zeros.
+pub fn format_decimal(units: U256, decimals: u8) -> String {
+    let digits = units.to_string();
+    let decimals = decimals as usize;
+    let padded = if digits.len() <= decimals {
+        format!("{}{}", "0".repeat(decimals + 1 - digits.len()), digits)
+    } else {
+        digits
+    };
+    let (whole, frac) = padded.split_at(padded.len() - decimals);
+    match frac.trim_end_matches('0') {
+        "" => whole.to_string(),
+        frac => format!("{}.{}", whole, frac),
+    }
+}
+
+impl TokenAmount {
+    pub fn new(units: U256, token: &Token) -> Self {
+        TokenAmount {
+            units,
+            token: token.clone(),
+        }
+    }
+
+    pub fn native(units: U256) -> Self {
+        TokenAmount::new(units, &Token::native())
+    }
+
+    /// Parses a submitted bounty in whole tokens. Decimal strings are
+    /// converted exactly; JSON numbers are read from their shortest form.
+    pub fn from_request(value: &Value, token: &Token) -> Result<Self, String> {
+        let text = match value {
+            Value::String(s) => s.clone(),
+            Value::Number(n) => n.to_string(),
+            _ => return Err("must be a decimal string such as \"0.1\"".to_string()),
+        };
+        let units = parse_decimal(&text, token.decimals)?;
+        if units.is_zero() {
+            return Err("must be greater than zero".to_string());
+        }
+        Ok(TokenAmount::new(units, token))
+    }
+
+    /// The amount in whole tokens, for display.
+    pub fn to_decimal(&self) -> String {
+        format_decimal(self.units, self.token.decimals)
+    }
+
+    /// Approximate whole tokens, for rates and ordering only.
+    pub fn to_f64(&self) -> f64 {
+        self.to_decimal().parse().unwrap_or(0.0)
+    }
+
+    /// Compares with a decimal in whole tokens (one `check_decimal` accepts)
+    /// without rounding either side.
+    pub fn cmp_decimal(&self, value: &str) -> Ordering {
+        let (digits, places) = match split_decimal(value) {
+            Ok(split) => split,
+            Err(_) => return Ordering::Equal,
+        };
+        let decimals = self.token.decimals as usize;
+        let common = decimals.max(places);
+        let ours = scale(self.units, common - decimals);
+        let theirs = U256::from_dec_str(&digits)
+            .ok()
+            .and_then(|units| scale(units, common - places));
+        match (ours, theirs) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (None, Some(_)) => Ordering::Greater,
+            (Some(_), None) => Ordering::Less,
+            (None, None) => Ordering::Equal,
+        }
+    }
+
+    /// Reads the float ether amounts jobs stored before bounties were exact.
+    /// Floats print their shortest round-tripping decimal, so 0.1 comes back
+    /// as exactly 0.1 ETH; anything finer than a wei is dropped.
+    fn from_legacy(value: f64) -> Result<Self, String> {
+        if !value.is_finite() || value < 0.0 {
+            return Err(format!("invalid legacy bounty {}", value));
+        }
+        let text = value.to_string();
+        let (whole, frac) = text.split_once('.').unwrap_or((&text, ""));
+        let frac = &frac[..frac.len().min(NATIVE_DECIMALS as usize)];
+        parse_decimal(&format!("{}.{}", whole, frac), NATIVE_DECIMALS).map(TokenAmount::native)
+    }
+}
+
+/// Reads a stored bounty, migrating the float or numeric string older job
+/// files hold into native base units.
+pub fn deserialize_stored<'de, D>(deserializer: D) -> Result<TokenAmount, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let legacy = match Value::deserialize(deserializer)? {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => match parse_decimal(&s, NATIVE_DECIMALS) {
+            Ok(units) => return Ok(TokenAmount::native(units)),
+            Err(_) => s.trim().parse::<f64>().ok(),
+        },
+        value => {
+            return serde_json::from_value(value).map_err(serde::de::Error::custom);
+        }
+    };
+    legacy
+        .ok_or_else(|| "expected a bounty amount".to_string())
+        .and_then(TokenAmount::from_legacy)
+        .map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const U256_MAX: &str =
+        "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+
+    fn wei(value: &str) -> U256 {
+        U256::from_dec_str(value).unwrap()
+    }
+
+    fn usdc(units: U256) -> TokenAmount {
+        TokenAmount::new(
+            units,
+            &Token {
+                symbol: "USDC".to_string(),
+                decimals: 6,
+                address: Some(Address::repeat_byte(1)),
+            },
+        )
+    }
+
+    #[test]
+    fn parses_decimals_exactly() {
+        assert_eq!(parse_decimal("1", 18), Ok(U256::exp10(18)));
+        assert_eq!(parse_decimal("0.1", 18), Ok(U256::exp10(17)));
+        assert_eq!(parse_decimal(" 1.50 ", 6), Ok(U256::from(1_500_000u64)));
+        assert_eq!(parse_decimal(".5", 1), Ok(U256::from(5u64)));
+        assert_eq!(parse_decimal("5.", 0), Ok(U256::from(5u64)));
+        assert_eq!(parse_decimal("000", 18), Ok(U256::zero()));
+        assert_eq!(parse_decimal("0.000000000000000001", 18), Ok(U256::one()));
+    }
+
+    #[test]
+    fn rejects_what_is_not_a_plain_decimal() {
+        for value in ["", ".", "-1", "1e3", "0x10", "1.2.3", "one"] {
+            assert!(parse_decimal(value, 18).is_err(), "{:?}", value);
+        }
+        assert!(check_decimal("1e3").is_err());
+        assert!(check_decimal("12.5").is_ok());
+    }
+
+    #[test]
+    fn limits_places_to_the_token_decimals() {
+        assert!(parse_decimal("0.0000001", 6).is_err());
+        assert_eq!(parse_decimal("0.1000000000", 6), Ok(U256::from(100_000u64)));
+    }
+
+    #[test]
+    fn u256_bounds() {
+        assert_eq!(parse_decimal(U256_MAX, 0), Ok(U256::MAX));
+        assert_eq!(parse_decimal(&format!("{}.0", U256_MAX), 0), Ok(U256::MAX));
+        let over = "115792089237316195423570985008687907853269984665640564039457584007913129639936";
+        assert_eq!(parse_decimal(over, 0), Err("is too large".to_string()));
+        assert_eq!(parse_decimal(U256_MAX, 1), Err("is too large".to_string()));
+        assert_eq!(parse_decimal(&format!("0.{}", U256_MAX), 78), Ok(U256::MAX));
+        assert_eq!(
+            parse_decimal(&format!("0.{}", U256_MAX), 77),
+            Err("must have at most 77 decimal places".to_string())
+        );
+    }
+
+    #[test]
+    fn formats_without_trailing_zeros() {
+        assert_eq!(format_decimal(U256::zero(), 18), "0");
+        assert_eq!(format_decimal(U256::one(), 18), "0.000000000000000001");
+        assert_eq!(format_decimal(U256::exp10(18) * 12, 18), "12");
+        assert_eq!(format_decimal(U256::from(1_500_000u64), 6), "1.5");
+        assert_eq!(format_decimal(U256::MAX, 0), U256_MAX);
+        let max = format_decimal(U256::MAX, 18);
+        assert_eq!(parse_decimal(&max, 18), Ok(U256::MAX));
+    }
+
+    #[test]
+    fn bounties_must_be_positive() {
+        let native = Token::native();
+        assert_eq!(
+            TokenAmount::from_request(&Value::from("0.25"), &native)
+                .unwrap()
+                .units,
+            U256::exp10(17) * 25 / 10
+        );
+        assert_eq!(
+            TokenAmount::from_request(&serde_json::json!(0.1), &native)
+                .unwrap()
+                .units,
+            U256::exp10(17)
+        );
+        assert!(TokenAmount::from_request(&Value::from("0"), &native).is_err());
+        assert!(TokenAmount::from_request(&Value::Bool(true), &native).is_err());
+    }
+
+    #[test]
+    fn legacy_floats_keep_their_shortest_decimal() {
+        assert_eq!(
+            TokenAmount::from_legacy(0.1).unwrap().units,
+            U256::exp10(17)
+        );
+        assert_eq!(
+            TokenAmount::from_legacy(1.23456789).unwrap().units,
+            wei("1234567890000000000")
+        );
+        assert_eq!(TokenAmount::from_legacy(1e-19).unwrap().units, U256::zero());
+        assert_eq!(TokenAmount::from_legacy(0.0).unwrap().units, U256::zero());
+        assert_eq!(
+            TokenAmount::from_legacy(1e21).unwrap().units,
+            U256::exp10(39)
+        );
+        assert!(TokenAmount::from_legacy(1e300).is_err());
+        assert!(TokenAmount::from_legacy(-1.0).is_err());
+        assert!(TokenAmount::from_legacy(f64::NAN).is_err());
+        assert!(TokenAmount::from_legacy(f64::INFINITY).is_err());
+    }
+
+    #[test]
+    fn stored_bounties_migrate_from_every_older_form() {
+        let read = |value: Value| deserialize_stored(value).unwrap();
+        assert_eq!(read(serde_json::json!(0.5)).units, U256::exp10(17) * 5);
+        assert_eq!(read(Value::from("2")).units, U256::exp10(18) * 2);
+        assert_eq!(read(Value::from("2.5e-1")).units, U256::exp10(17) * 25 / 10);
+        let exact = read(serde_json::json!({ "amount": U256_MAX, "token": Token::native() }));
+        assert_eq!(exact.units, U256::MAX);
+        assert!(deserialize_stored(Value::from("lots")).is_err());
+        assert!(deserialize_stored(Value::Null).is_err());
+    }
+
+    #[test]
+    fn compares_with_decimals_across_scales() {
+        let amount = usdc(U256::from(1_500_000u64));
+        assert_eq!(amount.cmp_decimal("1.5"), Ordering::Equal);
+        assert_eq!(
+            amount.cmp_decimal("1.50000000000000000000"),
+            Ordering::Equal
+        );
+        assert_eq!(amount.cmp_decimal("1.5000001"), Ordering::Less);
+        assert_eq!(amount.cmp_decimal("1.4999999"), Ordering::Greater);
+        assert_eq!(amount.cmp_decimal("not a number"), Ordering::Equal);
+    }
+
+    #[test]
+    fn compares_at_the_u256_bounds() {
+        let max = TokenAmount::native(U256::MAX);
+        assert_eq!(
+            max.cmp_decimal(&format_decimal(U256::MAX, 18)),
+            Ordering::Equal
+        );
+        assert_eq!(max.cmp_decimal("0.0000000000000000001"), Ordering::Greater);
+        assert_eq!(max.cmp_decimal(&format!("{}0", U256_MAX)), Ordering::Less);
+
+        let zero = TokenAmount::native(U256::zero());
+        assert_eq!(zero.cmp_decimal("0"), Ordering::Equal);
+        assert_eq!(zero.cmp_decimal(U256_MAX), Ordering::Less);
+        assert_eq!(zero.cmp_decimal(&format!("{}0", U256_MAX)), Ordering::Less);
+    }
+}
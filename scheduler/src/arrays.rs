@@ -0,0 +1,361 @@
+use crate::auth::verify_owner;
+use crate::erc20;
+use crate::helper::save_jobs;
+use crate::history::{JobEvent, JobEventKind};
+use crate::images;
+use crate::jobs::{accept_job, cap_priority};
+use crate::models::{CreateJobRequest, Job};
+use crate::state::AppState;
+use crate::validation::{self, ValidationErrors};
+use crate::workflow;
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use tracing::{info, warn};
+
+type ParamSet = BTreeMap<String, Value>;
+
+/// Body accepted by `POST /jobs/array`: a template plus either an explicit
+/// list of parameter sets or a grid whose cartesian product is expanded.
+#[derive(Debug, Deserialize)]
+pub struct JobArrayRequest {
+    #[serde(rename = "arrayId", default)]
+    pub array_id: Option<String>,
+    pub template: CreateJobRequest,
+    #[serde(default)]
+    pub params: Option<Vec<ParamSet>>,
+    #[serde(default)]
+    pub grid: Option<BTreeMap<String, Vec<Value>>>,
+}
+
+/// Number of jobs a grid expands to, or None if it overflows `usize`.
+fn grid_size(grid: &BTreeMap<String, Vec<Value>>) -> Option<usize> {
+    grid.values()
+        .try_fold(1usize, |acc, values| acc.checked_mul(values.len()))
+}
+
+fn expand_grid(grid: &BTreeMap<String, Vec<Value>>) -> Result<Vec<ParamSet>, String> {
+    grid_size(grid)
+        .filter(|n| *n <= validation::MAX_ARRAY_SIZE)
+        .ok_or_else(|| {
+            format!(
+                "expands to more than {} jobs",
+                validation::MAX_ARRAY_SIZE
+            )
+        })?;
+
+    let mut sets = vec![ParamSet::new()];
+    for (key, values) in grid {
+        sets = sets
+            .into_iter()
+            .flat_map(|set| {
+                values.iter().map(move |value| {
+                    let mut set = set.clone();
+                    set.insert(key.clone(), value.clone());
+                    set
+                })
+            })
+            .collect();
+    }
+    Ok(sets)
+}
+
+fn members<'a>(jobs: &'a [Job], array_id: &str) -> Vec<&'a Job> {
+    let mut members: Vec<&Job> = jobs
+        .iter()
+        .filter(|j| j.array_id.as_deref() == Some(array_id))
+        .collect();
+    members.sort_by_key(|j| j.array_index);
+    members
+}
+
+pub async fn add_job_array(
+    http_req: HttpRequest,
+    req: web::Json<JobArrayRequest>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let JobArrayRequest {
+        array_id,
+        mut template,
+        params,
+        grid,
+    } = req.into_inner();
+    let image_size = match images::admit(&data, template.container_cid.as_deref()).await {
+        Ok(size) => size,
+        Err(errors) => {
+            warn!("Rejected job array: image policy");
+            return errors.prefixed("template").into_response();
+        }
+    };
+    // An array of the wrong size is rejected below; funding is only checked
+    // for the copies of one that can be created.
+    let copies = match (&params, &grid) {
+        (Some(params), _) => Some(params.len()),
+        (None, Some(grid)) => grid_size(grid),
+        (None, None) => Some(1),
+    }
+    .filter(|n| (1..=validation::MAX_ARRAY_SIZE).contains(n))
+    .unwrap_or(1);
+    if let Err(errors) = erc20::admit(&data, &mut template, copies).await {
+        warn!("Rejected job array: token bounty");
+        return errors.prefixed("template").into_response();
+    }
+    let mut jobs = data.jobs.lock().unwrap();
+    let mut errors = ValidationErrors::default();
+
+    let array_id = array_id.or_else(|| template.job_id.clone());
+    let array_id = errors
+        .check("arrayId", validation::required(&array_id))
+        .map(str::to_string);
+    if let Some(id) = &array_id {
+        errors.check("arrayId", validation::identifier(id));
+        if jobs.iter().any(|j| j.array_id.as_deref() == Some(id.as_str())) {
+            errors.add("arrayId", "already exists");
+        }
+    }
+
+    let sets = match (params, grid) {
+        (Some(params), None) => Some(params),
+        (None, Some(grid)) => errors.check("grid", expand_grid(&grid)),
+        _ => {
+            errors.add("params", "provide exactly one of params or grid");
+            None
+        }
+    };
+    if let Some(sets) = &sets {
+        if sets.is_empty() || sets.len() > validation::MAX_ARRAY_SIZE {
+            errors.add(
+                "params",
+                format!(
+                    "must expand to between 1 and {} jobs",
+                    validation::MAX_ARRAY_SIZE
+                ),
+            );
+        }
+    }
+
+    if !errors.is_empty() {
+        warn!("Rejected job array: validation failed");
+        return errors.into_response();
+    }
+    let (array_id, sets) = (array_id.unwrap(), sets.unwrap());
+
+    // Validate every element before accepting any, so a bad template leaves
+    // nothing half-submitted.
+    let mut children = Vec::with_capacity(sets.len());
+    for (index, params) in sets.into_iter().enumerate() {
+        let mut request = template.clone();
+        request.job_id = Some(format!("{}-{}", array_id, index));
+        match request.into_job(&jobs) {
+            Ok(mut job) => {
+                job.array_id = Some(array_id.clone());
+                job.array_index = Some(index);
+                job.params = params;
+                job.image_size = image_size;
+                cap_priority(&http_req, &data.cfg, &mut job);
+                children.push(job);
+            }
+            Err(errors) => {
+                warn!(
+                    "Rejected job array {}: element {} is invalid",
+                    array_id, index
+                );
+                return errors
+                    .prefixed(&format!("template[{}]", index))
+                    .into_response();
+            }
+        }
+    }
+
+    let total = children.len();
+    let accepted: Vec<Job> = children
+        .into_iter()
+        .map(|job| {
+            let message = format!(
+                "Job submitted by {} as element {} of array {}",
+                job.owner,
+                job.array_index.unwrap_or_default(),
+                array_id
+            );
+            accept_job(&data, &mut jobs, job, message)
+        })
+        .collect();
+    save_jobs(&jobs);
+    info!("Accepted job array {} with {} jobs", array_id, total);
+
+    HttpResponse::Created().json(json!({
+        "success": true,
+        "array_id": array_id,
+        "total": total,
+        "jobs": accepted,
+    }))
+}
+
+pub async fn get_job_array(
+    array_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let jobs = data.jobs.lock().unwrap();
+    let members = members(&jobs, &array_id);
+    if members.is_empty() {
+        return HttpResponse::NotFound().json(json!({ "error": "Job array not found" }));
+    }
+
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for job in &members {
+        *counts.entry(job.status.as_str()).or_default() += 1;
+    }
+    let finished = members
+        .iter()
+        .filter(|j| matches!(j.status.as_str(), "completed" | "failed" | "cancelled"))
+        .count();
+
+    HttpResponse::Ok().json(json!({
+        "array_id": *array_id,
+        "state": workflow::group_state(&members),
+        "total": members.len(),
+        "finished": finished,
+        "counts_by_status": counts,
+        "jobs": members.iter().map(|j| &j.job_id).collect::<Vec<_>>(),
+    }))
+}
+
+pub async fn get_job_array_results(
+    array_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let jobs = data.jobs.lock().unwrap();
+    let members = members(&jobs, &array_id);
+    if members.is_empty() {
+        return HttpResponse::NotFound().json(json!({ "error": "Job array not found" }));
+    }
+
+    let results: Vec<_> = members
+        .iter()
+        .map(|j| {
+            json!({
+                "index": j.array_index,
+                "jobId": j.job_id,
+                "params": j.params,
+                "status": j.status,
+                "result_hash": j.result_hash,
+                "error": j.error,
+                "gpu_hours": j.gpu_hours,
+            })
+        })
+        .collect();
+
+    HttpResponse::Ok().json(json!({
+        "array_id": *array_id,
+        "results": results,
+    }))
+}
+
+/// Cancels every element that has not been placed on a node yet, and their
+/// dependents. Placed elements run to completion and are reported as skipped.
+pub async fn cancel_job_array(
+    http_req: HttpRequest,
+    array_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let array_id = array_id.into_inner();
+    let mut jobs = data.jobs.lock().unwrap();
+
+    let owner = match members(&jobs, &array_id).first() {
+        Some(job) => job.owner.clone(),
+        None => return HttpResponse::NotFound().json(json!({ "error": "Job array not found" })),
+    };
+    if let Err(resp) = verify_owner(&http_req, &owner) {
+        return *resp;
+    }
+
+    let mut cancelled = Vec::new();
+    let mut skipped = Vec::new();
+    for job in jobs
+        .iter_mut()
+        .filter(|j| j.array_id.as_deref() == Some(array_id.as_str()))
+    {
+        match job.status.as_str() {
+            "pending" | "blocked" => {
+                job.status = "cancelled".to_string();
+                data.history.record(JobEvent::new(
+                    &job.job_id,
+                    JobEventKind::Cancelled,
+                    format!("Array {} cancelled by owner", array_id),
+                ));
+                data.events.publish_job(job);
+                cancelled.push(job.job_id.clone());
+            }
+            "completed" | "failed" | "cancelled" => {}
+            status => skipped.push(json!({ "jobId": job.job_id, "status": status })),
+        }
+    }
+    for job_id in &cancelled {
+        workflow::cancel_dependents(&data, &mut jobs, job_id);
+    }
+    save_jobs(&jobs);
+    info!(
+        "Cancelled {} jobs of array {} ({} already placed)",
+        cancelled.len(),
+        array_id,
+        skipped.len()
+    );
+
+    HttpResponse::Ok().json(json!({
+        "array_id": array_id,
+        "cancelled": cancelled,
+        "skipped": skipped,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(axes: &[(&str, usize)]) -> BTreeMap<String, Vec<Value>> {
+        axes.iter()
+            .map(|(key, len)| (key.to_string(), (0..*len).map(|i| json!(i)).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn expands_the_cartesian_product_in_key_order() {
+        let mut grid = grid(&[("lr", 2)]);
+        grid.insert("batch".to_string(), vec![json!(32), json!(64)]);
+        let sets = expand_grid(&grid).unwrap();
+        let pairs: Vec<(i64, i64)> = sets
+            .iter()
+            .map(|s| (s["batch"].as_i64().unwrap(), s["lr"].as_i64().unwrap()))
+            .collect();
+        assert_eq!(pairs, vec![(32, 0), (32, 1), (64, 0), (64, 1)]);
+    }
+
+    #[test]
+    fn an_empty_axis_expands_to_nothing() {
+        let grid = grid(&[("lr", 3), ("seed", 0)]);
+        assert_eq!(grid_size(&grid), Some(0));
+        assert!(expand_grid(&grid).unwrap().is_empty());
+    }
+
+    #[test]
+    fn the_size_limit_is_inclusive() {
+        let grid = grid(&[("a", 10), ("b", 10), ("c", 10)]);
+        assert_eq!(
+            expand_grid(&grid).unwrap().len(),
+            validation::MAX_ARRAY_SIZE
+        );
+
+        let grid = self::grid(&[("a", 10), ("b", 10), ("c", 10), ("d", 2)]);
+        assert!(expand_grid(&grid).is_err());
+    }
+
+    #[test]
+    fn an_overflowing_grid_is_rejected_without_expanding() {
+        let axes: Vec<(String, usize)> = (0..64).map(|i| (format!("k{:02}", i), 2)).collect();
+        let axes: Vec<(&str, usize)> = axes.iter().map(|(k, n)| (k.as_str(), *n)).collect();
+        let grid = grid(&axes);
+        assert_eq!(grid_size(&grid), None);
+        assert!(expand_grid(&grid).is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use crate::config::AppConfig;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::error::PayloadError;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::middleware::Next;
+use actix_web::web::Bytes;
+use actix_web::{Error, HttpMessage, HttpRequest, HttpResponse};
+use chrono::Utc;
+use ethers::types::{Address, Signature};
+use ethers::utils::{hex, keccak256};
+use futures_util::stream::{self, Stream};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::pin::Pin;
+use std::str::FromStr;
+use tracing::warn;
+
+pub const SIGNATURE_HEADER: &str = "X-Owner-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Signature-Timestamp";
+const MAX_CLOCK_SKEW_SECS: i64 = 300;
+
+/// The message an owner signs (EIP-191 `personal_sign`) to authorise a
+/// request; `body_hash` is the hex keccak256 of the raw request body, so a
+/// signature cannot be replayed with a different one.
+pub fn signed_message(method: &str, path: &str, timestamp: i64, body_hash: &str) -> String {
+    format!("gpu-scheduler:{}:{}:{}:{}", method, path, timestamp, body_hash)
+}
+
+/// Keccak256 of a signed request's body, left in the request extensions by
+/// `hash_signed_body`.
+#[derive(Clone)]
+struct BodyHash(String);
+
+fn body_hash(body: &[u8]) -> String {
+    format!("0x{}", hex::encode(keccak256(body)))
+}
+
+/// Middleware that hashes the body of requests carrying an owner signature
+/// before the handler's extractor consumes it, then hands the body back.
+pub async fn hash_signed_body(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if req.headers().contains_key(SIGNATURE_HEADER) {
+        let body = req.extract::<Bytes>().await?;
+        req.extensions_mut().insert(BodyHash(body_hash(&body)));
+        let stream: Pin<Box<dyn Stream<Item = Result<Bytes, PayloadError>>>> =
+            Box::pin(stream::once(async move { Ok(body) }));
+        req.set_payload(Payload::from(stream));
+    }
+    next.call(req).await
+}
+
+fn header<'a>(req: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    req.headers().get(name).and_then(|v| v.to_str().ok())
+}
+
+/// Checks that the request carries a fresh signature from `owner` over its
+/// method and path.
+pub fn verify_owner(req: &HttpRequest, owner: &str) -> Result<(), Box<HttpResponse>> {
+    let unauthorized = |message: &str| {
+        warn!("Rejected owner authentication: {}", message);
+        Box::new(HttpResponse::Unauthorized().json(json!({ "error": message })))
+    };
+
+    let owner = Address::from_str(owner).map_err(|_| unauthorized("Node has no valid owner"))?;
+    let signature =
+        header(req, SIGNATURE_HEADER).ok_or_else(|| unauthorized("Missing owner signature"))?;
+    let timestamp = header(req, TIMESTAMP_HEADER)
+        .and_then(|t| t.parse::<i64>().ok())
+        .ok_or_else(|| unauthorized("Missing or invalid signature timestamp"))?;
+
+    if (Utc::now().timestamp() - timestamp).abs() > MAX_CLOCK_SKEW_SECS {
+        return Err(unauthorized(
+            "Signature timestamp outside the allowed window",
+        ));
+    }
+
+    let signature = Signature::from_str(signature.trim_start_matches("0x"))
+        .map_err(|_| unauthorized("Malformed owner signature"))?;
+    let hash = req
+        .extensions()
+        .get::<BodyHash>()
+        .map(|h| h.0.clone())
+        .unwrap_or_else(|| body_hash(&[]));
+    let message = signed_message(req.method().as_str(), req.path(), timestamp, &hash);
+    let signer = signature
+        .recover(message)
+        .map_err(|_| unauthorized("Could not recover signer"))?;
+
+    if signer != owner {
+        warn!(
+            "Signature from {:?} does not match owner {:?}",
+            signer, owner
+        );
+        return Err(Box::new(
+            HttpResponse::Forbidden().json(json!({ "error": "Signer is not the node owner" })),
+        ));
+    }
+    Ok(())
+}
+
+/// Checks the `Authorization: Bearer` token of an admin API request. The
+/// admin API is disabled unless `server.admin_token` is set.
+pub fn verify_admin(req: &HttpRequest, cfg: &AppConfig) -> Result<(), Box<HttpResponse>> {
+    let expected = cfg.admin_token.as_deref().ok_or_else(|| {
+        Box::new(HttpResponse::Forbidden().json(json!({ "error": "Admin API is disabled" })))
+    })?;
+    let token = header(req, AUTHORIZATION.as_str())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    // Comparing digests keeps the time taken independent of the token.
+    if Sha256::digest(token.trim()) != Sha256::digest(expected) {
+        warn!("Rejected admin request to {}", req.path());
+        return Err(Box::new(
+            HttpResponse::Unauthorized().json(json!({ "error": "Invalid admin token" })),
+        ));
+    }
+    Ok(())
+}
+
+/// Whether the request carries `owner`'s signature or the admin token. Unlike
+/// `verify_owner` and `verify_admin`, a request with neither is not logged as
+/// a rejection.
+pub fn vouched_for(req: &HttpRequest, owner: &str, cfg: &AppConfig) -> bool {
+    (header(req, AUTHORIZATION.as_str()).is_some() && verify_admin(req, cfg).is_ok())
+        || (header(req, SIGNATURE_HEADER).is_some() && verify_owner(req, owner).is_ok())
+}
@@ -0,0 +1,197 @@
+use crate::config::AppConfig;
+use crate::models::{ChainRegistration, Node};
+use crate::nodes::save_nodes;
+use crate::state::AppState;
+use actix_web::{web, HttpResponse, Responder};
+use chrono::Utc;
+use ethers::types::{Address, U256};
+use serde_json::json;
+use std::str::FromStr;
+use tracing::{info, info_span, warn, Instrument};
+
+/// The `providers(address)` getter: wallet, specs, memoryAvailable, gpuName, active.
+type ProviderEntry = (Address, String, U256, String, bool);
+
+async fn fetch_provider(cfg: &AppConfig, owner: Address) -> Result<ProviderEntry, String> {
+    cfg.contract
+        .method::<_, ProviderEntry>("providers", owner)
+        .map_err(|e| format!("Failed to prepare providers call: {}", e))?
+        .call()
+        .await
+        .map_err(|e| format!("providers call failed: {}", e))
+}
+
+/// `registerNode` registers `msg.sender`, so the scheduler can only submit it
+/// for nodes owned by its own wallet.
+async fn register_on_chain(cfg: &AppConfig, node: &Node) -> Result<(), String> {
+    let call = cfg
+        .contract
+        .method::<_, ()>(
+            "registerNode",
+            (
+                node.gpu_specs.clone(),
+                U256::from(node.memory),
+                node.gpu_name.clone().unwrap_or_default(),
+            ),
+        )
+        .map_err(|e| format!("Failed to prepare registerNode call: {}", e))?;
+    let pending = call
+        .send()
+        .await
+        .map_err(|e| format!("registerNode send failed: {}", e))?;
+    info!(
+        "registerNode sent for node {} with hash: {:?}",
+        node.node_id,
+        pending.tx_hash()
+    );
+    match pending.await {
+        Ok(Some(receipt)) if receipt.status == Some(1.into()) => Ok(()),
+        Ok(_) => Err("registerNode transaction was not confirmed".to_string()),
+        Err(e) => Err(format!("Awaiting registerNode receipt failed: {}", e)),
+    }
+}
+
+fn compare(node: &Node, entry: &ProviderEntry) -> Vec<String> {
+    let (_, specs, memory, gpu_name, _) = entry;
+    let mut drift = Vec::new();
+    if *specs != node.gpu_specs {
+        drift.push(format!(
+            "specs: scheduler '{}' vs chain '{}'",
+            node.gpu_specs, specs
+        ));
+    }
+    if *memory != U256::from(node.memory) {
+        drift.push(format!(
+            "memory: scheduler {} vs chain {}",
+            node.memory, memory
+        ));
+    }
+    let local_name = node.gpu_name.clone().unwrap_or_default();
+    if *gpu_name != local_name {
+        drift.push(format!(
+            "gpu name: scheduler '{}' vs chain '{}'",
+            local_name, gpu_name
+        ));
+    }
+    drift
+}
+
+/// Reads the owner's on-chain provider entry and compares it with the local
+/// node. With `register`, a node owned by the scheduler wallet that is not on
+/// chain yet is registered first, which sends a transaction.
+pub async fn check_registration(cfg: &AppConfig, node: &Node, register: bool) -> ChainRegistration {
+    let failed = |error: String| {
+        warn!(
+            "Chain registration check failed for node {}: {}",
+            node.node_id, error
+        );
+        ChainRegistration {
+            registered: false,
+            drift: Vec::new(),
+            error: Some(error),
+            checked_at: Utc::now(),
+        }
+    };
+
+    let owner = match node.owner.as_deref().map(Address::from_str) {
+        Some(Ok(addr)) => addr,
+        _ => return failed("Node has no owner wallet".to_string()),
+    };
+
+    let mut entry = match fetch_provider(cfg, owner).await {
+        Ok(entry) => entry,
+        Err(e) => return failed(e),
+    };
+
+    if register && !entry.4 && owner == cfg.wallet_address {
+        info!(
+            "Registering node {} on-chain for scheduler wallet {:?}",
+            node.node_id, owner
+        );
+        if let Err(e) = register_on_chain(cfg, node).await {
+            return failed(e);
+        }
+        entry = match fetch_provider(cfg, owner).await {
+            Ok(entry) => entry,
+            Err(e) => return failed(e),
+        };
+    }
+
+    let registered = entry.4;
+    let drift = if registered {
+        compare(node, &entry)
+    } else {
+        Vec::new()
+    };
+    if !drift.is_empty() {
+        warn!(
+            "Node {} differs from its chain registration: {}",
+            node.node_id,
+            drift.join("; ")
+        );
+    }
+
+    ChainRegistration {
+        registered,
+        drift,
+        error: if registered {
+            None
+        } else {
+            Some("Owner wallet is not registered on-chain".to_string())
+        },
+        checked_at: Utc::now(),
+    }
+}
+
+/// Re-checks `node_id` against the chain, registering scheduler-owned nodes,
+/// and stores the result on the node.
+pub async fn sync_node(data: &web::Data<AppState>, node_id: &str) -> Option<Node> {
+    let node = {
+        let nodes = data.nodes.lock().unwrap();
+        nodes.iter().find(|n| n.node_id == node_id).cloned()
+    }?;
+
+    let registration = check_registration(&data.cfg, &node, true).await;
+
+    let mut nodes = data.nodes.lock().unwrap();
+    let node = nodes.iter_mut().find(|n| n.node_id == node_id)?;
+    node.chain = Some(registration);
+    let updated = node.clone();
+    save_nodes(&nodes);
+    data.events.publish_node(&updated);
+    Some(updated)
+}
+
+pub async fn sync_all_nodes(data: web::Data<AppState>) {
+    let node_ids: Vec<String> = {
+        let nodes = data.nodes.lock().unwrap();
+        nodes.iter().map(|n| n.node_id.clone()).collect()
+    };
+    info!(
+        "Checking chain registration for {} nodes",
+        node_ids.len()
+    );
+    for node_id in node_ids {
+        sync_node(&data, &node_id)
+            .instrument(info_span!("node", node_id = %node_id))
+            .await;
+    }
+}
+
+pub async fn get_node_chain_status(
+    node_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    // Read-only: nothing is registered on-chain or stored from here.
+    let node = {
+        let nodes = data.nodes.lock().unwrap();
+        nodes.iter().find(|n| n.node_id == *node_id).cloned()
+    };
+    match node {
+        Some(node) => HttpResponse::Ok().json(json!({
+            "node_id": node.node_id,
+            "chain": check_registration(&data.cfg, &node, false).await,
+        })),
+        None => HttpResponse::NotFound().json(json!({ "error": "Node not found" })),
+    }
+}
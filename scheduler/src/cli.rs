@@ -0,0 +1,624 @@
+use crate::admin::{self, AdminError, Changes};
+use crate::allocation;
+use crate::config::{self, Settings};
+use crate::history::JobHistory;
+use crate::models::{Job, Node};
+use crate::store::{Snapshot, Store};
+use crate::validation::ValidationErrors;
+use anyhow::{bail, Context};
+use clap::{Args, Parser, Subcommand};
+use reqwest::{Method, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Page size used when listing jobs through the API.
+const PAGE_SIZE: usize = 500;
+
+/// Runs the GPU scheduler, or operates on its state.
+///
+/// Without --url, commands read and write the configured store directly;
+/// stop the server first, as it overwrites the store with its own state.
+/// With --url, they go through the admin API of a running instance.
+#[derive(Debug, Parser)]
+#[command(name = "gpu-scheduler", version)]
+pub struct Cli {
+    /// TOML settings file; defaults to CONFIG_FILE, then ./scheduler.toml.
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    /// Print the effective settings, secrets redacted, and exit.
+    #[arg(long)]
+    pub print_config: bool,
+    #[command(flatten)]
+    pub remote: Remote,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Args)]
+pub struct Remote {
+    /// Base URL of a running scheduler to operate through.
+    #[arg(long, global = true, env = "SCHEDULER_URL", value_name = "URL")]
+    url: Option<String>,
+    /// Admin API token; defaults to server.admin_token.
+    #[arg(long, global = true, env = "ADMIN_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the HTTP server (the default).
+    Serve,
+    /// List, inspect, cancel and requeue jobs.
+    #[command(subcommand)]
+    Jobs(JobsCommand),
+    /// List, drain and remove nodes.
+    #[command(subcommand)]
+    Nodes(NodesCommand),
+    /// Requeue jobs on missing nodes and drop stale allocations and leases.
+    Reconcile,
+    /// Rewrite the store in the current format. Store only.
+    Migrate,
+    /// Write the store and job history as one JSON snapshot.
+    Export {
+        /// File to write; stdout when omitted.
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Replace the store with a snapshot written by `export`.
+    Import {
+        file: PathBuf,
+        /// Overwrite a store that already holds jobs or nodes.
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum JobsCommand {
+    /// List jobs, newest first.
+    List {
+        #[arg(long)]
+        status: Option<String>,
+        #[arg(long)]
+        owner: Option<String>,
+        /// Only jobs assigned to this node.
+        #[arg(long)]
+        node: Option<String>,
+        /// Print JSON instead of a table.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print a job and its timeline.
+    Show { id: String },
+    /// Cancel a job that has not finished, and the jobs blocked on it.
+    Cancel {
+        id: String,
+        /// Recorded in the job's timeline.
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Return a failed, cancelled or placed job to the pending queue.
+    Requeue { id: String },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum NodesCommand {
+    /// List registered nodes and what they hold.
+    List {
+        /// Print JSON instead of a table.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Stop placing jobs on a node; jobs already on it run to completion.
+    Drain { id: String },
+    /// Remove a node that holds no jobs.
+    Remove {
+        id: String,
+        /// Remove it anyway, requeueing the jobs it holds.
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+/// The admin API of a running scheduler.
+struct Client {
+    base: String,
+    token: Option<String>,
+    http: reqwest::Client,
+}
+
+impl Client {
+    fn new(url: &str, token: Option<String>) -> anyhow::Result<Self> {
+        crate::validation::http_url(url).map_err(|e| anyhow::anyhow!("--url {}", e))?;
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(60))
+            .build()?;
+        Ok(Client {
+            base: url.trim_end_matches('/').to_string(),
+            token,
+            http,
+        })
+    }
+
+    async fn send<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, String)],
+        body: Option<&impl Serialize>,
+    ) -> anyhow::Result<T> {
+        let url = format!("{}{}", self.base, path);
+        let mut req = self.http.request(method, &url).query(query);
+        if let Some(token) = &self.token {
+            req = req.bearer_auth(token);
+        }
+        if let Some(body) = body {
+            req = req.json(body);
+        }
+        let resp = req
+            .send()
+            .await
+            .with_context(|| format!("cannot reach {}", url))?;
+        let status = resp.status();
+        let body: Value = resp.json().await.unwrap_or(Value::Null);
+        if !status.is_success() {
+            let error = body["error"]
+                .as_str()
+                .unwrap_or_else(|| status.canonical_reason().unwrap_or("request failed"));
+            if status == StatusCode::FORBIDDEN || status == StatusCode::UNAUTHORIZED {
+                bail!("{} ({}); check --token or ADMIN_TOKEN", error, status);
+            }
+            bail!("{} ({})", error, status);
+        }
+        serde_json::from_value(body).with_context(|| format!("unexpected response from {}", url))
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> anyhow::Result<T> {
+        self.send(Method::GET, path, query, None::<&()>).await
+    }
+
+    async fn post<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+        body: Option<&impl Serialize>,
+    ) -> anyhow::Result<T> {
+        self.send(Method::POST, path, query, body).await
+    }
+}
+
+/// Percent-encodes an id for use as one path segment.
+fn encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// The configured store, for commands run without `--url`.
+struct Local<'a> {
+    settings: &'a Settings,
+}
+
+impl Local<'_> {
+    /// Loads the store the way the server does at startup.
+    fn open(&self) -> anyhow::Result<Store> {
+        let mut store = Store::load()?;
+        allocation::reconcile(&store.jobs, &mut store.nodes);
+        Ok(store)
+    }
+
+    /// Warns when something already listens on the configured port, as a
+    /// running server would overwrite whatever this command saves.
+    fn warn_if_serving(&self) {
+        let server = &self.settings.server;
+        let running = (server.bind.as_str(), server.port)
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .is_some_and(|addr| {
+                TcpStream::connect_timeout(&addr, Duration::from_millis(300)).is_ok()
+            });
+        if running {
+            eprintln!(
+                "warning: something is listening on {}:{}; a running scheduler will overwrite \
+                 these changes. Use --url to go through its admin API instead.",
+                server.bind, server.port
+            );
+        }
+    }
+
+    /// Applies an admin action to the store, records its events and saves.
+    fn apply(
+        &self,
+        action: impl FnOnce(&mut Store) -> Result<Changes, AdminError>,
+    ) -> anyhow::Result<Changes> {
+        self.warn_if_serving();
+        let mut store = self.open()?;
+        let mut changes = action(&mut store).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let history = JobHistory::load()?;
+        for event in changes.events.drain(..) {
+            history.record(event);
+        }
+        if !changes.is_empty() {
+            store.save();
+        }
+        Ok(changes)
+    }
+}
+
+enum Target<'a> {
+    Local(Local<'a>),
+    Remote(Client),
+}
+
+fn print_json(value: &impl Serialize) -> anyhow::Result<()> {
+    writeln!(io::stdout(), "{}", serde_json::to_string_pretty(value)?)?;
+    Ok(())
+}
+
+fn print_changes(summary: &str, changes: &Changes) {
+    println!("{}", summary);
+    if !changes.jobs.is_empty() {
+        println!("  jobs changed: {}", changes.jobs.join(", "));
+    }
+    if !changes.nodes.is_empty() {
+        println!("  nodes changed: {}", changes.nodes.join(", "));
+    }
+    for node in &changes.removed_nodes {
+        println!("  node removed: {}", node.node_id);
+    }
+    if changes.released_leases > 0 {
+        println!("  leases released: {}", changes.released_leases);
+    }
+    for note in &changes.notes {
+        println!("  note: {}", note);
+    }
+}
+
+fn print_jobs(jobs: &[Job]) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    writeln!(
+        out,
+        "{:<36}  {:<10}  {:<42}  {:<16}  {:>20}  CREATED",
+        "JOB", "STATUS", "OWNER", "NODE", "BOUNTY"
+    )?;
+    for job in jobs {
+        writeln!(
+            out,
+            "{:<36}  {:<10}  {:<42}  {:<16}  {:>20}  {}",
+            job.job_id,
+            job.status,
+            job.owner,
+            job.assigned_node.as_deref().unwrap_or("-"),
+            format!("{} {}", job.bounty.to_decimal(), job.bounty.token.symbol),
+            job.created_at
+        )?;
+    }
+    Ok(())
+}
+
+fn print_nodes(nodes: &[Node]) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    writeln!(
+        out,
+        "{:<24}  {:<8}  {:<6}  {:<24}  {:>9}  JOBS",
+        "NODE", "STATUS", "ACTIVE", "GPU", "FREE GB"
+    )?;
+    for node in nodes {
+        let jobs: BTreeSet<&str> = node.allocations.iter().map(|a| a.job_id.as_str()).collect();
+        writeln!(
+            out,
+            "{:<24}  {:<8}  {:<6}  {:<24}  {:>4}/{:<4}  {}",
+            node.node_id,
+            node.status,
+            if node.active { "yes" } else { "no" },
+            node.gpu_name.as_deref().unwrap_or(&node.gpu_specs),
+            allocation::total_free_memory(node),
+            node.devices.iter().map(|d| d.memory).sum::<u64>(),
+            jobs.into_iter().collect::<Vec<_>>().join(",")
+        )?;
+    }
+    Ok(())
+}
+
+/// Errors that stop commands working on the store; chain and pricing
+/// settings only matter to the server.
+fn storage_errors(errors: &ValidationErrors) -> Vec<String> {
+    errors
+        .iter()
+        .filter(|e| e.field == "config" || e.field.starts_with("storage"))
+        .map(|e| format!("{} {}", e.field, e.message))
+        .collect()
+}
+
+/// Runs every subcommand but `serve`.
+pub async fn run(
+    command: Command,
+    remote: Remote,
+    settings: &Settings,
+    errors: &ValidationErrors,
+) -> anyhow::Result<()> {
+    let target = match remote.url {
+        Some(url) => {
+            let token = remote.token.or_else(|| settings.server.admin_token.clone());
+            Target::Remote(Client::new(&url, token)?)
+        }
+        None => {
+            let problems = storage_errors(errors);
+            if !problems.is_empty() {
+                bail!("invalid configuration: {}", problems.join("; "));
+            }
+            config::init_storage(settings.storage.clone());
+            Target::Local(Local { settings })
+        }
+    };
+
+    match command {
+        Command::Serve => unreachable!("serve is handled by main"),
+        Command::Jobs(command) => jobs(command, &target).await,
+        Command::Nodes(command) => nodes(command, &target).await,
+        Command::Reconcile => {
+            let changes = match &target {
+                Target::Local(local) => local
+                    .apply(|s| Ok(admin::reconcile(&mut s.jobs, &mut s.nodes, &mut s.leases)))?,
+                Target::Remote(client) => client.post("/admin/reconcile", &[], None::<&()>).await?,
+            };
+            let summary = if changes.is_empty() {
+                "Store is consistent"
+            } else {
+                "Reconciled store"
+            };
+            print_changes(summary, &changes);
+            Ok(())
+        }
+        Command::Migrate => {
+            let Target::Local(local) = &target else {
+                bail!(
+                    "migrate rewrites the store files; stop the scheduler and run it without --url"
+                );
+            };
+            local.warn_if_serving();
+            let store = local.open()?;
+            store.save();
+            println!(
+                "Rewrote {} jobs, {} nodes, {} leases and {} schedules in {}",
+                store.jobs.len(),
+                store.nodes.len(),
+                store.leases.len(),
+                store.schedules.len(),
+                config::storage().dir.display()
+            );
+            Ok(())
+        }
+        Command::Export { output } => {
+            let snapshot: Value = match &target {
+                Target::Local(local) => {
+                    let history = JobHistory::load()?;
+                    json!(local.open()?.snapshot(history.all()))
+                }
+                Target::Remote(client) => client.get("/admin/export", &[]).await?,
+            };
+            let content = serde_json::to_string_pretty(&snapshot)?;
+            match output {
+                Some(path) => {
+                    fs::write(&path, content)
+                        .with_context(|| format!("cannot write {}", path.display()))?;
+                    eprintln!("Exported to {}", path.display());
+                }
+                None => writeln!(io::stdout(), "{}", content)?,
+            }
+            Ok(())
+        }
+        Command::Import { file, force } => {
+            let content = fs::read_to_string(&file)
+                .with_context(|| format!("cannot read {}", file.display()))?;
+            let snapshot: Snapshot = serde_json::from_str(&content)
+                .with_context(|| format!("{} is not a snapshot", file.display()))?;
+            snapshot.check().map_err(anyhow::Error::msg)?;
+            match &target {
+                Target::Local(local) => {
+                    local.warn_if_serving();
+                    if !force && Store::exists() && !local.open()?.is_empty() {
+                        bail!("the store already holds jobs or nodes; import with --force to replace them");
+                    }
+                    let Snapshot {
+                        mut store, history, ..
+                    } = snapshot;
+                    allocation::reconcile(&store.jobs, &mut store.nodes);
+                    store.save();
+                    JobHistory::load()?.replace(history)?;
+                    println!(
+                        "Imported {} jobs, {} nodes, {} leases and {} schedules",
+                        store.jobs.len(),
+                        store.nodes.len(),
+                        store.leases.len(),
+                        store.schedules.len()
+                    );
+                }
+                Target::Remote(client) => {
+                    let query = [("force", force.to_string())];
+                    let counts: Value = client
+                        .post("/admin/import", &query, Some(&snapshot))
+                        .await?;
+                    println!(
+                        "Imported {} jobs, {} nodes, {} leases and {} schedules",
+                        counts["jobs"], counts["nodes"], counts["leases"], counts["schedules"]
+                    );
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+async fn jobs(command: JobsCommand, target: &Target<'_>) -> anyhow::Result<()> {
+    match command {
+        JobsCommand::List {
+            status,
+            owner,
+            node,
+            json,
+        } => {
+            let jobs = match target {
+                Target::Local(local) => {
+                    let mut jobs: Vec<Job> = local
+                        .open()?
+                        .jobs
+                        .into_iter()
+                        .filter(|j| status.as_ref().is_none_or(|s| *s == j.status))
+                        .filter(|j| {
+                            owner
+                                .as_ref()
+                                .is_none_or(|o| o.eq_ignore_ascii_case(&j.owner))
+                        })
+                        .filter(|j| node.is_none() || j.assigned_node == node)
+                        .collect();
+                    jobs.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+                    jobs
+                }
+                Target::Remote(client) => {
+                    let mut query = vec![("limit", PAGE_SIZE.to_string())];
+                    for (key, value) in [
+                        ("status", &status),
+                        ("owner", &owner),
+                        ("assigned_node", &node),
+                    ] {
+                        if let Some(value) = value {
+                            query.push((key, value.clone()));
+                        }
+                    }
+                    let mut jobs = Vec::new();
+                    loop {
+                        let page: Value = client.get("/jobs", &query).await?;
+                        jobs.extend(serde_json::from_value::<Vec<Job>>(page["jobs"].clone())?);
+                        match page["meta"]["next_cursor"].as_str() {
+                            Some(cursor) => {
+                                query.retain(|(key, _)| *key != "cursor");
+                                query.push(("cursor", cursor.to_string()));
+                            }
+                            None => break,
+                        }
+                    }
+                    jobs
+                }
+            };
+            if json {
+                print_json(&jobs)
+            } else {
+                Ok(print_jobs(&jobs)?)
+            }
+        }
+        JobsCommand::Show { id } => {
+            let shown: Value = match target {
+                Target::Local(local) => {
+                    let job = local
+                        .open()?
+                        .jobs
+                        .into_iter()
+                        .find(|j| j.job_id == id)
+                        .with_context(|| format!("Job {} not found", id))?;
+                    json!({ "job": job, "timeline": JobHistory::load()?.timeline(&id) })
+                }
+                Target::Remote(client) => {
+                    client.get(&format!("/jobs/{}", encode(&id)), &[]).await?
+                }
+            };
+            print_json(&shown)
+        }
+        JobsCommand::Cancel { id, reason } => {
+            let changes = match target {
+                Target::Local(local) => local.apply(|s| {
+                    admin::cancel_job(
+                        &mut s.jobs,
+                        &mut s.nodes,
+                        &mut s.leases,
+                        &id,
+                        reason.as_deref(),
+                    )
+                })?,
+                Target::Remote(client) => {
+                    let path = format!("/admin/jobs/{}/cancel", encode(&id));
+                    client
+                        .post(&path, &[], Some(&json!({ "reason": reason })))
+                        .await?
+                }
+            };
+            print_changes(&format!("Cancelled job {}", id), &changes);
+            Ok(())
+        }
+        JobsCommand::Requeue { id } => {
+            let changes = match target {
+                Target::Local(local) => local
+                    .apply(|s| admin::requeue_job(&mut s.jobs, &mut s.nodes, &mut s.leases, &id))?,
+                Target::Remote(client) => {
+                    let path = format!("/admin/jobs/{}/requeue", encode(&id));
+                    client.post(&path, &[], None::<&()>).await?
+                }
+            };
+            print_changes(&format!("Requeued job {}", id), &changes);
+            Ok(())
+        }
+    }
+}
+
+async fn nodes(command: NodesCommand, target: &Target<'_>) -> anyhow::Result<()> {
+    match command {
+        NodesCommand::List { json } => {
+            let nodes: Vec<Node> = match target {
+                Target::Local(local) => local.open()?.nodes,
+                Target::Remote(client) => client.get("/nodes", &[]).await?,
+            };
+            if json {
+                print_json(&nodes)
+            } else {
+                Ok(print_nodes(&nodes)?)
+            }
+        }
+        NodesCommand::Drain { id } => {
+            let changes = match target {
+                Target::Local(local) => {
+                    local.apply(|s| admin::drain_node(&s.jobs, &mut s.nodes, &id))?
+                }
+                Target::Remote(client) => {
+                    let path = format!("/admin/nodes/{}/drain", encode(&id));
+                    client.post(&path, &[], None::<&()>).await?
+                }
+            };
+            print_changes(&format!("Drained node {}", id), &changes);
+            Ok(())
+        }
+        NodesCommand::Remove { id, force } => {
+            let changes = match target {
+                Target::Local(local) => local.apply(|s| {
+                    admin::remove_node(&mut s.jobs, &mut s.nodes, &mut s.leases, &id, force)
+                })?,
+                Target::Remote(client) => {
+                    let path = format!("/admin/nodes/{}", encode(&id));
+                    let query = [("force", force.to_string())];
+                    client
+                        .send(Method::DELETE, &path, &query, None::<&()>)
+                        .await?
+                }
+            };
+            print_changes(&format!("Removed node {}", id), &changes);
+            Ok(())
+        }
+    }
+}
@@ -1,66 +1,783 @@
+use crate::amount;
+use crate::images::ImagePolicy;
+use crate::telemetry::{LogConfig, LogFormat};
+use crate::usage::LimitEnforcement;
+use crate::validation::{self, ValidationErrors};
 use dotenvy::dotenv;
 use ethers::abi::Abi;
 use ethers::core::k256::ecdsa::SigningKey;
 use ethers::prelude::*;
-use std::convert::TryFrom;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::env;
+use std::fmt::Display;
 use std::fs;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use url::Url;
 
 pub type Client = SignerMiddleware<Provider<Http>, Wallet<SigningKey>>;
 pub type YourContractError = ContractError<Client>;
 
+/// Read when neither `--config` nor `CONFIG_FILE` names a file.
+const DEFAULT_CONFIG_FILE: &str = "scheduler.toml";
+const REDACTED: &str = "<redacted>";
+const MIN_ADMIN_TOKEN_LEN: usize = 16;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    pub bind: String,
+    pub port: u16,
+    /// Bearer token for the `/admin` API, which is disabled when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin_token: Option<String>,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        ServerSettings {
+            bind: "127.0.0.1".to_string(),
+            port: 3000,
+            admin_token: None,
+        }
+    }
+}
+
+/// Where the scheduler keeps its state. File names are relative to `dir`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StoragePaths {
+    pub dir: PathBuf,
+    pub jobs: String,
+    pub nodes: String,
+    pub leases: String,
+    pub schedules: String,
+    pub history: String,
+}
+
+impl Default for StoragePaths {
+    fn default() -> Self {
+        StoragePaths {
+            dir: PathBuf::from("."),
+            jobs: "jobs.json".to_string(),
+            nodes: "nodes.json".to_string(),
+            leases: "leases.json".to_string(),
+            schedules: "recurring.json".to_string(),
+            history: "job_events.jsonl".to_string(),
+        }
+    }
+}
+
+impl StoragePaths {
+    pub fn jobs_path(&self) -> PathBuf {
+        self.dir.join(&self.jobs)
+    }
+
+    pub fn nodes_path(&self) -> PathBuf {
+        self.dir.join(&self.nodes)
+    }
+
+    pub fn leases_path(&self) -> PathBuf {
+        self.dir.join(&self.leases)
+    }
+
+    pub fn schedules_path(&self) -> PathBuf {
+        self.dir.join(&self.schedules)
+    }
+
+    pub fn history_path(&self) -> PathBuf {
+        self.dir.join(&self.history)
+    }
+}
+
+static STORAGE: OnceLock<StoragePaths> = OnceLock::new();
+
+/// Fixes the storage paths for the rest of the process. Only the first call
+/// takes effect.
+pub fn init_storage(paths: StoragePaths) {
+    let _ = STORAGE.set(paths);
+}
+
+/// The configured storage paths, or the defaults before `init_storage`.
+pub fn storage() -> &'static StoragePaths {
+    STORAGE.get_or_init(StoragePaths::default)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChainSettings {
+    pub rpc_url: String,
+    /// Per-request timeout for RPC calls.
+    pub rpc_timeout_secs: u64,
+    /// Refuse to start against any other chain; unset accepts what the RPC reports.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<u64>,
+    /// Signs node assignments and result submissions.
+    pub private_key: String,
+    /// Signs releases and escrows recurring jobs.
+    pub private_key_owner: String,
+    pub contract_address: String,
+    pub abi_path: PathBuf,
+}
+
+impl Default for ChainSettings {
+    fn default() -> Self {
+        ChainSettings {
+            rpc_url: String::new(),
+            rpc_timeout_secs: 30,
+            chain_id: None,
+            private_key: String::new(),
+            private_key_owner: String::new(),
+            contract_address: String::new(),
+            abi_path: PathBuf::from("abi/JobRegistryABI.json"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LeaseSettings {
+    pub ttl_secs: u64,
+    pub max_wait_secs: u64,
+}
+
+impl Default for LeaseSettings {
+    fn default() -> Self {
+        LeaseSettings {
+            ttl_secs: 60,
+            max_wait_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrioritySettings {
+    pub high_rate: f64,
+    pub low_rate: f64,
+    pub preemption_enabled: bool,
+}
+
+impl Default for PrioritySettings {
+    fn default() -> Self {
+        PrioritySettings {
+            high_rate: 1.0,
+            low_rate: 0.01,
+            preemption_enabled: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FairShareSettings {
+    pub window_hours: f64,
+    pub weight: f64,
+    pub owner_shares: BTreeMap<String, f64>,
+    pub max_running: usize,
+    pub owner_limits: BTreeMap<String, usize>,
+}
+
+impl Default for FairShareSettings {
+    fn default() -> Self {
+        FairShareSettings {
+            window_hours: 24.0,
+            weight: 1.0,
+            owner_shares: BTreeMap::new(),
+            max_running: 0,
+            owner_limits: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImageSettings {
+    pub allowed_registries: Vec<String>,
+    pub denied_registries: Vec<String>,
+    pub require_digest: bool,
+    /// 0 means no limit.
+    pub max_size_mb: u64,
+    pub plain_http_registries: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlacementSettings {
+    pub locality_weight: f64,
+    pub limit_enforcement: LimitEnforcement,
+}
+
+impl Default for PlacementSettings {
+    fn default() -> Self {
+        PlacementSettings {
+            locality_weight: 0.5,
+            limit_enforcement: LimitEnforcement::Flag,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PricingSettings {
+    pub base_gpu_hour: f64,
+    pub class_rates: BTreeMap<String, f64>,
+    pub memory_gb_hour: f64,
+    pub queue_surge: f64,
+    pub max_surge: f64,
+}
+
+impl Default for PricingSettings {
+    fn default() -> Self {
+        PricingSettings {
+            base_gpu_hour: 0.5,
+            class_rates: BTreeMap::new(),
+            memory_gb_hour: 0.01,
+            queue_surge: 0.1,
+            max_surge: 3.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReadinessSettings {
+    /// In whole native tokens.
+    pub min_signer_balance: String,
+    pub check_timeout_secs: u64,
+}
+
+impl Default for ReadinessSettings {
+    fn default() -> Self {
+        ReadinessSettings {
+            min_signer_balance: "0.01".to_string(),
+            check_timeout_secs: 5,
+        }
+    }
+}
+
+/// Everything the scheduler can be configured with, as read from the TOML
+/// file with environment variables layered on top.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub server: ServerSettings,
+    pub storage: StoragePaths,
+    pub chain: ChainSettings,
+    pub leases: LeaseSettings,
+    pub priority: PrioritySettings,
+    pub fairshare: FairShareSettings,
+    pub images: ImageSettings,
+    pub placement: PlacementSettings,
+    pub pricing: PricingSettings,
+    pub readiness: ReadinessSettings,
+    pub logging: LogConfig,
+}
+
+/// Reads `var`, treating an empty value as unset.
+fn env_value(var: &str) -> Option<String> {
+    env::var(var)
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+/// Applies environment variables over the file, recording the ones that do
+/// not parse under the setting they override.
+struct Overrides<'a> {
+    errors: &'a mut ValidationErrors,
+}
+
+impl Overrides<'_> {
+    fn string(&mut self, var: &str, target: &mut String) {
+        if let Some(value) = env_value(var) {
+            *target = value;
+        }
+    }
+
+    fn path(&mut self, var: &str, target: &mut PathBuf) {
+        if let Some(value) = env_value(var) {
+            *target = PathBuf::from(value);
+        }
+    }
+
+    fn parse<T>(&mut self, field: &str, var: &str, target: &mut T)
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        if let Some(value) = env_value(var) {
+            match value.parse::<T>() {
+                Ok(v) => *target = v,
+                Err(e) => self.errors.add(field, format!("from {}: {}", var, e)),
+            }
+        }
+    }
+
+    fn optional<T>(&mut self, field: &str, var: &str, target: &mut Option<T>)
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        if let Some(value) = env_value(var) {
+            match value.parse::<T>() {
+                Ok(v) => *target = Some(v),
+                Err(e) => self.errors.add(field, format!("from {}: {}", var, e)),
+            }
+        }
+    }
+
+    /// A comma-separated list.
+    fn list(&mut self, var: &str, target: &mut Vec<String>) {
+        if let Some(value) = env_value(var) {
+            *target = value
+                .split(',')
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+    }
+
+    /// Comma-separated `key=value` pairs.
+    fn map<T>(&mut self, field: &str, var: &str, target: &mut BTreeMap<String, T>)
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let Some(value) = env_value(var) else {
+            return;
+        };
+        let mut entries = BTreeMap::new();
+        for pair in value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            match pair.split_once('=') {
+                Some((key, v)) => match v.trim().parse::<T>() {
+                    Ok(v) => {
+                        entries.insert(key.trim().to_string(), v);
+                    }
+                    Err(e) => self.errors.add(field, format!("from {}: {}: {}", var, pair, e)),
+                },
+                None => self
+                    .errors
+                    .add(field, format!("from {}: {} is not key=value", var, pair)),
+            }
+        }
+        *target = entries;
+    }
+}
+
+/// Keys by lowercase, so owner addresses and specs match however written.
+fn lowercase_keys<T: Clone>(map: &BTreeMap<String, T>) -> HashMap<String, T> {
+    map.iter()
+        .map(|(k, v)| (k.trim().to_lowercase(), v.clone()))
+        .collect()
+}
+
+fn at_least(value: f64, min: f64) -> Result<(), String> {
+    if !value.is_finite() || value < min {
+        return Err(format!("must be at least {}", min));
+    }
+    Ok(())
+}
+
+fn positive(value: f64) -> Result<(), String> {
+    if !value.is_finite() || value <= 0.0 {
+        return Err("must be greater than zero".to_string());
+    }
+    Ok(())
+}
+
+fn non_zero(value: u64) -> Result<(), String> {
+    if value == 0 {
+        return Err("must be greater than zero".to_string());
+    }
+    Ok(())
+}
+
+fn required(value: &str) -> Result<&str, String> {
+    match value.trim() {
+        "" => Err("is required".to_string()),
+        v => Ok(v),
+    }
+}
+
+fn private_key(value: &str) -> Result<LocalWallet, String> {
+    required(value)?
+        .parse::<LocalWallet>()
+        .map_err(|_| "is not a valid secp256k1 private key".to_string())
+}
+
+fn read_abi(path: &Path) -> Result<Abi, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    let artifact: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|e| format!("{} is not JSON: {}", path.display(), e))?;
+    serde_json::from_value(artifact["abi"].clone())
+        .map_err(|e| format!("{} has no valid \"abi\" array: {}", path.display(), e))
+}
+
+impl Settings {
+    /// Defaults, then the TOML file, then environment variables (and `.env`).
+    /// Returns the merged settings together with every problem found, so
+    /// they can be printed even when invalid.
+    pub fn load(path: Option<&Path>) -> (Self, ValidationErrors) {
+        dotenv().ok();
+        let mut errors = ValidationErrors::default();
+
+        let explicit = path
+            .map(Path::to_path_buf)
+            .or_else(|| env_value("CONFIG_FILE").map(PathBuf::from));
+        let file = explicit
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_FILE));
+        let mut settings = match fs::read_to_string(&file) {
+            Ok(content) => match toml::from_str::<Settings>(&content) {
+                Ok(settings) => settings,
+                Err(e) => {
+                    errors.add("config", format!("{} is invalid: {}", file.display(), e));
+                    Settings::default()
+                }
+            },
+            Err(e) if explicit.is_some() => {
+                errors.add("config", format!("cannot read {}: {}", file.display(), e));
+                Settings::default()
+            }
+            Err(_) => Settings::default(),
+        };
+
+        settings.apply_env(&mut errors);
+        errors.extend(settings.validate());
+        (settings, errors)
+    }
+
+    fn apply_env(&mut self, errors: &mut ValidationErrors) {
+        let mut env = Overrides { errors };
+
+        let server = &mut self.server;
+        env.string("BIND_ADDRESS", &mut server.bind);
+        env.parse("server.port", "PORT", &mut server.port);
+        env.optional("server.admin_token", "ADMIN_TOKEN", &mut server.admin_token);
+
+        env.path("STORAGE_DIR", &mut self.storage.dir);
+
+        let chain = &mut self.chain;
+        env.string("RPC_URL", &mut chain.rpc_url);
+        env.parse("chain.rpc_timeout_secs", "RPC_TIMEOUT_SECS", &mut chain.rpc_timeout_secs);
+        env.optional("chain.chain_id", "CHAIN_ID", &mut chain.chain_id);
+        env.string("PRIVATE_KEY", &mut chain.private_key);
+        env.string("PRIVATE_KEY_OWNER", &mut chain.private_key_owner);
+        env.string("CONTRACT_ADDRESS", &mut chain.contract_address);
+        env.path("ABI_PATH", &mut chain.abi_path);
+
+        let leases = &mut self.leases;
+        env.parse("leases.ttl_secs", "LEASE_TTL_SECS", &mut leases.ttl_secs);
+        env.parse("leases.max_wait_secs", "LEASE_MAX_WAIT_SECS", &mut leases.max_wait_secs);
+
+        let priority = &mut self.priority;
+        env.parse("priority.high_rate", "PRIORITY_HIGH_RATE", &mut priority.high_rate);
+        env.parse("priority.low_rate", "PRIORITY_LOW_RATE", &mut priority.low_rate);
+        env.parse(
+            "priority.preemption_enabled",
+            "PREEMPTION_ENABLED",
+            &mut priority.preemption_enabled,
+        );
+
+        let fairshare = &mut self.fairshare;
+        env.parse("fairshare.window_hours", "FAIRSHARE_WINDOW_HOURS", &mut fairshare.window_hours);
+        env.parse("fairshare.weight", "FAIRSHARE_WEIGHT", &mut fairshare.weight);
+        env.map("fairshare.owner_shares", "FAIRSHARE_OWNER_SHARES", &mut fairshare.owner_shares);
+        env.parse("fairshare.max_running", "FAIRSHARE_MAX_RUNNING", &mut fairshare.max_running);
+        env.map("fairshare.owner_limits", "FAIRSHARE_OWNER_LIMITS", &mut fairshare.owner_limits);
+
+        let images = &mut self.images;
+        env.list("IMAGE_ALLOWED_REGISTRIES", &mut images.allowed_registries);
+        env.list("IMAGE_DENIED_REGISTRIES", &mut images.denied_registries);
+        env.parse("images.require_digest", "IMAGE_REQUIRE_DIGEST", &mut images.require_digest);
+        env.parse("images.max_size_mb", "IMAGE_MAX_SIZE_MB", &mut images.max_size_mb);
+        env.list("IMAGE_PLAIN_HTTP_REGISTRIES", &mut images.plain_http_registries);
+
+        let placement = &mut self.placement;
+        env.parse("placement.locality_weight", "LOCALITY_WEIGHT", &mut placement.locality_weight);
+        env.parse(
+            "placement.limit_enforcement",
+            "LIMIT_ENFORCEMENT",
+            &mut placement.limit_enforcement,
+        );
+
+        let pricing = &mut self.pricing;
+        env.parse("pricing.base_gpu_hour", "PRICING_BASE_GPU_HOUR", &mut pricing.base_gpu_hour);
+        env.map("pricing.class_rates", "PRICING_CLASS_RATES", &mut pricing.class_rates);
+        env.parse("pricing.memory_gb_hour", "PRICING_MEMORY_GB_HOUR", &mut pricing.memory_gb_hour);
+        env.parse("pricing.queue_surge", "PRICING_QUEUE_SURGE", &mut pricing.queue_surge);
+        env.parse("pricing.max_surge", "PRICING_MAX_SURGE", &mut pricing.max_surge);
+
+        let readiness = &mut self.readiness;
+        env.string("READY_MIN_SIGNER_BALANCE", &mut readiness.min_signer_balance);
+        env.parse(
+            "readiness.check_timeout_secs",
+            "READY_CHECK_TIMEOUT_SECS",
+            &mut readiness.check_timeout_secs,
+        );
+
+        let logging = &mut self.logging;
+        env.parse::<LogFormat>("logging.format", "LOG_FORMAT", &mut logging.format);
+        env.string("RUST_LOG", &mut logging.filter);
+        env.optional(
+            "logging.otlp_endpoint",
+            "OTEL_EXPORTER_OTLP_ENDPOINT",
+            &mut logging.otlp_endpoint,
+        );
+        env.string("OTEL_SERVICE_NAME", &mut logging.service_name);
+    }
+
+    /// Checks every setting, collecting all problems rather than stopping at
+    /// the first.
+    pub fn validate(&self) -> ValidationErrors {
+        let mut errors = ValidationErrors::default();
+
+        errors.check("server.bind", required(&self.server.bind));
+        errors.check("server.port", non_zero(self.server.port as u64));
+        if let Some(token) = &self.server.admin_token {
+            if token.trim().len() < MIN_ADMIN_TOKEN_LEN {
+                errors.add(
+                    "server.admin_token",
+                    format!("must be at least {} characters", MIN_ADMIN_TOKEN_LEN),
+                );
+            }
+        }
+
+        if !self.storage.dir.is_dir() {
+            errors.add(
+                "storage.dir",
+                format!("{} is not a directory", self.storage.dir.display()),
+            );
+        }
+
+        let chain = &self.chain;
+        let rpc = required(&chain.rpc_url).and_then(validation::http_url);
+        errors.check("chain.rpc_url", rpc);
+        errors.check("chain.rpc_timeout_secs", non_zero(chain.rpc_timeout_secs));
+        errors.check("chain.private_key", private_key(&chain.private_key));
+        errors.check("chain.private_key_owner", private_key(&chain.private_key_owner));
+        let contract = required(&chain.contract_address).and_then(validation::checksummed_address);
+        errors.check("chain.contract_address", contract);
+        errors.check("chain.abi_path", read_abi(&chain.abi_path));
+
+        errors.check("leases.ttl_secs", non_zero(self.leases.ttl_secs));
+
+        let priority = &self.priority;
+        errors.check("priority.low_rate", at_least(priority.low_rate, 0.0));
+        if priority.high_rate < priority.low_rate {
+            errors.add("priority.high_rate", "must not be below priority.low_rate");
+        }
+
+        let fairshare = &self.fairshare;
+        errors.check("fairshare.window_hours", positive(fairshare.window_hours));
+        errors.check("fairshare.weight", at_least(fairshare.weight, 0.0));
+        for (owner, share) in &fairshare.owner_shares {
+            errors.check(&format!("fairshare.owner_shares.{}", owner), positive(*share));
+        }
+
+        if !(0.0..=1.0).contains(&self.placement.locality_weight) {
+            errors.add("placement.locality_weight", "must be between 0 and 1");
+        }
+
+        let pricing = &self.pricing;
+        errors.check("pricing.base_gpu_hour", at_least(pricing.base_gpu_hour, 0.0));
+        for (specs, rate) in &pricing.class_rates {
+            errors.check(&format!("pricing.class_rates.{}", specs), at_least(*rate, 0.0));
+        }
+        errors.check("pricing.memory_gb_hour", at_least(pricing.memory_gb_hour, 0.0));
+        errors.check("pricing.queue_surge", at_least(pricing.queue_surge, 0.0));
+        errors.check("pricing.max_surge", at_least(pricing.max_surge, 1.0));
+
+        errors.check(
+            "readiness.min_signer_balance",
+            amount::parse_decimal(&self.readiness.min_signer_balance, amount::NATIVE_DECIMALS),
+        );
+        errors.check(
+            "readiness.check_timeout_secs",
+            non_zero(self.readiness.check_timeout_secs),
+        );
+
+        let logging = &self.logging;
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&logging.filter) {
+            errors.add("logging.filter", format!("is not a valid filter: {}", e));
+        }
+        if let Some(endpoint) = &logging.otlp_endpoint {
+            errors.check("logging.otlp_endpoint", validation::http_url(endpoint));
+        }
+
+        errors
+    }
+
+    /// The settings as TOML, with private keys and RPC credentials hidden.
+    pub fn redacted_toml(&self) -> String {
+        let mut settings = self.clone();
+        for key in [
+            &mut settings.chain.private_key,
+            &mut settings.chain.private_key_owner,
+        ] {
+            if !key.is_empty() {
+                *key = REDACTED.to_string();
+            }
+        }
+        settings.chain.rpc_url = redact_url(&settings.chain.rpc_url);
+        if let Some(token) = &mut settings.server.admin_token {
+            *token = REDACTED.to_string();
+        }
+        toml::to_string_pretty(&settings).unwrap_or_else(|e| format!("# cannot render: {}\n", e))
+    }
+}
+
+/// Hides an RPC URL's password, and its path and query, where hosted
+/// providers put the API key.
+fn redact_url(value: &str) -> String {
+    let Ok(mut url) = Url::parse(value) else {
+        return value.to_string();
+    };
+    if url.password().is_some() {
+        let _ = url.set_password(Some(REDACTED));
+    }
+    if url.path() != "/" || url.query().is_some() {
+        url.set_path(REDACTED);
+        url.set_query(None);
+    }
+    url.to_string()
+}
+
 pub struct AppConfig {
+    pub bind: String,
     pub port: u16,
+    pub admin_token: Option<String>,
+    pub lease_ttl_secs: u64,
+    pub lease_max_wait_secs: u64,
+    /// Bounty per hour to the deadline at or above which a job counts as high priority.
+    pub priority_high_rate: f64,
+    /// Bounty per hour below which a job counts as low priority.
+    pub priority_low_rate: f64,
+    pub preemption_enabled: bool,
+    /// How far back completed jobs count towards an owner's usage.
+    pub fairshare_window_hours: f64,
+    /// Hours of queue position an owner loses per GPU-hour of recent usage.
+    pub fairshare_weight: f64,
+    /// Relative shares by lowercase owner address; owners not listed get 1.0.
+    pub fairshare_owner_shares: HashMap<String, f64>,
+    /// Jobs an owner may hold on nodes at once; 0 means unlimited.
+    pub fairshare_max_running: usize,
+    pub fairshare_owner_limits: HashMap<String, usize>,
+    /// Applied to every submitted image; nodes may add their own on top.
+    pub image_policy: ImagePolicy,
+    /// Registries reached over plain HTTP, such as a local mirror.
+    pub image_plain_http_registries: Vec<String>,
+    /// Between 0 and 1: how much node selection favours nodes that already
+    /// cache a job's dataset and image over the tightest fit.
+    pub locality_weight: f64,
+    pub limit_enforcement: LimitEnforcement,
+    /// Suggested bounty per GPU-hour for specs without a rate of their own.
+    pub pricing_base_gpu_hour: f64,
+    /// Per-GPU-hour rates by lowercase specs, such as `rtx 4090=0.8`.
+    pub pricing_class_rates: HashMap<String, f64>,
+    /// Added per GB of requested memory per hour.
+    pub pricing_memory_gb_hour: f64,
+    /// Surge added per pending job of the same specs per available node.
+    pub pricing_queue_surge: f64,
+    pub pricing_max_surge: f64,
+    /// Wei each signing wallet must hold for `/readyz` to report ready.
+    pub ready_min_signer_balance: U256,
+    pub ready_check_timeout_secs: u64,
+    /// Chain id the wallets sign for, read from the RPC at startup.
+    pub chain_id: u64,
     pub provider: Arc<Provider<Http>>,
     pub wallet_address: Address,
     pub contract: Contract<SignerMiddleware<Provider<Http>, Wallet<k256::ecdsa::SigningKey>>>,
     pub owner_contract: Contract<SignerMiddleware<Provider<Http>, Wallet<k256::ecdsa::SigningKey>>>,
 }
 
-
-
 impl AppConfig {
-    pub async fn new() -> anyhow::Result<Self> {
-        dotenv().ok();
-
-        let port = env::var("PORT")
-            .ok()
-            .and_then(|p| p.parse::<u16>().ok())
-            .unwrap_or(3000);
-
-        let rpc_url = env::var("RPC_URL")
-            .expect("Missing RPC_URL in .env");
-        let private_key = env::var("PRIVATE_KEY")
-            .expect("Missing PRIVATE_KEY in .env");
-        let private_key_owner = env::var("PRIVATE_KEY_OWNER")
-            .expect("Missing PRIVATE_KEY_OWNER in .env");
-        let contract_address = env::var("CONTRACT_ADDRESS")
-            .expect("Missing CONTRACT_ADDRESS in .env");
-
-        let abi_path = "abi/JobRegistryABI.json";
-        let abi_json = fs::read_to_string(abi_path)?;
-        let artifact: serde_json::Value = serde_json::from_str(&abi_json)?;
-        let abi_array = artifact["abi"].to_string();
-        let abi: Abi = serde_json::from_str(&abi_array)?; 
-        let provider = Provider::<Http>::try_from(rpc_url)?;
+    /// Connects to the chain described by already validated `settings`.
+    pub async fn new(settings: &Settings) -> anyhow::Result<Self> {
+        let chain = &settings.chain;
+        let abi = read_abi(&chain.abi_path).map_err(anyhow::Error::msg)?;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(chain.rpc_timeout_secs))
+            .build()?;
+        let provider = Provider::new(Http::new_with_client(Url::parse(&chain.rpc_url)?, client));
 
         let chain_id = provider.get_chainid().await?.as_u64();
+        if let Some(expected) = chain.chain_id {
+            anyhow::ensure!(
+                chain_id == expected,
+                "RPC serves chain {}, but chain.chain_id is {}",
+                chain_id,
+                expected
+            );
+        }
+        Self::with_provider(settings, provider, abi, chain_id)
+    }
 
-        let wallet: LocalWallet = private_key.parse()?;
+    /// Builds the config over `provider` for `chain_id` without calling it.
+    fn with_provider(
+        settings: &Settings,
+        provider: Provider<Http>,
+        abi: Abi,
+        chain_id: u64,
+    ) -> anyhow::Result<Self> {
+        let chain = &settings.chain;
+        let wallet: LocalWallet = chain.private_key.parse()?;
         let wallet = wallet.with_chain_id(chain_id);
         let wallet_mw = Arc::new(SignerMiddleware::new(provider.clone(), wallet));
 
-        let owner_wallet: LocalWallet = private_key_owner.parse()?;
+        let owner_wallet: LocalWallet = chain.private_key_owner.parse()?;
         let owner_wallet = owner_wallet.with_chain_id(chain_id);
         let owner_wallet_mw = Arc::new(SignerMiddleware::new(provider.clone(), owner_wallet));
 
-        let address: Address = contract_address.parse()?;
+        let address: Address = chain.contract_address.parse()?;
         let contract = Contract::new(address, abi.clone(), wallet_mw.clone());
         let owner_contract = Contract::new(address, abi, owner_wallet_mw.clone());
 
+        let images = &settings.images;
+        let image_policy = ImagePolicy {
+            allowed_registries: images.allowed_registries.clone(),
+            denied_registries: images.denied_registries.clone(),
+            require_digest: images.require_digest,
+            max_size_mb: Some(images.max_size_mb).filter(|mb| *mb > 0),
+        };
+        let ready_min_signer_balance = amount::parse_decimal(
+            &settings.readiness.min_signer_balance,
+            amount::NATIVE_DECIMALS,
+        )
+        .map_err(anyhow::Error::msg)?;
+
         Ok(Self {
-            port,
+            bind: settings.server.bind.clone(),
+            port: settings.server.port,
+            admin_token: settings.server.admin_token.clone(),
+            lease_ttl_secs: settings.leases.ttl_secs,
+            lease_max_wait_secs: settings.leases.max_wait_secs,
+            priority_high_rate: settings.priority.high_rate,
+            priority_low_rate: settings.priority.low_rate,
+            preemption_enabled: settings.priority.preemption_enabled,
+            fairshare_window_hours: settings.fairshare.window_hours,
+            fairshare_weight: settings.fairshare.weight,
+            fairshare_owner_shares: lowercase_keys(&settings.fairshare.owner_shares),
+            fairshare_max_running: settings.fairshare.max_running,
+            fairshare_owner_limits: lowercase_keys(&settings.fairshare.owner_limits),
+            image_policy,
+            image_plain_http_registries: images.plain_http_registries.clone(),
+            locality_weight: settings.placement.locality_weight,
+            limit_enforcement: settings.placement.limit_enforcement,
+            pricing_base_gpu_hour: settings.pricing.base_gpu_hour,
+            pricing_class_rates: lowercase_keys(&settings.pricing.class_rates),
+            pricing_memory_gb_hour: settings.pricing.memory_gb_hour,
+            pricing_queue_surge: settings.pricing.queue_surge,
+            pricing_max_surge: settings.pricing.max_surge,
+            ready_min_signer_balance,
+            ready_check_timeout_secs: settings.readiness.check_timeout_secs,
+            chain_id,
             provider: Arc::new(provider),
             wallet_address: wallet_mw.address(),
             contract,
@@ -68,3 +785,19 @@ impl AppConfig {
         })
     }
 }
+
+#[cfg(test)]
+impl AppConfig {
+    /// Default settings with Hardhat's first two accounts as wallets and an
+    /// RPC that is never called.
+    pub fn for_tests() -> Self {
+        let mut settings = Settings::default();
+        settings.chain.private_key =
+            "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80".to_string();
+        settings.chain.private_key_owner =
+            "0x59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d".to_string();
+        settings.chain.contract_address = format!("{:?}", Address::zero());
+        let provider = Provider::<Http>::try_from("http://127.0.0.1:1").unwrap();
+        Self::with_provider(&settings, provider, Abi::default(), 31337).unwrap()
+    }
+}
@@ -0,0 +1,288 @@
+use crate::validation::{self, MAX_SPECS_LEN};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tracing::warn;
+use url::Url;
+
+/// Largest decoded payload accepted inline; bigger data belongs in storage.
+pub const MAX_INLINE_BYTES: usize = 64 * 1024;
+const MAX_S3_KEY_LEN: usize = 1024;
+
+/// Where a job's input data lives and how an agent fetches it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DatasetRef {
+    /// Fetched from `gateway` (the agent's default when absent) or a local node.
+    Ipfs {
+        cid: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        gateway: Option<String>,
+    },
+    /// A plain download the agent checks against `sha256`. Only datasets
+    /// submitted as a bare URL lack a checksum.
+    Https {
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sha256: Option<String>,
+    },
+    /// An object in AWS S3 or any S3-compatible store at `endpoint`.
+    S3 {
+        bucket: String,
+        key: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        endpoint: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        region: Option<String>,
+    },
+    /// Base64 payload carried in the job itself.
+    Inline { data: String },
+}
+
+fn gateway_origin(url: &Url) -> String {
+    url.origin().ascii_serialization()
+}
+
+fn optional_path(path: &str) -> Option<String> {
+    let path = path.trim_matches('/');
+    (!path.is_empty()).then(|| format!("/{}", path))
+}
+
+fn https_url(value: &str) -> Result<String, String> {
+    let url = validation::http_url(value)?;
+    if url.scheme() != "https" {
+        return Err("must be an https URL".to_string());
+    }
+    Ok(url.as_str().trim_end_matches('/').to_string())
+}
+
+fn sha256_hex(value: &str) -> Result<String, String> {
+    let hex = value.trim().trim_start_matches("sha256:").to_ascii_lowercase();
+    if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err("sha256 must be 64 hex characters".to_string());
+    }
+    Ok(hex)
+}
+
+/// Bucket names follow the S3 rules: 3-63 lowercase letters, digits, '.' and
+/// '-', starting and ending with a letter or digit.
+fn bucket_name(value: &str) -> Result<(), String> {
+    let edges_ok = value.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && value.ends_with(|c: char| c.is_ascii_alphanumeric());
+    let chars_ok = value
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '-'));
+    if !(3..=63).contains(&value.len()) || !edges_ok || !chars_ok {
+        return Err("bucket must be a valid S3 bucket name".to_string());
+    }
+    Ok(())
+}
+
+impl DatasetRef {
+    /// Parses the single-string form jobs used before typed datasets: a bare
+    /// CID, `ipfs://`, `s3://`, a gateway URL (`.../ipfs/<cid>`) or any other
+    /// http(s) URL.
+    pub fn from_legacy(value: &str) -> Result<Self, String> {
+        let value = value.trim();
+        if validation::is_cid(value) {
+            return Ok(DatasetRef::Ipfs {
+                cid: value.to_string(),
+                path: None,
+                gateway: None,
+            });
+        }
+        if let Some(rest) = value.strip_prefix("ipfs://") {
+            let (cid, path) = rest.split_once('/').unwrap_or((rest, ""));
+            if !validation::is_cid(cid) {
+                return Err("must reference a valid IPFS CID".to_string());
+            }
+            return Ok(DatasetRef::Ipfs {
+                cid: cid.to_string(),
+                path: optional_path(path),
+                gateway: None,
+            });
+        }
+        if let Some(rest) = value.strip_prefix("s3://") {
+            let (bucket, key) = rest
+                .split_once('/')
+                .ok_or_else(|| "s3:// URI must name a bucket and key".to_string())?;
+            let dataset = DatasetRef::S3 {
+                bucket: bucket.to_string(),
+                key: key.to_string(),
+                endpoint: None,
+                region: None,
+            };
+            dataset.validate()?;
+            return Ok(dataset);
+        }
+        if value.starts_with("http://") || value.starts_with("https://") {
+            let url = validation::http_url(value)?;
+            let mut segments = url.path_segments().into_iter().flatten();
+            if segments.next() == Some("ipfs") {
+                if let Some(cid) = segments.next().filter(|c| validation::is_cid(c)) {
+                    return Ok(DatasetRef::Ipfs {
+                        cid: cid.to_string(),
+                        path: optional_path(&segments.collect::<Vec<_>>().join("/")),
+                        gateway: Some(gateway_origin(&url)),
+                    });
+                }
+            }
+            return Ok(DatasetRef::Https {
+                url: url.to_string(),
+                sha256: None,
+            });
+        }
+        Err("must be an IPFS CID, ipfs:// or s3:// URI, or http(s) URL".to_string())
+    }
+
+    /// Parses a submitted dataset: either the typed object or a legacy string.
+    pub fn from_request(value: &Value) -> Result<Self, String> {
+        let dataset = match value {
+            Value::String(s) => return Self::from_legacy(s),
+            Value::Object(_) => serde_json::from_value::<DatasetRef>(value.clone())
+                .map_err(|e| format!("is not a valid dataset: {}", e))?,
+            _ => return Err("must be a string or a dataset object".to_string()),
+        };
+        if matches!(dataset, DatasetRef::Https { sha256: None, .. }) {
+            return Err("https datasets need a sha256 checksum".to_string());
+        }
+        dataset.validate()?;
+        Ok(dataset.normalised())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            DatasetRef::Ipfs { cid, path, gateway } => {
+                if !validation::is_cid(cid) {
+                    return Err("cid must be a valid IPFS CID".to_string());
+                }
+                if let Some(path) = path {
+                    validation::bounded_text(path, MAX_SPECS_LEN)?;
+                }
+                if let Some(gateway) = gateway {
+                    validation::http_url(gateway)?;
+                }
+            }
+            DatasetRef::Https { url, sha256 } => {
+                validation::http_url(url)?;
+                if let Some(hash) = sha256 {
+                    sha256_hex(hash)?;
+                }
+            }
+            DatasetRef::S3 {
+                bucket,
+                key,
+                endpoint,
+                region,
+            } => {
+                bucket_name(bucket)?;
+                if key.is_empty() || key.len() > MAX_S3_KEY_LEN {
+                    return Err(format!(
+                        "key must be between 1 and {} characters",
+                        MAX_S3_KEY_LEN
+                    ));
+                }
+                if let Some(endpoint) = endpoint {
+                    https_url(endpoint)?;
+                }
+                if let Some(region) = region {
+                    validation::identifier(region)?;
+                }
+            }
+            DatasetRef::Inline { data } => {
+                let bytes = BASE64
+                    .decode(data)
+                    .map_err(|_| "inline data must be base64".to_string())?;
+                if bytes.len() > MAX_INLINE_BYTES {
+                    return Err(format!(
+                        "inline data must be at most {} bytes; store larger datasets elsewhere",
+                        MAX_INLINE_BYTES
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Trims trailing slashes, lowercases checksums and reduces gateways to
+    /// their origin, so equal references compare and serialise equally.
+    fn normalised(self) -> Self {
+        match self {
+            DatasetRef::Ipfs { cid, path, gateway } => DatasetRef::Ipfs {
+                cid,
+                path: path.and_then(|p| optional_path(&p)),
+                gateway: gateway
+                    .and_then(|g| Url::parse(&g).ok())
+                    .map(|g| gateway_origin(&g)),
+            },
+            DatasetRef::Https { url, sha256 } => DatasetRef::Https {
+                url,
+                sha256: sha256.and_then(|h| sha256_hex(&h).ok()),
+            },
+            DatasetRef::S3 {
+                bucket,
+                key,
+                endpoint,
+                region,
+            } => DatasetRef::S3 {
+                bucket,
+                key: key.trim_start_matches('/').to_string(),
+                endpoint: endpoint.map(|e| e.trim_end_matches('/').to_string()),
+                region,
+            },
+            inline => inline,
+        }
+    }
+
+    /// Identity of the underlying data for cache matching: the CID for IPFS
+    /// (whatever the gateway or path), the URL or object URI otherwise. Inline
+    /// data is never fetched, so it has none.
+    pub fn cache_key(&self) -> Option<String> {
+        match self {
+            DatasetRef::Ipfs { cid, .. } => Some(cid.clone()),
+            DatasetRef::Inline { .. } => None,
+            other => Some(other.uri()),
+        }
+    }
+
+    /// Single-string form recorded on-chain and in logs.
+    pub fn uri(&self) -> String {
+        match self {
+            DatasetRef::Ipfs { cid, path, .. } => {
+                format!("ipfs://{}{}", cid, path.as_deref().unwrap_or_default())
+            }
+            DatasetRef::Https { url, .. } => url.clone(),
+            DatasetRef::S3 { bucket, key, .. } => format!("s3://{}/{}", bucket, key),
+            DatasetRef::Inline { data } => {
+                let bytes = BASE64.decode(data).unwrap_or_default();
+                format!("inline:sha256:{:x}", Sha256::digest(bytes))
+            }
+        }
+    }
+}
+
+/// Reads a stored job's dataset, upgrading legacy strings. Unparseable
+/// strings are dropped with a warning rather than failing the whole load.
+pub fn deserialize_stored<'de, D>(deserializer: D) -> Result<Option<DatasetRef>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<Value>::deserialize(deserializer)? {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(s)) if s.is_empty() => Ok(None),
+        Some(Value::String(s)) => match DatasetRef::from_legacy(&s) {
+            Ok(dataset) => Ok(Some(dataset)),
+            Err(e) => {
+                warn!("Dropping stored dataset '{}': {}", s, e);
+                Ok(None)
+            }
+        },
+        Some(value) => serde_json::from_value(value)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+    }
+}
@@ -0,0 +1,222 @@
+use crate::amount::{Token, TokenAmount};
+use crate::config::AppConfig;
+use crate::models::CreateJobRequest;
+use crate::state::AppState;
+use crate::validation::{self, ValidationErrors};
+use actix_web::web;
+use ethers::abi::{parse_abi, Abi};
+use ethers::contract::Contract;
+use ethers::providers::{Http, Provider};
+use ethers::types::{Address, U256};
+use std::str::FromStr;
+
+/// The slice of the ERC-20 interface the scheduler reads.
+fn erc20_abi() -> Abi {
+    parse_abi(&[
+        "function symbol() view returns (string)",
+        "function decimals() view returns (uint8)",
+        "function balanceOf(address) view returns (uint256)",
+        "function allowance(address, address) view returns (uint256)",
+    ])
+    .expect("ERC-20 ABI is valid")
+}
+
+fn token_contract(cfg: &AppConfig, address: Address) -> Contract<Provider<Http>> {
+    Contract::new(address, erc20_abi(), cfg.provider.clone())
+}
+
+/// Reads a token's symbol and decimals from the chain.
+pub async fn describe(cfg: &AppConfig, address: Address) -> Result<Token, String> {
+    let token = token_contract(cfg, address);
+    let symbol = token
+        .method::<_, String>("symbol", ())
+        .map_err(|e| e.to_string())?
+        .call()
+        .await
+        .map_err(|e| format!("symbol() failed: {}", e))?;
+    let decimals = token
+        .method::<_, u8>("decimals", ())
+        .map_err(|e| e.to_string())?
+        .call()
+        .await
+        .map_err(|e| format!("decimals() failed: {}", e))?;
+    Ok(Token {
+        symbol,
+        decimals,
+        address: Some(address),
+    })
+}
+
+async fn balance_of(cfg: &AppConfig, token: Address, account: Address) -> Result<U256, String> {
+    token_contract(cfg, token)
+        .method::<_, U256>("balanceOf", account)
+        .map_err(|e| e.to_string())?
+        .call()
+        .await
+        .map_err(|e| format!("balanceOf() failed: {}", e))
+}
+
+async fn allowance(
+    cfg: &AppConfig,
+    token: Address,
+    owner: Address,
+    spender: Address,
+) -> Result<U256, String> {
+    token_contract(cfg, token)
+        .method::<_, U256>("allowance", (owner, spender))
+        .map_err(|e| e.to_string())?
+        .call()
+        .await
+        .map_err(|e| format!("allowance() failed: {}", e))
+}
+
+/// Checks that `owner` holds `total` of the token and has approved the job
+/// registry to pull it, so `createTokenJob` can escrow it.
+pub async fn check_funding(
+    cfg: &AppConfig,
+    owner: Address,
+    total: &TokenAmount,
+) -> Result<(), String> {
+    let token = match total.token.address {
+        Some(t) => t,
+        None => return Ok(()),
+    };
+    let registry = cfg.contract.address();
+    let balance = balance_of(cfg, token, owner).await?;
+    if balance < total.units {
+        return Err(format!(
+            "owner holds {} {}, less than the {} needed",
+            TokenAmount::new(balance, &total.token).to_decimal(),
+            total.token.symbol,
+            total.to_decimal()
+        ));
+    }
+    let approved = allowance(cfg, token, owner, registry).await?;
+    if approved < total.units {
+        return Err(format!(
+            "owner has approved the job registry for {} {}; approve at least {}",
+            TokenAmount::new(approved, &total.token).to_decimal(),
+            total.token.symbol,
+            total.to_decimal()
+        ));
+    }
+    Ok(())
+}
+
+/// A job's entry in JobRegistry's `jobs` mapping.
+type RegistryJob = (
+    String,
+    Address,
+    U256,
+    String,
+    String,
+    U256,
+    Address,
+    [u8; 32],
+    String,
+    U256,
+    bool,
+);
+
+/// Checks that JobRegistry escrowed `job_id` for exactly `bounty`: its
+/// recorded bounty matches, and so does its token (`jobTokens`, zero for
+/// native bounties), which the submitter only declared to the scheduler.
+pub async fn check_escrow(
+    cfg: &AppConfig,
+    job_id: &str,
+    bounty: &TokenAmount,
+) -> Result<(), String> {
+    let (_, owner, escrowed, ..) = cfg
+        .contract
+        .method::<_, RegistryJob>("jobs", job_id.to_string())
+        .map_err(|e| e.to_string())?
+        .call()
+        .await
+        .map_err(|e| format!("jobs() failed: {}", e))?;
+    if owner.is_zero() {
+        return Err("the job registry has no record of the job".to_string());
+    }
+    let token = cfg
+        .contract
+        .method::<_, Address>("jobTokens", job_id.to_string())
+        .map_err(|e| e.to_string())?
+        .call()
+        .await
+        .map_err(|e| format!("jobTokens() failed: {}", e))?;
+    if token != bounty.token.address.unwrap_or_default() {
+        let escrowed_in = if token.is_zero() {
+            "the native currency".to_string()
+        } else {
+            format!("token {:?}", token)
+        };
+        return Err(format!(
+            "the job was escrowed in {}, not {}",
+            escrowed_in, bounty.token.symbol
+        ));
+    }
+    if escrowed != bounty.units {
+        return Err(format!(
+            "the job registry escrowed {} {}, not the {} bounty",
+            TokenAmount::new(escrowed, &bounty.token).to_decimal(),
+            bounty.token.symbol,
+            bounty.to_decimal()
+        ));
+    }
+    Ok(())
+}
+
+/// Resolves a submission's `bountyToken` into `req.token` and checks the
+/// owner can fund `copies` jobs of its bounty. Malformed owners and bounties
+/// are left to `CreateJobRequest::into_job` to report.
+pub async fn admit(
+    data: &web::Data<AppState>,
+    req: &mut CreateJobRequest,
+    copies: usize,
+) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::default();
+    req.token = None;
+    let address = match req.bounty_token.as_deref().map(str::trim) {
+        None | Some("") => return Ok(()),
+        Some(a) => match validation::checksummed_address(a) {
+            Ok(a) => Address::from_str(&a).unwrap_or_default(),
+            Err(e) => {
+                errors.add("bountyToken", e);
+                return Err(errors);
+            }
+        },
+    };
+    let token = match describe(&data.cfg, address).await {
+        Ok(token) => token,
+        Err(e) => {
+            errors.add("bountyToken", format!("is not a readable ERC-20 token: {}", e));
+            return Err(errors);
+        }
+    };
+    req.token = Some(token.clone());
+
+    let owner = req
+        .owner
+        .as_deref()
+        .and_then(|o| validation::checksummed_address(o.trim()).ok())
+        .and_then(|o| Address::from_str(&o).ok());
+    let bounty = req
+        .bounty
+        .as_ref()
+        .and_then(|b| TokenAmount::from_request(b, &token).ok());
+    let (owner, bounty) = match (owner, bounty) {
+        (Some(owner), Some(bounty)) => (owner, bounty),
+        _ => return Ok(()),
+    };
+    let total = match bounty.units.checked_mul(U256::from(copies.max(1))) {
+        Some(units) => TokenAmount::new(units, &token),
+        None => {
+            errors.add("bounty", "is too large");
+            return Err(errors);
+        }
+    };
+    if let Err(e) = check_funding(&data.cfg, owner, &total).await {
+        errors.add("bounty", e);
+        return Err(errors);
+    }
+    Ok(())
+}
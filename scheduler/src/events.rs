@@ -0,0 +1,189 @@
+use crate::models::{Job, Node};
+use crate::state::AppState;
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use futures_util::StreamExt as _;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::{BroadcastStream, IntervalStream};
+use tracing::info;
+
+const EVENT_BUS_CAPACITY: usize = 1024;
+const SSE_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    JobUpdated { job: Box<Job> },
+    NodeUpdated { node: Node },
+    NodeRemoved { node: Node },
+}
+
+impl Event {
+    fn name(&self) -> &'static str {
+        match self {
+            Event::JobUpdated { .. } => "job_updated",
+            Event::NodeUpdated { .. } => "node_updated",
+            Event::NodeRemoved { .. } => "node_removed",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<Event>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+        Self { tx }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.tx.subscribe()
+    }
+
+    pub fn publish_job(&self, job: &Job) {
+        // Sending only fails when nobody is subscribed, which is fine.
+        let _ = self.tx.send(Event::JobUpdated { job: Box::new(job.clone()) });
+    }
+
+    pub fn publish_node(&self, node: &Node) {
+        let _ = self.tx.send(Event::NodeUpdated { node: node.clone() });
+    }
+
+    pub fn publish_node_removed(&self, node: &Node) {
+        let _ = self.tx.send(Event::NodeRemoved { node: node.clone() });
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct EventFilter {
+    pub job_id: Option<String>,
+    pub owner: Option<String>,
+    pub node_id: Option<String>,
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &Event) -> bool {
+        match event {
+            Event::JobUpdated { job } => {
+                self.job_id.as_ref().is_none_or(|id| *id == job.job_id)
+                    && self
+                        .owner
+                        .as_ref()
+                        .is_none_or(|o| o.eq_ignore_ascii_case(&job.owner))
+                    && self
+                        .node_id
+                        .as_ref()
+                        .is_none_or(|id| job.assigned_node.as_ref() == Some(id))
+            }
+            Event::NodeUpdated { node } | Event::NodeRemoved { node } => {
+                self.job_id.is_none()
+                    && self.owner.as_ref().is_none_or(|o| {
+                        node.owner
+                            .as_ref()
+                            .is_some_and(|owner| owner.eq_ignore_ascii_case(o))
+                    })
+                    && self.node_id.as_ref().is_none_or(|id| *id == node.node_id)
+            }
+        }
+    }
+}
+
+fn sse_event_frame(event: &Event) -> web::Bytes {
+    let payload = serde_json::to_string(event).unwrap_or_default();
+    web::Bytes::from(format!("event: {}\ndata: {}\n\n", event.name(), payload))
+}
+
+fn sse_lagged_frame(skipped: u64) -> web::Bytes {
+    web::Bytes::from(format!(
+        "event: lagged\ndata: {{\"skipped\":{}}}\n\n",
+        skipped
+    ))
+}
+
+async fn sse_events(filter: web::Query<EventFilter>, data: web::Data<AppState>) -> HttpResponse {
+    let filter = filter.into_inner();
+    info!("SSE subscriber connected with filter: {:?}", filter);
+
+    let events = BroadcastStream::new(data.events.subscribe()).filter_map(move |msg| {
+        let frame = match msg {
+            Ok(event) if filter.matches(&event) => Some(sse_event_frame(&event)),
+            Ok(_) => None,
+            Err(BroadcastStreamRecvError::Lagged(skipped)) => Some(sse_lagged_frame(skipped)),
+        };
+        async move { frame }
+    });
+    let keep_alive = IntervalStream::new(tokio::time::interval(SSE_KEEP_ALIVE))
+        .map(|_| web::Bytes::from_static(b": keep-alive\n\n"));
+
+    let stream = futures_util::stream::select(events, keep_alive).map(Ok::<_, Error>);
+
+    HttpResponse::Ok()
+        .insert_header(("Content-Type", "text/event-stream"))
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(stream)
+}
+
+async fn ws_events(
+    req: HttpRequest,
+    body: web::Payload,
+    filter: web::Query<EventFilter>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let (response, mut session, mut msg_stream) = actix_ws::handle(&req, body)?;
+    let filter = filter.into_inner();
+    let mut rx = data.events.subscribe();
+    info!(
+        "WebSocket subscriber connected with filter: {:?}",
+        filter
+    );
+
+    actix_web::rt::spawn(async move {
+        loop {
+            tokio::select! {
+                msg = rx.recv() => {
+                    let text = match msg {
+                        Ok(event) if filter.matches(&event) => {
+                            serde_json::to_string(&event).unwrap_or_default()
+                        }
+                        Ok(_) => continue,
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            serde_json::json!({ "type": "lagged", "skipped": skipped }).to_string()
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    };
+                    if session.text(text).await.is_err() {
+                        break;
+                    }
+                }
+                incoming = msg_stream.next() => {
+                    match incoming {
+                        Some(Ok(actix_ws::Message::Ping(bytes))) => {
+                            if session.pong(&bytes).await.is_err() {
+                                break;
+                            }
+                        }
+                        Some(Ok(actix_ws::Message::Close(_))) | None | Some(Err(_)) => break,
+                        Some(Ok(_)) => {}
+                    }
+                }
+            }
+        }
+        let _ = session.close(None).await;
+        info!("WebSocket subscriber disconnected");
+    });
+
+    Ok(response)
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/events")
+            .route("", web::get().to(sse_events))
+            .route("/ws", web::get().to(ws_events)),
+    );
+}
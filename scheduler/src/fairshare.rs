@@ -0,0 +1,234 @@
+use crate::config::AppConfig;
+use crate::models::Job;
+use crate::nodes::holds_node;
+use crate::state::AppState;
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct OwnerUsage {
+    /// GPU-hours of jobs completed within the fair-share window.
+    pub gpu_hours: f64,
+    /// Jobs currently holding a node.
+    pub running: usize,
+}
+
+/// Per-owner usage snapshot used to order and throttle the pending queue.
+pub struct FairShare<'a> {
+    cfg: &'a AppConfig,
+    owners: BTreeMap<String, OwnerUsage>,
+}
+
+impl<'a> FairShare<'a> {
+    pub fn compute(jobs: &[Job], cfg: &'a AppConfig) -> Self {
+        let since = Utc::now() - Duration::seconds((cfg.fairshare_window_hours * 3600.0) as i64);
+        let mut owners: BTreeMap<String, OwnerUsage> = BTreeMap::new();
+        for job in jobs {
+            let usage = owners.entry(job.owner.to_lowercase()).or_default();
+            if holds_node(job) {
+                usage.running += 1;
+            }
+            if job.completed_at.is_some_and(|at| at >= since) {
+                usage.gpu_hours += job.gpu_hours.unwrap_or(0.0);
+            }
+        }
+        Self { cfg, owners }
+    }
+
+    fn usage(&self, owner: &str) -> OwnerUsage {
+        self.owners
+            .get(&owner.to_lowercase())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn share(&self, owner: &str) -> f64 {
+        self.cfg
+            .fairshare_owner_shares
+            .get(&owner.to_lowercase())
+            .copied()
+            .filter(|s| *s > 0.0)
+            .unwrap_or(1.0)
+    }
+
+    pub fn limit(&self, owner: &str) -> Option<usize> {
+        let limit = self
+            .cfg
+            .fairshare_owner_limits
+            .get(&owner.to_lowercase())
+            .copied()
+            .unwrap_or(self.cfg.fairshare_max_running);
+        (limit > 0).then_some(limit)
+    }
+
+    pub fn at_limit(&self, owner: &str) -> bool {
+        self.limit(owner)
+            .is_some_and(|limit| self.usage(owner).running >= limit)
+    }
+
+    /// Queue hours an owner's jobs lose to their recent usage.
+    pub fn delay_hours(&self, owner: &str) -> f64 {
+        self.cfg.fairshare_weight * self.usage(owner).gpu_hours / self.share(owner)
+    }
+
+    /// Submission time pushed back by the owner's delay; the queue orders
+    /// jobs of equal priority by this instead of `created_at`. A delay too
+    /// large to represent, e.g. from a tiny share, puts the job last.
+    pub fn adjusted_created_at(&self, job: &Job) -> DateTime<Utc> {
+        let created = DateTime::parse_from_rfc3339(&job.created_at)
+            .map(|t| t.with_timezone(&Utc))
+            .unwrap_or_else(|_| Utc::now());
+        Duration::try_seconds((self.delay_hours(&job.owner) * 3600.0) as i64)
+            .and_then(|delay| created.checked_add_signed(delay))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC)
+    }
+}
+
+async fn get_fairshare(data: web::Data<AppState>) -> impl Responder {
+    let jobs = data.jobs.lock().unwrap();
+    let fairshare = FairShare::compute(&jobs, &data.cfg);
+    let owners: Vec<_> = fairshare
+        .owners
+        .iter()
+        .map(|(owner, usage)| {
+            json!({
+                "owner": owner,
+                "gpu_hours": usage.gpu_hours,
+                "running": usage.running,
+                "share": fairshare.share(owner),
+                "limit": fairshare.limit(owner),
+                "at_limit": fairshare.at_limit(owner),
+                "delay_hours": fairshare.delay_hours(owner),
+            })
+        })
+        .collect();
+
+    HttpResponse::Ok().json(json!({
+        "window_hours": data.cfg.fairshare_window_hours,
+        "weight": data.cfg.fairshare_weight,
+        "owners": owners,
+    }))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.route("/fairshare", web::get().to(get_fairshare));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::TokenAmount;
+    use chrono::TimeZone;
+    use ethers::types::U256;
+
+    fn job(owner: &str, status: &str) -> Job {
+        let mut job = Job::new(
+            format!("{}-{}", owner, status),
+            owner.to_string(),
+            None,
+            "train:1".to_string(),
+            TokenAmount::native(U256::one()),
+            "2999-01-01".to_string(),
+            "A100".to_string(),
+            16,
+        );
+        job.status = status.to_string();
+        job.created_at = "2026-01-01T00:00:00Z".to_string();
+        job
+    }
+
+    fn finished(owner: &str, gpu_hours: f64, hours_ago: i64) -> Job {
+        let mut job = job(owner, "completed");
+        job.completed_at = Some(Utc::now() - Duration::hours(hours_ago));
+        job.gpu_hours = Some(gpu_hours);
+        job
+    }
+
+    fn created() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn owners_without_usage_keep_their_submission_time() {
+        let cfg = AppConfig::for_tests();
+        let jobs = vec![job("a", "pending")];
+        let fairshare = FairShare::compute(&jobs, &cfg);
+        assert_eq!(fairshare.adjusted_created_at(&jobs[0]), created());
+    }
+
+    #[test]
+    fn usage_in_the_window_delays_by_weight_over_share() {
+        let mut cfg = AppConfig::for_tests();
+        cfg.fairshare_weight = 2.0;
+        cfg.fairshare_owner_shares.insert("b".to_string(), 4.0);
+        let jobs = vec![
+            finished("A", 3.0, 1),
+            finished("a", 100.0, 48),
+            finished("b", 3.0, 1),
+        ];
+        let fairshare = FairShare::compute(&jobs, &cfg);
+        assert_eq!(fairshare.delay_hours("a"), 6.0);
+        assert_eq!(fairshare.delay_hours("B"), 1.5);
+        assert_eq!(
+            fairshare.adjusted_created_at(&job("a", "pending")),
+            created() + Duration::hours(6)
+        );
+        assert_eq!(
+            fairshare.adjusted_created_at(&job("b", "pending")),
+            created() + Duration::minutes(90)
+        );
+    }
+
+    #[test]
+    fn a_delay_too_large_for_chrono_sorts_last() {
+        let mut cfg = AppConfig::for_tests();
+        cfg.fairshare_owner_shares.insert("a".to_string(), 1e-300);
+        let jobs = vec![finished("a", 1.0, 1)];
+        let fairshare = FairShare::compute(&jobs, &cfg);
+        assert!(fairshare.delay_hours("a").is_finite());
+        assert_eq!(
+            fairshare.adjusted_created_at(&job("a", "pending")),
+            DateTime::<Utc>::MAX_UTC
+        );
+
+        cfg.fairshare_weight = f64::INFINITY;
+        let fairshare = FairShare::compute(&jobs, &cfg);
+        assert_eq!(
+            fairshare.adjusted_created_at(&job("a", "pending")),
+            DateTime::<Utc>::MAX_UTC
+        );
+    }
+
+    #[test]
+    fn non_positive_shares_count_as_one() {
+        let mut cfg = AppConfig::for_tests();
+        cfg.fairshare_owner_shares.insert("a".to_string(), 0.0);
+        cfg.fairshare_owner_shares.insert("b".to_string(), -2.0);
+        let fairshare = FairShare::compute(&[], &cfg);
+        assert_eq!(fairshare.share("a"), 1.0);
+        assert_eq!(fairshare.share("b"), 1.0);
+    }
+
+    #[test]
+    fn running_jobs_count_against_the_owner_limit() {
+        let mut cfg = AppConfig::for_tests();
+        cfg.fairshare_max_running = 2;
+        cfg.fairshare_owner_limits.insert("vip".to_string(), 0);
+        let jobs = vec![
+            job("a", "assigning"),
+            job("a", "running"),
+            job("a", "pending"),
+            job("b", "assigned"),
+            job("vip", "running"),
+            job("vip", "assigned"),
+        ];
+        let fairshare = FairShare::compute(&jobs, &cfg);
+        assert!(fairshare.at_limit("A"));
+        assert!(!fairshare.at_limit("b"));
+        assert_eq!(fairshare.limit("vip"), None);
+        assert!(!fairshare.at_limit("vip"));
+    }
+}
@@ -0,0 +1,176 @@
+use crate::amount::TokenAmount;
+use crate::config;
+use crate::state::AppState;
+use actix_web::{web, HttpResponse, Responder};
+use ethers::providers::Middleware;
+use ethers::types::{Address, U256};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tracing::warn;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize)]
+struct Check {
+    ok: bool,
+    #[serde(rename = "latencyMs")]
+    latency_ms: u128,
+    #[serde(skip_serializing_if = "Value::is_null")]
+    detail: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Runs one readiness check, failing it if it outlives `timeout`.
+async fn run<F>(timeout: Duration, check: F) -> Check
+where
+    F: Future<Output = Result<Value, (Value, String)>>,
+{
+    let started = Instant::now();
+    let (ok, detail, error) = match tokio::time::timeout(timeout, check).await {
+        Ok(Ok(detail)) => (true, detail, None),
+        Ok(Err((detail, e))) => (false, detail, Some(e)),
+        Err(_) => (
+            false,
+            Value::Null,
+            Some(format!("timed out after {}s", timeout.as_secs())),
+        ),
+    };
+    Check {
+        ok,
+        latency_ms: started.elapsed().as_millis(),
+        detail,
+        error,
+    }
+}
+
+/// Writes and removes a probe file next to the state files.
+async fn storage() -> Result<Value, (Value, String)> {
+    let dir = &config::storage().dir;
+    let probe = dir.join(format!(".readyz-{}", Uuid::new_v4()));
+    let detail = json!({ "dir": dir });
+    fs::write(&probe, b"ok")
+        .and_then(|_| fs::remove_file(&probe))
+        .map(|_| detail.clone())
+        .map_err(|e| (detail, format!("cannot write state files: {}", e)))
+}
+
+/// The RPC answers and still serves the chain the wallets sign for.
+async fn rpc(data: &AppState) -> Result<Value, (Value, String)> {
+    let expected = data.cfg.chain_id;
+    let chain_id = data
+        .cfg
+        .provider
+        .get_chainid()
+        .await
+        .map_err(|e| (Value::Null, format!("RPC unreachable: {}", e)))?
+        .as_u64();
+    let detail = json!({ "chainId": chain_id, "expectedChainId": expected });
+    if chain_id != expected {
+        return Err((detail, format!("RPC serves chain {}, expected {}", chain_id, expected)));
+    }
+    Ok(detail)
+}
+
+/// Each signing wallet can still pay gas for the transactions it sends.
+async fn signer_balance(data: &AppState) -> Result<Value, (Value, String)> {
+    let minimum = data.cfg.ready_min_signer_balance;
+    let signers = [
+        ("scheduler", data.cfg.wallet_address),
+        ("owner", data.cfg.owner_contract.client().address()),
+    ];
+    let mut detail = serde_json::Map::new();
+    let mut short = Vec::new();
+    for (role, address) in signers {
+        let balance: U256 = data
+            .cfg
+            .provider
+            .get_balance(address, None)
+            .await
+            .map_err(|e| (Value::Null, format!("balance of {:?} unavailable: {}", address, e)))?;
+        detail.insert(
+            role.to_string(),
+            json!({
+                "address": address,
+                "balance": TokenAmount::native(balance).to_decimal(),
+            }),
+        );
+        if balance < minimum {
+            short.push(format!("{} wallet {:?}", role, address));
+        }
+    }
+    detail.insert(
+        "minimum".to_string(),
+        json!(TokenAmount::native(minimum).to_decimal()),
+    );
+    let detail = Value::Object(detail);
+    if !short.is_empty() {
+        return Err((detail, format!("below the minimum balance: {}", short.join(", "))));
+    }
+    Ok(detail)
+}
+
+/// `CONTRACT_ADDRESS` holds deployed code rather than an empty account.
+async fn contract(data: &AppState) -> Result<Value, (Value, String)> {
+    let address: Address = data.cfg.contract.address();
+    let detail = json!({ "address": address });
+    let code = data
+        .cfg
+        .provider
+        .get_code(address, None)
+        .await
+        .map_err(|e| (detail.clone(), format!("code lookup failed: {}", e)))?;
+    if code.is_empty() {
+        return Err((detail, "no contract code at this address".to_string()));
+    }
+    Ok(detail)
+}
+
+/// Liveness: the process is up and serving requests.
+async fn healthz() -> impl Responder {
+    HttpResponse::Ok().json(json!({ "status": "ok" }))
+}
+
+/// Readiness: every dependency the scheduler needs to place and settle jobs
+/// answers. Responds 503 with the failing checks otherwise.
+async fn readyz(data: web::Data<AppState>) -> impl Responder {
+    let timeout = Duration::from_secs(data.cfg.ready_check_timeout_secs);
+    let (storage, rpc, signer_balance, contract) = tokio::join!(
+        run(timeout, storage()),
+        run(timeout, rpc(&data)),
+        run(timeout, signer_balance(&data)),
+        run(timeout, contract(&data)),
+    );
+    let checks = [
+        ("storage", storage),
+        ("rpc", rpc),
+        ("signerBalance", signer_balance),
+        ("contract", contract),
+    ];
+    let failing: Vec<&str> = checks
+        .iter()
+        .filter(|(_, check)| !check.ok)
+        .map(|(name, _)| *name)
+        .collect();
+    let checks: serde_json::Map<String, Value> = checks
+        .into_iter()
+        .map(|(name, check)| (name.to_string(), json!(check)))
+        .collect();
+    let body = json!({
+        "status": if failing.is_empty() { "ready" } else { "not_ready" },
+        "checks": checks,
+    });
+    if failing.is_empty() {
+        HttpResponse::Ok().json(body)
+    } else {
+        warn!("Not ready; failing checks: {}", failing.join(", "));
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.route("/healthz", web::get().to(healthz))
+        .route("/readyz", web::get().to(readyz));
+}
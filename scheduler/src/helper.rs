@@ -1,9 +1,11 @@
-use std::fs::{File, OpenOptions};
+use std::fs::OpenOptions;
 use std::io::Write;
-use crate::models::Job;
+use crate::config::storage;
+use crate::models::{Job, Lease};
+use crate::recurring::Schedule;
 
-pub fn save_jobs(jobs: &Vec<Job>) {
-    let file_path = "jobs.json";
+pub fn save_jobs(jobs: &[Job]) {
+    let file_path = storage().jobs_path();
     let mut file = OpenOptions::new()
         .write(true)
         .create(true)
@@ -12,4 +14,28 @@ pub fn save_jobs(jobs: &Vec<Job>) {
         .unwrap();
     let json = serde_json::to_string_pretty(jobs).unwrap();
     file.write_all(json.as_bytes()).unwrap();
-}
\ No newline at end of file
+}
+
+pub fn save_leases(leases: &[Lease]) {
+    let file_path = storage().leases_path();
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(file_path)
+        .unwrap();
+    let json = serde_json::to_string_pretty(leases).unwrap();
+    file.write_all(json.as_bytes()).unwrap();
+}
+
+pub fn save_schedules(schedules: &[Schedule]) {
+    let file_path = storage().schedules_path();
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(file_path)
+        .unwrap();
+    let json = serde_json::to_string_pretty(schedules).unwrap();
+    file.write_all(json.as_bytes()).unwrap();
+}
@@ -0,0 +1,187 @@
+use crate::amount::TokenAmount;
+use crate::config::storage;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+use tracing::{debug, error, info, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobEventKind {
+    Created,
+    AssignmentAttempted,
+    Assigned,
+    AssignmentFailed,
+    LeaseIssued,
+    LeaseExpired,
+    Requeued,
+    Preempted,
+    ResultSubmitted,
+    Blocked,
+    Unblocked,
+    Failed,
+    Cancelled,
+    LimitExceeded,
+    Transaction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TxRecord {
+    pub method: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// "submitted", "confirmed", "reverted" or "failed".
+    pub status: String,
+    /// Funds the transaction escrows or releases.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amount: Option<TokenAmount>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobEvent {
+    pub job_id: String,
+    pub at: DateTime<Utc>,
+    pub kind: JobEventKind,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx: Option<TxRecord>,
+}
+
+impl JobEvent {
+    pub fn new(job_id: &str, kind: JobEventKind, message: impl Into<String>) -> Self {
+        Self {
+            job_id: job_id.to_string(),
+            at: Utc::now(),
+            kind,
+            message: message.into(),
+            node_id: None,
+            tx: None,
+        }
+    }
+
+    pub fn node(mut self, node_id: &str) -> Self {
+        self.node_id = Some(node_id.to_string());
+        self
+    }
+
+    pub fn transaction(job_id: &str, method: &str, hash: Option<String>, status: &str) -> Self {
+        let message = format!("{} transaction {}", method, status);
+        let mut event = Self::new(job_id, JobEventKind::Transaction, message);
+        event.tx = Some(TxRecord {
+            method: method.to_string(),
+            hash,
+            status: status.to_string(),
+            amount: None,
+        });
+        event
+    }
+
+    /// Records the funds a transaction event moves.
+    pub fn amount(mut self, amount: &TokenAmount) -> Self {
+        if let Some(tx) = &mut self.tx {
+            tx.amount = Some(amount.clone());
+        }
+        self
+    }
+}
+
+/// Append-only per-job event log, mirrored to the history file next to the job list.
+pub struct JobHistory {
+    events: Mutex<HashMap<String, Vec<JobEvent>>>,
+}
+
+impl JobHistory {
+    /// Reads the event log. A malformed line, such as one cut short by a
+    /// crash mid-write, is skipped rather than keeping the scheduler down.
+    pub fn load() -> anyhow::Result<Self> {
+        let mut events: HashMap<String, Vec<JobEvent>> = HashMap::new();
+        let path = storage().history_path();
+        if let Ok(content) = fs::read_to_string(&path) {
+            for (number, line) in content.lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<JobEvent>(line) {
+                    Ok(event) => events.entry(event.job_id.clone()).or_default().push(event),
+                    Err(e) => warn!(
+                        "Skipping malformed event on line {} of {}: {}",
+                        number + 1,
+                        path.display(),
+                        e
+                    ),
+                }
+            }
+        }
+        info!(
+            "Loaded event history for {} jobs from {}",
+            events.len(),
+            path.display()
+        );
+        Ok(Self {
+            events: Mutex::new(events),
+        })
+    }
+
+    pub fn record(&self, event: JobEvent) {
+        let tx = event.tx.as_ref();
+        debug!(
+            job_id = %event.job_id,
+            node_id = event.node_id.as_deref(),
+            tx_hash = tx.and_then(|t| t.hash.as_deref()),
+            tx_status = tx.map(|t| t.status.as_str()),
+            kind = ?event.kind,
+            "{}",
+            event.message
+        );
+        let mut events = self.events.lock().unwrap();
+        match OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(storage().history_path())
+            .and_then(|mut file| {
+                let line = serde_json::to_string(&event).unwrap();
+                writeln!(file, "{}", line)
+            }) {
+            Ok(()) => {}
+            Err(e) => error!(
+                "Failed to append event for job {}: {}",
+                event.job_id, e
+            ),
+        }
+        events.entry(event.job_id.clone()).or_default().push(event);
+    }
+
+    pub fn timeline(&self, job_id: &str) -> Vec<JobEvent> {
+        let events = self.events.lock().unwrap();
+        events.get(job_id).cloned().unwrap_or_default()
+    }
+
+    /// Every recorded event, oldest first.
+    pub fn all(&self) -> Vec<JobEvent> {
+        let events = self.events.lock().unwrap();
+        let mut all: Vec<JobEvent> = events.values().flatten().cloned().collect();
+        all.sort_by_key(|e| e.at);
+        all
+    }
+
+    /// Rewrites the history file with `events`, e.g. when restoring an export.
+    pub fn replace(&self, events: Vec<JobEvent>) -> std::io::Result<()> {
+        let mut current = self.events.lock().unwrap();
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(storage().history_path())?;
+        current.clear();
+        for event in events {
+            writeln!(file, "{}", serde_json::to_string(&event).unwrap())?;
+            current.entry(event.job_id.clone()).or_default().push(event);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,562 @@
+use crate::config::AppConfig;
+use crate::models::{Job, Node};
+use crate::state::AppState;
+use crate::validation::{self, ValidationErrors};
+use actix_web::web;
+use reqwest::header::{ACCEPT, WWW_AUTHENTICATE};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::warn;
+
+const REGISTRY_TIMEOUT: Duration = Duration::from_secs(10);
+const MANIFEST_ACCEPT: &str = "application/vnd.oci.image.index.v1+json, \
+    application/vnd.docker.distribution.manifest.list.v2+json, \
+    application/vnd.oci.image.manifest.v1+json, \
+    application/vnd.docker.distribution.manifest.v2+json";
+/// Registry name under which CID-addressed images are matched by policies.
+const IPFS_REGISTRY: &str = "ipfs";
+const MAX_POLICY_ENTRIES: usize = 64;
+
+/// Rules a job's container image must satisfy. The scheduler applies the
+/// configured policy at submission; a node's own policy is applied on top of
+/// it when the node is picked, so it can only narrow what the node runs.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImagePolicy {
+    /// Registries (or `registry/namespace` prefixes) images must come from;
+    /// empty allows any registry not denied.
+    #[serde(rename = "allowedRegistries", default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_registries: Vec<String>,
+    #[serde(rename = "deniedRegistries", default, skip_serializing_if = "Vec::is_empty")]
+    pub denied_registries: Vec<String>,
+    /// Images must be pinned with `@sha256:`; CIDs are content-addressed and
+    /// always count as pinned.
+    #[serde(rename = "requireDigest", default)]
+    pub require_digest: bool,
+    /// Compressed size of config and layers, as listed in the manifest.
+    #[serde(rename = "maxSizeMb", default, skip_serializing_if = "Option::is_none")]
+    pub max_size_mb: Option<u64>,
+}
+
+/// A parsed `[registry/]repository[:tag][@digest]` reference, normalised the
+/// way Docker resolves it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageRef {
+    pub registry: String,
+    pub repository: String,
+    pub tag: Option<String>,
+    pub digest: Option<String>,
+}
+
+impl ImageRef {
+    /// Returns None for CIDs and references `validation::container_ref` rejects.
+    pub fn parse(value: &str) -> Option<Self> {
+        if validation::is_cid(value) || validation::container_ref(value).is_err() {
+            return None;
+        }
+        let (name_and_tag, digest) = match value.split_once('@') {
+            Some((n, d)) => (n, Some(d.to_string())),
+            None => (value, None),
+        };
+        let last_slash = name_and_tag.rfind('/').map(|i| i + 1).unwrap_or(0);
+        let (name, tag) = match name_and_tag[last_slash..].rfind(':') {
+            Some(i) => (
+                &name_and_tag[..last_slash + i],
+                Some(name_and_tag[last_slash + i + 1..].to_string()),
+            ),
+            None => (name_and_tag, None),
+        };
+
+        let (registry, repository) = match name.split_once('/') {
+            Some((first, rest))
+                if first.contains('.') || first.contains(':') || first == "localhost" =>
+            {
+                (first.to_string(), rest.to_string())
+            }
+            _ => ("docker.io".to_string(), name.to_string()),
+        };
+        let repository = if registry == "docker.io" && !repository.contains('/') {
+            format!("library/{}", repository)
+        } else {
+            repository
+        };
+        Some(Self {
+            registry,
+            repository,
+            tag,
+            digest,
+        })
+    }
+
+    /// The digest when pinned, otherwise the tag (`latest` by default).
+    pub fn reference(&self) -> &str {
+        self.digest
+            .as_deref()
+            .or(self.tag.as_deref())
+            .unwrap_or("latest")
+    }
+}
+
+/// `registry/repository` with the digest, or the tag (`latest` by default),
+/// so `ubuntu` and `docker.io/library/ubuntu:latest` compare equal. CIDs are
+/// returned unchanged.
+pub fn canonical(image: &str) -> Option<String> {
+    let image = image.trim();
+    if validation::is_cid(image) {
+        return Some(image.to_string());
+    }
+    let image = ImageRef::parse(image)?;
+    let separator = if image.digest.is_some() { '@' } else { ':' };
+    Some(format!(
+        "{}/{}{}{}",
+        image.registry,
+        image.repository,
+        separator,
+        image.reference()
+    ))
+}
+
+fn registry_of(image: &str) -> Option<(String, String)> {
+    if validation::is_cid(image) {
+        return Some((IPFS_REGISTRY.to_string(), image.to_string()));
+    }
+    ImageRef::parse(image).map(|r| (r.registry, r.repository))
+}
+
+fn matches(entry: &str, registry: &str, repository: &str) -> bool {
+    let entry = entry.trim_end_matches('/').to_ascii_lowercase();
+    let path = format!("{}/{}", registry, repository).to_ascii_lowercase();
+    entry == registry.to_ascii_lowercase() || path == entry || path.starts_with(&format!("{}/", entry))
+}
+
+impl ImagePolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        let entries = self.allowed_registries.iter().chain(&self.denied_registries);
+        if self.allowed_registries.len() + self.denied_registries.len() > MAX_POLICY_ENTRIES {
+            return Err(format!("may list at most {} registries", MAX_POLICY_ENTRIES));
+        }
+        for entry in entries {
+            validation::bounded_text(entry, validation::MAX_SPECS_LEN)?;
+            if entry.chars().any(char::is_whitespace) {
+                return Err(format!("registry '{}' must not contain whitespace", entry));
+            }
+        }
+        if self.max_size_mb == Some(0) {
+            return Err("maxSizeMb must be at least 1".to_string());
+        }
+        Ok(())
+    }
+
+    /// Checks everything but the size limit.
+    pub fn check_reference(&self, image: &str) -> Result<(), String> {
+        let (registry, repository) = match registry_of(image) {
+            Some(r) => r,
+            None => return Ok(()),
+        };
+        if self
+            .denied_registries
+            .iter()
+            .any(|e| matches(e, &registry, &repository))
+        {
+            return Err(format!("registry {} is denied by policy", registry));
+        }
+        if !self.allowed_registries.is_empty()
+            && !self
+                .allowed_registries
+                .iter()
+                .any(|e| matches(e, &registry, &repository))
+        {
+            return Err(format!("registry {} is not on the allowlist", registry));
+        }
+        if self.require_digest
+            && registry != IPFS_REGISTRY
+            && ImageRef::parse(image).is_some_and(|r| r.digest.is_none())
+        {
+            return Err("must be pinned with @sha256:<digest>".to_string());
+        }
+        Ok(())
+    }
+
+    /// An image whose size could not be determined fails any size limit.
+    pub fn check_size(&self, size_bytes: Option<u64>) -> Result<(), String> {
+        let limit = match self.max_size_mb {
+            Some(mb) => mb,
+            None => return Ok(()),
+        };
+        match size_bytes {
+            Some(size) if size <= limit * 1024 * 1024 => Ok(()),
+            Some(size) => Err(format!(
+                "image is {} MB, above the {} MB limit",
+                size.div_ceil(1024 * 1024),
+                limit
+            )),
+            None => Err(format!(
+                "image size could not be determined for the {} MB limit",
+                limit
+            )),
+        }
+    }
+
+    pub fn permits(&self, image: &str, size_bytes: Option<u64>) -> Result<(), String> {
+        self.check_reference(image)?;
+        self.check_size(size_bytes)
+    }
+}
+
+/// Whether the node's own image policy, if any, lets it run the job.
+pub fn node_accepts(node: &Node, job: &Job) -> bool {
+    node.image_policy
+        .as_ref()
+        .is_none_or(|p| p.permits(&job.container_cid, job.image_size).is_ok())
+}
+
+fn registry_base(cfg: &AppConfig, registry: &str) -> String {
+    let host = if registry == "docker.io" {
+        "registry-1.docker.io"
+    } else {
+        registry
+    };
+    let scheme = if cfg
+        .image_plain_http_registries
+        .iter()
+        .any(|r| r.eq_ignore_ascii_case(registry))
+    {
+        "http"
+    } else {
+        "https"
+    };
+    format!("{}://{}", scheme, host)
+}
+
+/// Parses the `key="value"` pairs of a `WWW-Authenticate: Bearer` challenge.
+fn challenge_params(header: &str) -> BTreeMap<String, String> {
+    let mut params = BTreeMap::new();
+    let mut rest = header.trim().strip_prefix("Bearer").unwrap_or(header).trim();
+    while let Some((key, after)) = rest.split_once('=') {
+        let (value, tail) = match after.trim_start().strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => after.split_once(',').unwrap_or((after, "")),
+        };
+        params.insert(key.trim().to_ascii_lowercase(), value.to_string());
+        rest = tail.trim_start_matches(',').trim();
+    }
+    params
+}
+
+/// Fetches an anonymous pull token, as Docker Hub and most public registries
+/// require even for public images.
+async fn bearer_token(client: &reqwest::Client, challenge: &str) -> Result<String, String> {
+    let params = challenge_params(challenge);
+    let realm = params
+        .get("realm")
+        .ok_or_else(|| "registry auth challenge has no realm".to_string())?;
+    let query: Vec<(&str, &str)> = ["service", "scope"]
+        .iter()
+        .filter_map(|k| params.get(*k).map(|v| (*k, v.as_str())))
+        .collect();
+    let body: Value = client
+        .get(realm)
+        .query(&query)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("registry token request failed: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("registry token response is invalid: {}", e))?;
+    body["token"]
+        .as_str()
+        .or(body["access_token"].as_str())
+        .map(str::to_string)
+        .ok_or_else(|| "registry token response has no token".to_string())
+}
+
+async fn fetch_manifest(
+    client: &reqwest::Client,
+    base: &str,
+    repository: &str,
+    reference: &str,
+) -> Result<Value, String> {
+    let url = format!("{}/v2/{}/manifests/{}", base, repository, reference);
+    let request = || client.get(&url).header(ACCEPT, MANIFEST_ACCEPT);
+    let mut response = request()
+        .send()
+        .await
+        .map_err(|e| format!("registry request failed: {}", e))?;
+
+    if response.status() == StatusCode::UNAUTHORIZED {
+        let challenge = response
+            .headers()
+            .get(WWW_AUTHENTICATE)
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        let token = bearer_token(client, &challenge).await?;
+        response = request()
+            .bearer_auth(token)
+            .send()
+            .await
+            .map_err(|e| format!("registry request failed: {}", e))?;
+    }
+    if !response.status().is_success() {
+        return Err(format!("registry returned {} for {}", response.status(), url));
+    }
+    response
+        .json()
+        .await
+        .map_err(|e| format!("registry manifest is invalid: {}", e))
+}
+
+/// Digest of the manifest to measure when `manifest` is a multi-platform
+/// index: the linux/amd64 entry, or the first one. None for plain manifests.
+fn index_entry(manifest: &Value) -> Result<Option<String>, String> {
+    let Some(entries) = manifest["manifests"].as_array() else {
+        return Ok(None);
+    };
+    let entry = entries
+        .iter()
+        .find(|m| m["platform"]["os"] == "linux" && m["platform"]["architecture"] == "amd64")
+        .or(entries.first())
+        .ok_or_else(|| "image index lists no manifests".to_string())?;
+    entry["digest"]
+        .as_str()
+        .map(|d| Some(d.to_string()))
+        .ok_or_else(|| "image index entry has no digest".to_string())
+}
+
+/// Sum of the layer and config sizes listed in an image manifest.
+fn manifest_size(manifest: &Value) -> Result<u64, String> {
+    let layers = manifest["layers"]
+        .as_array()
+        .ok_or_else(|| "registry manifest lists no layers".to_string())?;
+    Ok(layers
+        .iter()
+        .chain(std::iter::once(&manifest["config"]))
+        .filter_map(|l| l["size"].as_u64())
+        .sum())
+}
+
+/// Total compressed size of an image from its registry manifest. For
+/// multi-platform images the linux/amd64 entry is measured.
+pub async fn image_size(cfg: &AppConfig, image: &ImageRef) -> Result<u64, String> {
+    registry_image_size(&registry_base(cfg, &image.registry), image).await
+}
+
+async fn registry_image_size(base: &str, image: &ImageRef) -> Result<u64, String> {
+    let client = reqwest::Client::builder()
+        .timeout(REGISTRY_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut manifest = fetch_manifest(&client, base, &image.repository, image.reference()).await?;
+    if let Some(digest) = index_entry(&manifest)? {
+        manifest = fetch_manifest(&client, base, &image.repository, &digest).await?;
+    }
+    manifest_size(&manifest)
+}
+
+/// Applies the scheduler's image policy to a submitted image and returns its
+/// size when some policy needs it. Invalid references are left to
+/// `CreateJobRequest::into_job` to report.
+pub async fn admit(
+    data: &web::Data<AppState>,
+    image: Option<&str>,
+) -> Result<Option<u64>, ValidationErrors> {
+    let image = match image.map(str::trim).filter(|i| !i.is_empty()) {
+        Some(i) => i,
+        None => return Ok(None),
+    };
+    let policy = &data.cfg.image_policy;
+    let mut errors = ValidationErrors::default();
+    if let Err(e) = policy.check_reference(image) {
+        errors.add("containerCID", e);
+        return Err(errors);
+    }
+
+    let nodes_limit_size = data
+        .nodes
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|n| n.image_policy.as_ref().is_some_and(|p| p.max_size_mb.is_some()));
+    if policy.max_size_mb.is_none() && !nodes_limit_size {
+        return Ok(None);
+    }
+    let size = match ImageRef::parse(image) {
+        Some(image_ref) => match image_size(&data.cfg, &image_ref).await {
+            Ok(size) => Some(size),
+            Err(e) => {
+                warn!("Could not inspect image {}: {}", image, e);
+                None
+            }
+        },
+        None => None,
+    };
+    if let Err(e) = policy.check_size(size) {
+        errors.add("containerCID", e);
+        return Err(errors);
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{App, HttpRequest, HttpResponse, HttpServer};
+    use serde_json::json;
+
+    #[test]
+    fn challenge_params_reads_quoted_and_bare_values() {
+        let params = challenge_params(
+            r#"Bearer realm="https://auth.docker.io/token",service="registry.docker.io",scope="repository:library/ubuntu:pull""#,
+        );
+        assert_eq!(params["realm"], "https://auth.docker.io/token");
+        assert_eq!(params["service"], "registry.docker.io");
+        assert_eq!(params["scope"], "repository:library/ubuntu:pull");
+
+        let params = challenge_params("Bearer Realm=http://auth.local/token, service=local");
+        assert_eq!(params["realm"], "http://auth.local/token");
+        assert_eq!(params["service"], "local");
+    }
+
+    #[test]
+    fn challenge_params_keeps_commas_inside_quotes() {
+        let params = challenge_params(r#"Bearer realm="r",scope="repository:a:pull,push""#);
+        assert_eq!(params["scope"], "repository:a:pull,push");
+    }
+
+    #[test]
+    fn parse_normalises_docker_hub_names() {
+        let image = ImageRef::parse("ubuntu").unwrap();
+        assert_eq!(image.registry, "docker.io");
+        assert_eq!(image.repository, "library/ubuntu");
+        assert_eq!(image.reference(), "latest");
+
+        let image = ImageRef::parse("pytorch/pytorch:2.3.0").unwrap();
+        assert_eq!(image.registry, "docker.io");
+        assert_eq!(image.repository, "pytorch/pytorch");
+        assert_eq!(image.tag.as_deref(), Some("2.3.0"));
+
+        assert_eq!(
+            canonical("ubuntu").as_deref(),
+            canonical("docker.io/library/ubuntu:latest").as_deref()
+        );
+    }
+
+    #[test]
+    fn parse_keeps_registry_hosts_with_ports() {
+        let image = ImageRef::parse("localhost:5000/x").unwrap();
+        assert_eq!(image.registry, "localhost:5000");
+        assert_eq!(image.repository, "x");
+        assert_eq!(image.tag, None);
+
+        let image = ImageRef::parse("ghcr.io/team/app:v1").unwrap();
+        assert_eq!(image.registry, "ghcr.io");
+        assert_eq!(image.repository, "team/app");
+        assert_eq!(image.tag.as_deref(), Some("v1"));
+    }
+
+    #[test]
+    fn parse_prefers_the_digest_over_the_tag() {
+        let digest = format!("sha256:{}", "a".repeat(64));
+        let image = ImageRef::parse(&format!("ubuntu:22.04@{}", digest)).unwrap();
+        assert_eq!(image.tag.as_deref(), Some("22.04"));
+        assert_eq!(image.reference(), digest);
+    }
+
+    fn index(entries: &[(&str, &str, &str)]) -> Value {
+        json!({
+            "manifests": entries
+                .iter()
+                .map(|(os, arch, digest)| json!({
+                    "digest": digest,
+                    "platform": { "os": os, "architecture": arch },
+                }))
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    #[test]
+    fn index_entry_picks_linux_amd64() {
+        let manifest = index(&[
+            ("linux", "arm64", "sha256:arm"),
+            ("linux", "amd64", "sha256:amd"),
+        ]);
+        assert_eq!(index_entry(&manifest).unwrap().as_deref(), Some("sha256:amd"));
+    }
+
+    #[test]
+    fn index_entry_falls_back_to_the_first_entry() {
+        let manifest = index(&[("windows", "amd64", "sha256:win"), ("linux", "arm64", "sha256:arm")]);
+        assert_eq!(index_entry(&manifest).unwrap().as_deref(), Some("sha256:win"));
+        assert!(index_entry(&index(&[])).is_err());
+        assert_eq!(index_entry(&json!({ "layers": [] })).unwrap(), None);
+    }
+
+    #[test]
+    fn manifest_size_adds_layers_and_config() {
+        let manifest = json!({
+            "config": { "size": 7 },
+            "layers": [{ "size": 100 }, { "size": 20 }],
+        });
+        assert_eq!(manifest_size(&manifest).unwrap(), 127);
+        assert!(manifest_size(&json!({})).is_err());
+    }
+
+    /// A registry serving a two-platform index for `team/app:v1` whose
+    /// manifests require an anonymous bearer token, as Docker Hub does.
+    async fn registry(req: HttpRequest) -> HttpResponse {
+        let host = req.connection_info().host().to_string();
+        if req.path() == "/token" {
+            return HttpResponse::Ok().json(json!({ "token": "anonymous" }));
+        }
+        let authorized = req
+            .headers()
+            .get("authorization")
+            .is_some_and(|v| v == "Bearer anonymous");
+        if !authorized {
+            return HttpResponse::Unauthorized()
+                .insert_header((
+                    "WWW-Authenticate",
+                    format!(
+                        r#"Bearer realm="http://{}/token",service="test",scope="repository:team/app:pull""#,
+                        host
+                    ),
+                ))
+                .finish();
+        }
+        match req.path() {
+            "/v2/team/app/manifests/v1" => HttpResponse::Ok().json(index(&[
+                ("linux", "arm64", "sha256:arm"),
+                ("linux", "amd64", "sha256:amd"),
+            ])),
+            "/v2/team/app/manifests/sha256:amd" => HttpResponse::Ok().json(json!({
+                "config": { "size": 1000 },
+                "layers": [{ "size": 52_428_800 }],
+            })),
+            _ => HttpResponse::NotFound().finish(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn image_size_follows_the_index_through_a_bearer_challenge() {
+        let server = HttpServer::new(|| App::new().default_service(web::to(registry)))
+            .workers(1)
+            .bind("127.0.0.1:0")
+            .unwrap();
+        let addr = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let base = format!("http://{}", addr);
+        let image = ImageRef::parse(&format!("{}/team/app:v1", addr)).unwrap();
+        assert_eq!(registry_image_size(&base, &image).await, Ok(52_429_800));
+
+        let missing = ImageRef::parse(&format!("{}/team/gone:v1", addr)).unwrap();
+        assert!(registry_image_size(&base, &missing).await.is_err());
+
+        handle.stop(false).await;
+    }
+}
@@ -1,49 +1,370 @@
-use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use crate::amount;
+use crate::arrays;
+use crate::auth;
+use crate::config::AppConfig;
+use crate::erc20;
+use crate::helper::save_jobs;
+use crate::history::{JobEvent, JobEventKind};
+use crate::images;
+use crate::models::{CreateJobRequest, Priority};
+use crate::validation;
+use crate::workflow;
 use crate::{models::Job, state::AppState};
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{error, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use ethers::types::{Address, U256};
+use ethers::utils::hex;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{field, info, instrument, warn, Span};
 
-async fn get_jobs(data: web::Data<AppState>) -> impl Responder {
-    let jobs = data.jobs.lock().unwrap();
-    HttpResponse::Ok().json(&*jobs)
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 500;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SortField {
+    #[default]
+    CreatedAt,
+    Bounty,
+    Deadline,
 }
 
-async fn add_job(job: web::Json<Job>, data: web::Data<AppState>) -> impl Responder {
-    let mut jobs = data.jobs.lock().unwrap();
-    let new_job = job.into_inner();
-    jobs.push(new_job.clone());
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JobQuery {
+    status: Option<String>,
+    owner: Option<String>,
+    assigned_node: Option<String>,
+    required_specs: Option<String>,
+    created_after: Option<DateTime<Utc>>,
+    created_before: Option<DateTime<Utc>>,
+    /// Whole tokens, compared exactly.
+    min_bounty: Option<String>,
+    max_bounty: Option<String>,
+    /// Bounty token that bounty filters and sorting apply to; native if unset.
+    token: Option<Address>,
+    #[serde(default)]
+    sort: SortField,
+    #[serde(default)]
+    order: SortOrder,
+    limit: Option<usize>,
+    cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(untagged)]
+enum SortValue {
+    /// Seconds (deadlines) or microseconds (creation) since the epoch.
+    Int(i64),
+    /// Bounties in base units, kept as a decimal string in cursors.
+    Amount(
+        #[serde(
+            serialize_with = "amount::serialize_units",
+            deserialize_with = "amount::deserialize_units"
+        )]
+        U256,
+    ),
+}
+/// Position of the last job on a page; encoded as hex JSON so clients treat it as opaque.
+#[derive(Debug, Serialize, Deserialize)]
+struct Cursor {
+    value: SortValue,
+    job_id: String,
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        hex::encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    fn decode(raw: &str) -> Option<Self> {
+        let bytes = hex::decode(raw).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+}
+
+fn sort_value(job: &Job, field: SortField) -> SortValue {
+    // Times are compared parsed, as deadlines mix dates and timestamps; one
+    // that does not parse sorts after every other.
+    match field {
+        SortField::CreatedAt => SortValue::Int(
+            created_at(job)
+                .map(|c| c.timestamp_micros())
+                .unwrap_or(i64::MAX),
+        ),
+        SortField::Bounty => SortValue::Amount(job.bounty.units),
+        SortField::Deadline => SortValue::Int(
+            validation::parse_deadline(&job.deadline)
+                .map(|d| d.timestamp())
+                .unwrap_or(i64::MAX),
+        ),
+    }
+}
+
+impl JobQuery {
+    /// Bounties in different tokens are not comparable, so filtering or
+    /// sorting by bounty keeps only jobs paid in the queried token.
+    fn scoped_to_token(&self) -> bool {
+        matches!(self.sort, SortField::Bounty)
+            || self.min_bounty.is_some()
+            || self.max_bounty.is_some()
+    }
+}
+
+fn compare_keys(a: (&SortValue, &str), b: (&SortValue, &str), order: SortOrder) -> Ordering {
+    let ordering = a.0.cmp(b.0).then_with(|| a.1.cmp(b.1));
+    match order {
+        SortOrder::Asc => ordering,
+        SortOrder::Desc => ordering.reverse(),
+    }
+}
+
+fn created_at(job: &Job) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(&job.created_at)
+        .ok()
+        .map(|d| d.with_timezone(&Utc))
+}
+
+/// Every filter except `status`, so status counts stay useful as tab badges.
+fn matches_query(job: &Job, query: &JobQuery) -> bool {
+    if let Some(owner) = &query.owner {
+        if !job.owner.eq_ignore_ascii_case(owner) {
+            return false;
+        }
+    }
+    if let Some(node) = &query.assigned_node {
+        if job.assigned_node.as_ref() != Some(node) {
+            return false;
+        }
+    }
+    if let Some(specs) = &query.required_specs {
+        if job.required_specs != *specs {
+            return false;
+        }
+    }
+    if query.created_after.is_some() || query.created_before.is_some() {
+        let created = match created_at(job) {
+            Some(c) => c,
+            None => return false,
+        };
+        if query.created_after.is_some_and(|after| created < after)
+            || query.created_before.is_some_and(|before| created > before)
+        {
+            return false;
+        }
+    }
+    if query.scoped_to_token() && job.bounty.token.address != query.token {
+        return false;
+    }
+    if query
+        .min_bounty
+        .as_deref()
+        .is_some_and(|min| job.bounty.cmp_decimal(min) == Ordering::Less)
+        || query
+            .max_bounty
+            .as_deref()
+            .is_some_and(|max| job.bounty.cmp_decimal(max) == Ordering::Greater)
+    {
+        return false;
+    }
+    true
+}
+
+async fn get_jobs(query: web::Query<JobQuery>, data: web::Data<AppState>) -> impl Responder {
+    let query = query.into_inner();
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let cursor = match query.cursor.as_deref().map(Cursor::decode) {
+        Some(Some(c)) => Some(c),
+        Some(None) => {
+            return HttpResponse::BadRequest().json(json!({ "error": "Invalid cursor" }));
+        }
+        None => None,
+    };
+    for (field, value) in [("min_bounty", &query.min_bounty), ("max_bounty", &query.max_bounty)] {
+        if let Some(Err(e)) = value.as_deref().map(amount::check_decimal) {
+            return HttpResponse::BadRequest()
+                .json(json!({ "error": format!("Invalid {}: {}", field, e) }));
+        }
+    }
 
-    let file_path = "jobs.json";
+    let jobs = data.jobs.lock().unwrap();
 
-    let mut all_jobs: Vec<Job> = if let Ok(mut file) = File::open(file_path) {
-        let mut content = String::new();
-        file.read_to_string(&mut content).unwrap();
-        if content.is_empty() {
-            Vec::new()
-        } else {
-            serde_json::from_str(&content).unwrap_or_else(|_| Vec::new())
+    let mut counts_by_status: BTreeMap<String, usize> = BTreeMap::new();
+    let mut matching: Vec<(SortValue, &Job)> = Vec::new();
+    for job in jobs.iter().filter(|j| matches_query(j, &query)) {
+        *counts_by_status.entry(job.status.clone()).or_default() += 1;
+        if query.status.as_ref().is_none_or(|s| *s == job.status) {
+            matching.push((sort_value(job, query.sort), job));
         }
+    }
+
+    matching.sort_by(|a, b| compare_keys((&a.0, &a.1.job_id), (&b.0, &b.1.job_id), query.order));
+    let total = matching.len();
+
+    let start = match &cursor {
+        Some(c) => matching.partition_point(|(value, job)| {
+            compare_keys((value, &job.job_id), (&c.value, &c.job_id), query.order)
+                != Ordering::Greater
+        }),
+        None => 0,
+    };
+    let page: Vec<(SortValue, &Job)> = matching.into_iter().skip(start).take(limit + 1).collect();
+    let has_more = page.len() > limit;
+    let page = &page[..page.len().min(limit)];
+
+    let next_cursor = if has_more {
+        page.last().map(|(value, job)| {
+            Cursor {
+                value: value.clone(),
+                job_id: job.job_id.clone(),
+            }
+            .encode()
+        })
     } else {
-        Vec::new()
+        None
+    };
+    let items: Vec<&Job> = page.iter().map(|(_, job)| *job).collect();
+
+    HttpResponse::Ok().json(json!({
+        "jobs": items,
+        "meta": {
+            "total": total,
+            "count": items.len(),
+            "next_cursor": next_cursor,
+            "counts_by_status": counts_by_status,
+        }
+    }))
+}
+
+#[instrument(skip_all, fields(job_id = field::Empty))]
+async fn add_job(
+    http_req: HttpRequest,
+    req: web::Json<CreateJobRequest>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let mut req = req.into_inner();
+    let image_size = match images::admit(&data, req.container_cid.as_deref()).await {
+        Ok(size) => size,
+        Err(errors) => {
+            warn!("Rejected job submission: image policy");
+            return errors.into_response();
+        }
     };
-    all_jobs.push(new_job);
-    let mut file = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(file_path)
-        .unwrap();
+    if let Err(errors) = erc20::admit(&data, &mut req, 1).await {
+        warn!("Rejected job submission: token bounty");
+        return errors.into_response();
+    }
 
-    let json = serde_json::to_string_pretty(&all_jobs).unwrap();
-    file.write_all(json.as_bytes()).unwrap();
+    let mut jobs = data.jobs.lock().unwrap();
+    let mut new_job = match req.into_job(&jobs) {
+        Ok(job) => job,
+        Err(errors) => {
+            warn!("Rejected job submission: validation failed");
+            return errors.into_response();
+        }
+    };
+
+    new_job.image_size = image_size;
+    cap_priority(&http_req, &data.cfg, &mut new_job);
+    let message = format!("Job submitted by {}", new_job.owner);
+    let new_job = accept_job(&data, &mut jobs, new_job, message);
+    save_jobs(&jobs);
+    Span::current().record("job_id", new_job.job_id.as_str());
+
+    HttpResponse::Created().json(json!({
+        "success": true,
+        "job": new_job,
+    }))
+}
+
+/// "urgent" jobs preempt everything else, so the level is only taken from
+/// requests signed by the job's owner or carrying the admin token; other
+/// submissions get at most "high".
+pub fn cap_priority(req: &HttpRequest, cfg: &AppConfig, job: &mut Job) {
+    if job.priority == Some(Priority::Urgent) && !auth::vouched_for(req, &job.owner, cfg) {
+        info!(
+            "Capped priority of job {} at high: request not signed by its owner",
+            job.job_id
+        );
+        job.priority = Some(Priority::High);
+    }
+}
+
+/// Links a validated job into its workflow, queues it and records its creation.
+/// The caller saves the job list.
+pub fn accept_job(
+    data: &web::Data<AppState>,
+    jobs: &mut Vec<Job>,
+    mut job: Job,
+    message: String,
+) -> Job {
+    let unresolved = workflow::attach(&mut job, jobs).err();
+
+    jobs.push(job.clone());
+    data.events.publish_job(&job);
+    data.history.record(JobEvent::new(&job.job_id, JobEventKind::Created, message));
+    if let Some(reason) = unresolved {
+        data.history.record(JobEvent::new(&job.job_id, JobEventKind::Cancelled, reason));
+    }
+    if job.status == "blocked" {
+        data.history.record(JobEvent::new(
+            &job.job_id,
+            JobEventKind::Blocked,
+            format!("Waiting on {}", job.depends_on.join(", ")),
+        ));
+    }
+    info!("Accepted job {}", job.job_id);
+    job
+}
+
+async fn get_job(job_id: web::Path<String>, data: web::Data<AppState>) -> impl Responder {
+    let job_id = job_id.into_inner();
+    let job = {
+        let jobs = data.jobs.lock().unwrap();
+        jobs.iter().find(|j| j.job_id == job_id).cloned()
+    };
+    match job {
+        Some(job) => HttpResponse::Ok().json(json!({
+            "job": job,
+            "timeline": data.history.timeline(&job_id),
+        })),
+        None => HttpResponse::NotFound().json(json!({ "error": "Job not found" })),
+    }
+}
 
-    HttpResponse::Ok().json(&*jobs)
+fn json_error_handler(err: error::JsonPayloadError, _req: &actix_web::HttpRequest) -> actix_web::Error {
+    let message = err.to_string();
+    error::InternalError::from_response(
+        err,
+        HttpResponse::BadRequest().json(json!({
+            "error": "Invalid JSON body",
+            "detail": message,
+        })),
+    )
+    .into()
 }
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/jobs")
+            .app_data(web::JsonConfig::default().error_handler(json_error_handler))
             .route("", web::get().to(get_jobs))
-            .route("", web::post().to(add_job)),
+            .route("", web::post().to(add_job))
+            .route("/array", web::post().to(arrays::add_job_array))
+            .route("/array/{id}", web::get().to(arrays::get_job_array))
+            .route("/array/{id}/results", web::get().to(arrays::get_job_array_results))
+            .route("/array/{id}/cancel", web::post().to(arrays::cancel_job_array))
+            .route("/{id}", web::get().to(get_job)),
     );
 }
@@ -0,0 +1,454 @@
+use crate::allocation;
+use crate::events::Event;
+use crate::helper::{save_jobs, save_leases};
+use crate::history::{JobEvent, JobEventKind};
+use crate::models::{Job, Lease, Node};
+use crate::nodes::{assign_on_chain, save_nodes, update_job_and_node_state};
+use crate::queue::{self, Preemption};
+use crate::state::AppState;
+use crate::usage;
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{Duration as ChronoDuration, Utc};
+use ethers::types::Address;
+use serde::Deserialize;
+use serde_json::json;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::Instant;
+use tracing::{field, info, info_span, instrument, warn, Instrument, Span};
+use uuid::Uuid;
+
+const REAPER_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Default, Deserialize)]
+pub struct LeaseRequest {
+    pub wait_secs: Option<u64>,
+}
+
+/// Outcome of a single, non-blocking attempt to hand a node some work.
+enum LeaseAttempt {
+    Leased(Lease),
+    /// A pending job was claimed for the node and must be assigned on-chain.
+    Claimed {
+        job_id: String,
+        node: Box<Node>,
+        owner: Address,
+    },
+    Nothing,
+}
+
+/// Why a claimed job could not be handed to the node after all.
+enum ClaimFailure {
+    Chain,
+    Conflict(String),
+}
+
+#[instrument(skip_all, fields(node_id = %node_id, job_id = field::Empty))]
+pub async fn acquire_lease(
+    node_id: web::Path<String>,
+    req: Option<web::Json<LeaseRequest>>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let node_id = node_id.into_inner();
+    let max_wait = data.cfg.lease_max_wait_secs;
+    let wait_secs = req
+        .and_then(|r| r.wait_secs)
+        .unwrap_or(max_wait)
+        .min(max_wait);
+    let deadline = Instant::now() + Duration::from_secs(wait_secs);
+    info!(
+        "Lease requested by node {} (waiting up to {}s)",
+        node_id, wait_secs
+    );
+
+    // Subscribe before the first attempt so no assignment slips through
+    // between checking the state and starting to wait.
+    let mut rx = data.events.subscribe();
+
+    loop {
+        match try_lease(&data, &node_id) {
+            Ok(LeaseAttempt::Leased(lease)) => {
+                Span::current().record("job_id", lease.job_id.as_str());
+                return lease_response(&data, lease);
+            }
+            Ok(LeaseAttempt::Claimed {
+                job_id,
+                node,
+                owner,
+            }) => {
+                Span::current().record("job_id", job_id.as_str());
+                // Run the assignment detached so a client disconnect cannot
+                // leave the job stuck in "assigning".
+                let task_data = data.clone();
+                let assigned = tokio::spawn(async move {
+                    match assign_on_chain(&task_data, &job_id, owner).await {
+                        Ok(()) => {
+                            let reason = format!(
+                                "Oldest pending job matching specs '{}' leased by idle node",
+                                node.gpu_specs
+                            );
+                            update_job_and_node_state(&task_data, &job_id, owner, &node, &reason)
+                                .map(|_| ())
+                                .map_err(ClaimFailure::Conflict)
+                        }
+                        Err(_) => {
+                            unclaim_job(&task_data, &job_id, "On-chain assignment failed");
+                            Err(ClaimFailure::Chain)
+                        }
+                    }
+                }.in_current_span())
+                .await
+                .unwrap_or(Err(ClaimFailure::Chain));
+                match assigned {
+                    Ok(()) => continue,
+                    Err(ClaimFailure::Chain) => {
+                        return HttpResponse::InternalServerError()
+                            .json(json!({ "error": "On-chain assignment failed" }))
+                    }
+                    Err(ClaimFailure::Conflict(e)) => {
+                        return HttpResponse::Conflict().json(json!({ "error": e }))
+                    }
+                }
+            }
+            Ok(LeaseAttempt::Nothing) => {}
+            Err(resp) => return *resp,
+        }
+
+        loop {
+            match tokio::time::timeout_at(deadline, rx.recv()).await {
+                Err(_) | Ok(Err(RecvError::Closed)) => {
+                    info!("Lease wait timed out for node {}", node_id);
+                    return HttpResponse::NoContent().finish();
+                }
+                Ok(Err(RecvError::Lagged(_))) => break,
+                Ok(Ok(Event::JobUpdated { job }))
+                    if job.status == "pending"
+                        || job.assigned_node.as_deref() == Some(node_id.as_str()) =>
+                {
+                    break
+                }
+                Ok(Ok(Event::NodeUpdated { node })) if node.node_id == node_id => break,
+                Ok(Ok(_)) => continue,
+            }
+        }
+    }
+}
+
+fn try_lease(
+    data: &web::Data<AppState>,
+    node_id: &str,
+) -> Result<LeaseAttempt, Box<HttpResponse>> {
+    let mut jobs = data.jobs.lock().unwrap();
+    let mut nodes = data.nodes.lock().unwrap();
+    let node = match nodes.iter_mut().find(|n| n.node_id == node_id && n.active) {
+        Some(n) => n,
+        None => {
+            warn!(
+                "Lease requested by unknown or inactive node {}",
+                node_id
+            );
+            return Err(Box::new(
+                HttpResponse::NotFound().json(json!({ "error": "Node not found or inactive" })),
+            ));
+        }
+    };
+    let mut leases = data.leases.lock().unwrap();
+
+    if let Some(job) = jobs
+        .iter_mut()
+        .find(|j| j.status == "assigned" && j.assigned_node.as_deref() == Some(node_id))
+    {
+        let now = Utc::now();
+        let lease = Lease {
+            lease_id: Uuid::new_v4().to_string(),
+            job_id: job.job_id.clone(),
+            node_id: node_id.to_string(),
+            issued_at: now,
+            expires_at: now + ChronoDuration::seconds(data.cfg.lease_ttl_secs as i64),
+            renewals: 0,
+        };
+        job.status = "running".to_string();
+        // Normally set at assignment; the lease does not restart the clock.
+        job.started_at.get_or_insert(now);
+        data.history.record(
+            JobEvent::new(
+                &job.job_id,
+                JobEventKind::LeaseIssued,
+                format!("Lease {} issued until {}", lease.lease_id, lease.expires_at),
+            )
+            .node(node_id),
+        );
+        leases.push(lease.clone());
+        data.events.publish_job(job);
+        save_jobs(&jobs);
+        save_leases(&leases);
+        info!(
+            "Issued lease {} for job {} to node {}",
+            lease.lease_id, lease.job_id, node_id
+        );
+        return Ok(LeaseAttempt::Leased(lease));
+    }
+
+    if !node.is_chain_registered() {
+        return Ok(LeaseAttempt::Nothing);
+    }
+    let owner = match node.owner.as_deref().map(Address::from_str) {
+        Some(Ok(addr)) => addr,
+        _ => return Ok(LeaseAttempt::Nothing),
+    };
+
+    // Take the first queued job that fits the node's free GPUs; mark it and
+    // reserve its GPUs so no other node or assign-provider call can pick it
+    // while the chain call is in flight.
+    let mut next = queue::next_for_node(&jobs, node, &data.cfg);
+    if next.is_none() && data.cfg.preemption_enabled {
+        if let Some(plan) = queue::plan_preemption(&jobs, node, &data.cfg) {
+            preempt(data, &mut jobs, node, &mut leases, &plan);
+            next = Some(plan.job_id);
+        }
+    }
+    let candidate = next.and_then(|id| jobs.iter_mut().find(|j| j.job_id == id));
+    match candidate {
+        Some(job) => {
+            allocation::allocate(node, job);
+            data.events.publish_node(node);
+            let node = node.clone();
+            save_nodes(&nodes);
+            job.status = "assigning".to_string();
+            job.assigned_node = Some(node.node_id.clone());
+            data.history.record(
+                JobEvent::new(
+                    &job.job_id,
+                    JobEventKind::AssignmentAttempted,
+                    "Claimed for a node with free GPUs requesting a lease",
+                )
+                .node(&node.node_id),
+            );
+            info!(
+                "Claimed job {} for node {}",
+                job.job_id, node.node_id
+            );
+            Ok(LeaseAttempt::Claimed {
+                job_id: job.job_id.clone(),
+                node: Box::new(node),
+                owner,
+            })
+        }
+        None => Ok(LeaseAttempt::Nothing),
+    }
+}
+
+/// Evicts the plan's victims from `node` and returns them to the pending queue.
+/// Like expired leases, their on-chain provider is left for the next
+/// placement to overwrite.
+fn preempt(
+    data: &web::Data<AppState>,
+    jobs: &mut [Job],
+    node: &mut Node,
+    leases: &mut Vec<Lease>,
+    plan: &Preemption,
+) {
+    for victim in &plan.victims {
+        let job = match jobs.iter_mut().find(|j| &j.job_id == victim) {
+            Some(j) => j,
+            None => continue,
+        };
+        warn!(
+            "Preempting job {} on node {} for job {}",
+            job.job_id, node.node_id, plan.job_id
+        );
+        job.status = "pending".to_string();
+        job.assigned_node = None;
+        job.provider_address = None;
+        job.started_at = None;
+        data.history.record(
+            JobEvent::new(
+                &job.job_id,
+                JobEventKind::Preempted,
+                format!(
+                    "Evicted for higher-priority job {}; returned to pending queue",
+                    plan.job_id
+                ),
+            )
+            .node(&node.node_id),
+        );
+        data.events.publish_job(job);
+        allocation::release(node, victim);
+        leases.retain(|l| &l.job_id != victim);
+    }
+    save_jobs(jobs);
+    save_leases(leases);
+}
+
+/// Returns a job claimed for assignment to the pending queue, frees the GPUs
+/// reserved for it and records why the assignment failed.
+pub fn unclaim_job(data: &web::Data<AppState>, job_id: &str, reason: &str) {
+    let mut jobs = data.jobs.lock().unwrap();
+    let mut nodes = data.nodes.lock().unwrap();
+    // A job cancelled or requeued during the chain call no longer belongs to
+    // this claim; whoever changed it has already released its GPUs.
+    let Some(job) = jobs
+        .iter_mut()
+        .find(|j| j.job_id == job_id && j.status == "assigning")
+    else {
+        return;
+    };
+    let node_id = job.assigned_node.take();
+    if let Some(node) = nodes
+        .iter_mut()
+        .find(|n| Some(&n.node_id) == node_id.as_ref())
+    {
+        allocation::release(node, job_id);
+        data.events.publish_node(node);
+        save_nodes(&nodes);
+    }
+    job.status = "pending".to_string();
+    job.started_at = None;
+    let mut event = JobEvent::new(job_id, JobEventKind::AssignmentFailed, reason);
+    if let Some(node_id) = &node_id {
+        event = event.node(node_id);
+    }
+    data.history.record(event);
+    data.events.publish_job(job);
+    save_jobs(&jobs);
+    warn!(
+        "Released claim on job {} after failed assignment: {}",
+        job_id, reason
+    );
+}
+
+fn lease_response(data: &web::Data<AppState>, lease: Lease) -> HttpResponse {
+    let jobs = data.jobs.lock().unwrap();
+    let job = jobs.iter().find(|j| j.job_id == lease.job_id).cloned();
+    HttpResponse::Ok().json(json!({
+        "lease_id": lease.lease_id,
+        "expires_at": lease.expires_at,
+        "ttl_secs": data.cfg.lease_ttl_secs,
+        "job": job,
+    }))
+}
+
+#[instrument(skip_all, fields(node_id = field::Empty, lease_id = field::Empty))]
+pub async fn renew_lease(
+    path: web::Path<(String, String)>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let (node_id, lease_id) = path.into_inner();
+    Span::current()
+        .record("node_id", node_id.as_str())
+        .record("lease_id", lease_id.as_str());
+    let mut leases = data.leases.lock().unwrap();
+
+    let lease = match leases
+        .iter_mut()
+        .find(|l| l.lease_id == lease_id && l.node_id == node_id)
+    {
+        Some(l) => l,
+        None => {
+            warn!(
+                "Renewal for unknown lease {} from node {}",
+                lease_id, node_id
+            );
+            return HttpResponse::NotFound().json(json!({ "error": "Lease not found" }));
+        }
+    };
+
+    let now = Utc::now();
+    if lease.expires_at <= now {
+        warn!("Renewal for expired lease {}", lease_id);
+        return HttpResponse::Gone().json(json!({ "error": "Lease expired" }));
+    }
+
+    lease.expires_at = now + ChronoDuration::seconds(data.cfg.lease_ttl_secs as i64);
+    lease.renewals += 1;
+    let renewed = lease.clone();
+    save_leases(&leases);
+    info!(
+        "Renewed lease {} until {}",
+        renewed.lease_id, renewed.expires_at
+    );
+
+    HttpResponse::Ok().json(renewed)
+}
+
+/// Drops any lease held on `job_id`, e.g. once its result is submitted.
+pub fn release_job_leases(data: &web::Data<AppState>, job_id: &str) {
+    let mut leases = data.leases.lock().unwrap();
+    let before = leases.len();
+    leases.retain(|l| l.job_id != job_id);
+    if leases.len() != before {
+        save_leases(&leases);
+        info!("Released lease for job {}", job_id);
+    }
+}
+
+/// Puts jobs whose lease ran out back in the pending queue and frees their nodes.
+///
+/// The provider recorded by `assignProvider` is not undone on-chain. That is
+/// safe because JobRegistry only reads it when a result is submitted, the
+/// scheduler only submits results from the node a job is assigned to, and a
+/// requeued job is assigned on-chain again, overwriting the provider, before
+/// any node is handed it.
+pub fn expire_leases(data: &web::Data<AppState>) {
+    let now = Utc::now();
+    let mut jobs = data.jobs.lock().unwrap();
+    let mut nodes = data.nodes.lock().unwrap();
+    let mut leases = data.leases.lock().unwrap();
+
+    let (expired, active): (Vec<Lease>, Vec<Lease>) =
+        leases.drain(..).partition(|l| l.expires_at <= now);
+    *leases = active;
+    if expired.is_empty() {
+        return;
+    }
+
+    for lease in &expired {
+        let _span = info_span!("job", job_id = %lease.job_id, node_id = %lease.node_id).entered();
+        warn!(
+            "Lease {} for job {} on node {} expired",
+            lease.lease_id, lease.job_id, lease.node_id
+        );
+        data.history.record(
+            JobEvent::new(
+                &lease.job_id,
+                JobEventKind::LeaseExpired,
+                format!("Lease {} expired without renewal", lease.lease_id),
+            )
+            .node(&lease.node_id),
+        );
+        if let Some(job) = jobs
+            .iter_mut()
+            .find(|j| j.job_id == lease.job_id && j.status == "running")
+        {
+            job.status = "pending".to_string();
+            job.assigned_node = None;
+            job.provider_address = None;
+            job.started_at = None;
+            job.retries = job.retries.saturating_add(1);
+            data.history.record(JobEvent::new(
+                &job.job_id,
+                JobEventKind::Requeued,
+                format!("Returned to pending queue (retry {})", job.retries),
+            ));
+            data.events.publish_job(job);
+        }
+        if let Some(node) = nodes.iter_mut().find(|n| n.node_id == lease.node_id) {
+            allocation::release(node, &lease.job_id);
+            data.events.publish_node(node);
+        }
+    }
+
+    save_jobs(&jobs);
+    save_nodes(&nodes);
+    save_leases(&leases);
+}
+
+pub async fn run_lease_reaper(data: web::Data<AppState>) {
+    let mut interval = tokio::time::interval(REAPER_INTERVAL);
+    loop {
+        interval.tick().await;
+        expire_leases(&data);
+        usage::enforce_runtime_limits(&data);
+    }
+}
@@ -0,0 +1,168 @@
+use crate::allocation;
+use crate::dataset::DatasetRef;
+use crate::images;
+use crate::models::{Job, Node};
+use crate::nodes::save_nodes;
+use crate::state::AppState;
+use crate::validation::ValidationErrors;
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::info;
+
+/// Entries of each kind a node may report; agents should send their most
+/// recently used items first.
+pub const MAX_CACHE_ENTRIES: usize = 1000;
+
+/// Datasets and images a node reports holding locally, stored in the form
+/// jobs are matched against.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NodeCache {
+    /// Cache keys of `DatasetRef`s: CIDs, URLs and `s3://` URIs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub datasets: Vec<String>,
+    /// Canonical image references.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<String>,
+    #[serde(rename = "reportedAt", default, skip_serializing_if = "Option::is_none")]
+    pub reported_at: Option<DateTime<Utc>>,
+}
+
+impl NodeCache {
+    pub fn is_empty(&self) -> bool {
+        self.datasets.is_empty() && self.images.is_empty()
+    }
+}
+
+/// What a node sends in `cache` at registration or heartbeat. Datasets may be
+/// given in any form `dataset` accepts as a string.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CacheReport {
+    #[serde(default)]
+    pub datasets: Vec<String>,
+    #[serde(default)]
+    pub images: Vec<String>,
+}
+
+impl CacheReport {
+    pub fn into_cache(self) -> Result<NodeCache, ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        for (field, len) in [("cache.datasets", self.datasets.len()), ("cache.images", self.images.len())] {
+            if len > MAX_CACHE_ENTRIES {
+                errors.add(field, format!("must list at most {} entries", MAX_CACHE_ENTRIES));
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let mut datasets = Vec::new();
+        for (i, entry) in self.datasets.iter().enumerate() {
+            let key = DatasetRef::from_legacy(entry).and_then(|d| {
+                d.cache_key()
+                    .ok_or_else(|| "inline data cannot be cached".to_string())
+            });
+            if let Some(key) = errors.check(&format!("cache.datasets[{}]", i), key) {
+                if !datasets.contains(&key) {
+                    datasets.push(key);
+                }
+            }
+        }
+        let mut images = Vec::new();
+        for (i, entry) in self.images.iter().enumerate() {
+            match images::canonical(entry) {
+                Some(image) if !images.contains(&image) => images.push(image),
+                Some(_) => {}
+                None => errors.add(
+                    &format!("cache.images[{}]", i),
+                    "must be a container image reference or CID",
+                ),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(NodeCache {
+            datasets,
+            images,
+            reported_at: Some(Utc::now()),
+        })
+    }
+}
+
+/// Share of the job's inputs already on the node: half for the dataset and
+/// half for the image.
+pub fn locality(node: &Node, job: &Job) -> f64 {
+    let has_dataset = job
+        .dataset
+        .as_ref()
+        .and_then(DatasetRef::cache_key)
+        .is_some_and(|key| node.cache.datasets.contains(&key));
+    let has_image = images::canonical(&job.container_cid)
+        .is_some_and(|image| node.cache.images.contains(&image));
+    (has_dataset as u8 + has_image as u8) as f64 / 2.0
+}
+
+/// Picks among nodes that can run the job. `weight` blends locality with the
+/// best-fit placement used otherwise: 0 ignores caches and 1 lets any cache
+/// hit outrank a tighter fit. Ties go to the tighter fit.
+pub fn best_node<'a>(job: &Job, candidates: Vec<&'a Node>, weight: f64) -> Option<&'a Node> {
+    let weight = weight.clamp(0.0, 1.0);
+    let max_free = candidates
+        .iter()
+        .map(|n| allocation::total_free_memory(n))
+        .max()?
+        .max(1);
+    let score = |n: &Node| {
+        let fit = 1.0 - allocation::total_free_memory(n) as f64 / max_free as f64;
+        weight * locality(n, job) + (1.0 - weight) * fit
+    };
+    candidates.into_iter().max_by(|a, b| {
+        score(a).total_cmp(&score(b)).then_with(|| {
+            allocation::total_free_memory(b).cmp(&allocation::total_free_memory(a))
+        })
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HeartbeatRequest {
+    #[serde(default)]
+    pub cache: Option<CacheReport>,
+}
+
+/// Lets an agent refresh what it has cached between registrations. A report
+/// replaces the previous one.
+pub async fn node_heartbeat(
+    node_id: web::Path<String>,
+    req: web::Json<HeartbeatRequest>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let cache = match req.into_inner().cache.map(CacheReport::into_cache).transpose() {
+        Ok(cache) => cache,
+        Err(errors) => return errors.into_response(),
+    };
+
+    let mut nodes = data.nodes.lock().unwrap();
+    let node = match nodes.iter_mut().find(|n| n.node_id == *node_id) {
+        Some(n) => n,
+        None => return HttpResponse::NotFound().json(json!({ "error": "Node not found" })),
+    };
+    if let Some(cache) = cache {
+        info!(
+            "Node {} reports {} cached datasets and {} images",
+            node.node_id,
+            cache.datasets.len(),
+            cache.images.len()
+        );
+        node.cache = cache;
+    }
+    let cache = node.cache.clone();
+    save_nodes(&nodes);
+
+    HttpResponse::Ok().json(json!({
+        "nodeId": *node_id,
+        "cache": cache,
+    }))
+}
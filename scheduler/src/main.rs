@@ -1,34 +1,127 @@
+mod admin;
+mod allocation;
+mod amount;
+mod arrays;
+mod auth;
+mod chain;
+mod cli;
 mod config;
+mod dataset;
+mod erc20;
+mod events;
+mod fairshare;
+mod health;
 mod helper;
+mod history;
+mod images;
 mod jobs;
+mod leases;
+mod locality;
+mod metrics;
 mod models;
 mod nodes;
+mod pricing;
+mod queue;
+mod recurring;
 mod state;
+mod store;
+mod telemetry;
+mod usage;
+mod validation;
+mod workflow;
 
+use crate::cli::{Cli, Command};
+use crate::config::Settings;
 use crate::state::AppState;
+use crate::validation::ValidationErrors;
 use actix_cors::Cors;
+use actix_web::middleware::from_fn;
 use actix_web::{web, App, HttpResponse, HttpServer};
+use clap::Parser;
+use tracing::error;
+use tracing_actix_web::TracingLogger;
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
-    let state = AppState::new().await.map_err(|e| {
-        eprintln!("[FATAL] Could not load initial state: {}", e);
-        std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
+    // Lets `.env` supply SCHEDULER_URL and ADMIN_TOKEN to the subcommands.
+    dotenvy::dotenv().ok();
+    let cli = Cli::parse();
+    let (settings, errors) = Settings::load(cli.config.as_deref());
+    if cli.print_config {
+        print!("{}", settings.redacted_toml());
+        for e in errors.iter() {
+            eprintln!("invalid: {} {}", e.field, e.message);
+        }
+        std::process::exit(if errors.is_empty() { 0 } else { 1 });
+    }
+
+    match cli.command {
+        None | Some(Command::Serve) => serve(settings, errors).await,
+        Some(command) => {
+            telemetry::init_cli();
+            if let Err(e) = cli::run(command, cli.remote, &settings, &errors).await {
+                // Output piped into `head` and the like is cut short, not failed.
+                let closed = e
+                    .downcast_ref::<std::io::Error>()
+                    .is_some_and(|e| e.kind() == std::io::ErrorKind::BrokenPipe);
+                if !closed {
+                    eprintln!("error: {:#}", e);
+                    std::process::exit(1);
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+async fn serve(settings: Settings, errors: ValidationErrors) -> std::io::Result<()> {
+    let _telemetry = telemetry::init(&settings.logging);
+    if !errors.is_empty() {
+        for e in errors.iter() {
+            error!("Invalid configuration: {} {}", e.field, e.message);
+        }
+        return Err(std::io::Error::other("invalid configuration"));
+    }
+    config::init_storage(settings.storage.clone());
+
+    let state = AppState::new(&settings).await.map_err(|e| {
+        error!("Could not load initial state: {}", e);
+        std::io::Error::other(e.to_string())
     })?;
 
+    let bind = state.cfg.bind.clone();
+    let port = state.cfg.port;
+
+    tokio::spawn(leases::run_lease_reaper(web::Data::new(state.clone())));
+    tokio::spawn(chain::sync_all_nodes(web::Data::new(state.clone())));
+    tokio::spawn(recurring::run_schedule_timer(web::Data::new(state.clone())));
+
     HttpServer::new(move || {
         let cors = Cors::permissive();
         App::new()
+            .wrap(from_fn(metrics::track_requests))
+            .wrap(from_fn(auth::hash_signed_body))
+            .wrap(TracingLogger::default())
             .wrap(cors)
-            .app_data(web::Data::new(state.clone())) // This is now correct!
+            .app_data(web::Data::new(state.clone()))
             .configure(jobs::config)
             .configure(nodes::config)
+            .configure(events::config)
+            .configure(queue::config)
+            .configure(fairshare::config)
+            .configure(workflow::config)
+            .configure(recurring::config)
+            .configure(usage::config)
+            .configure(pricing::config)
+            .configure(metrics::config)
+            .configure(health::config)
+            .configure(admin::config)
             .route(
                 "/",
                 web::get().to(|| async { HttpResponse::Ok().body("Hello from Rust!") }),
             )
     })
-    .bind(("127.0.0.1", 3000))?
+    .bind((bind.as_str(), port))?
     .run()
     .await
 }
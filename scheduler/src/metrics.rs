@@ -0,0 +1,252 @@
+use crate::state::AppState;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::{web, Error, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec,
+    Opts, Registry, TextEncoder,
+};
+use std::collections::BTreeMap;
+use std::sync::{LockResult, Mutex, MutexGuard};
+use std::time::Instant;
+use tracing::error;
+
+/// Buckets for job waits and runtimes, from seconds up to a day.
+const JOB_BUCKETS: &[f64] = &[
+    1.0, 5.0, 15.0, 60.0, 300.0, 900.0, 3600.0, 4.0 * 3600.0, 12.0 * 3600.0, 24.0 * 3600.0,
+];
+/// Buckets for contract calls, which wait for a receipt.
+const CONTRACT_BUCKETS: &[f64] = &[0.1, 0.5, 1.0, 2.0, 5.0, 10.0, 30.0, 60.0, 120.0];
+const LOCK_BUCKETS: &[f64] = &[0.000_01, 0.000_1, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0];
+
+/// Everything `GET /metrics` exposes. Job and node gauges are filled from
+/// state at scrape time; the rest are recorded as things happen.
+pub struct Metrics {
+    registry: Registry,
+    jobs: IntGaugeVec,
+    nodes: IntGaugeVec,
+    job_wait: Histogram,
+    job_completion: Histogram,
+    contract_latency: HistogramVec,
+    contract_failures: IntCounterVec,
+    lock_wait: HistogramVec,
+    http_requests: HistogramVec,
+}
+
+fn histogram(name: &str, help: &str, buckets: &[f64]) -> HistogramOpts {
+    HistogramOpts::new(name, help).buckets(buckets.to_vec())
+}
+
+impl Metrics {
+    pub fn new() -> prometheus::Result<Self> {
+        let registry = Registry::new_custom(Some("scheduler".to_string()), None)?;
+        let metrics = Metrics {
+            jobs: IntGaugeVec::new(Opts::new("jobs", "Jobs by status"), &["status"])?,
+            nodes: IntGaugeVec::new(
+                Opts::new("nodes", "Nodes by state; inactive nodes count only as inactive"),
+                &["state"],
+            )?,
+            job_wait: Histogram::with_opts(histogram(
+                "job_wait_seconds",
+                "Time from submission to assignment",
+                JOB_BUCKETS,
+            ))?,
+            job_completion: Histogram::with_opts(histogram(
+                "job_completion_seconds",
+                "Time from submission to a successful result",
+                JOB_BUCKETS,
+            ))?,
+            contract_latency: HistogramVec::new(
+                histogram(
+                    "contract_call_seconds",
+                    "Contract transactions from send to receipt",
+                    CONTRACT_BUCKETS,
+                ),
+                &["method"],
+            )?,
+            contract_failures: IntCounterVec::new(
+                Opts::new(
+                    "contract_call_failures_total",
+                    "Contract transactions that failed to send, confirm or were reverted",
+                ),
+                &["method"],
+            )?,
+            lock_wait: HistogramVec::new(
+                histogram(
+                    "lock_wait_seconds",
+                    "Time spent waiting for an AppState lock",
+                    LOCK_BUCKETS,
+                ),
+                &["lock"],
+            )?,
+            http_requests: HistogramVec::new(
+                HistogramOpts::new("http_request_duration_seconds", "HTTP requests served"),
+                &["method", "route", "status"],
+            )?,
+            registry,
+        };
+        // Export the core methods at zero before their first call.
+        for method in ["assignProvider", "submitResult", "release"] {
+            metrics.contract_latency.with_label_values(&[method]);
+            metrics.contract_failures.with_label_values(&[method]);
+        }
+        metrics.registry.register(Box::new(metrics.jobs.clone()))?;
+        metrics.registry.register(Box::new(metrics.nodes.clone()))?;
+        metrics.registry.register(Box::new(metrics.job_wait.clone()))?;
+        metrics.registry.register(Box::new(metrics.job_completion.clone()))?;
+        metrics.registry.register(Box::new(metrics.contract_latency.clone()))?;
+        metrics.registry.register(Box::new(metrics.contract_failures.clone()))?;
+        metrics.registry.register(Box::new(metrics.lock_wait.clone()))?;
+        metrics.registry.register(Box::new(metrics.http_requests.clone()))?;
+        Ok(metrics)
+    }
+
+    /// Wraps one of the `AppState` collections so its lock waits are recorded.
+    pub fn timed_mutex<T>(&self, name: &str, value: T) -> TimedMutex<T> {
+        TimedMutex {
+            inner: Mutex::new(value),
+            wait: self.lock_wait.with_label_values(&[name]),
+        }
+    }
+
+    /// Starts timing a contract transaction; see `ContractCallTimer`.
+    pub fn contract_call(&self, method: &str) -> ContractCallTimer {
+        ContractCallTimer {
+            latency: self.contract_latency.with_label_values(&[method]),
+            failures: self.contract_failures.with_label_values(&[method]),
+            started: Instant::now(),
+            succeeded: false,
+        }
+    }
+
+    pub fn observe_job_wait(&self, created_at: &str) {
+        if let Some(secs) = seconds_since(created_at) {
+            self.job_wait.observe(secs);
+        }
+    }
+
+    pub fn observe_job_completion(&self, created_at: &str) {
+        if let Some(secs) = seconds_since(created_at) {
+            self.job_completion.observe(secs);
+        }
+    }
+
+    fn observe_request(&self, method: &str, route: &str, status: u16, secs: f64) {
+        self.http_requests
+            .with_label_values(&[method, route, &status.to_string()])
+            .observe(secs);
+    }
+}
+
+fn seconds_since(rfc3339: &str) -> Option<f64> {
+    let at = DateTime::parse_from_rfc3339(rfc3339).ok()?;
+    let elapsed = Utc::now() - at.with_timezone(&Utc);
+    Some(elapsed.num_milliseconds().max(0) as f64 / 1000.0)
+}
+
+/// A `Mutex` whose `lock` records how long callers waited for it.
+pub struct TimedMutex<T> {
+    inner: Mutex<T>,
+    wait: Histogram,
+}
+
+impl<T> TimedMutex<T> {
+    pub fn lock(&self) -> LockResult<MutexGuard<'_, T>> {
+        let started = Instant::now();
+        let guard = self.inner.lock();
+        self.wait.observe(started.elapsed().as_secs_f64());
+        guard
+    }
+}
+
+/// Records a contract transaction's latency when dropped, counting it as a
+/// failure unless `succeeded` was called first.
+pub struct ContractCallTimer {
+    latency: Histogram,
+    failures: IntCounter,
+    started: Instant,
+    succeeded: bool,
+}
+
+impl ContractCallTimer {
+    pub fn succeeded(mut self) {
+        self.succeeded = true;
+    }
+}
+
+impl Drop for ContractCallTimer {
+    fn drop(&mut self) {
+        self.latency.observe(self.started.elapsed().as_secs_f64());
+        if !self.succeeded {
+            self.failures.inc();
+        }
+    }
+}
+
+/// Middleware timing every request by method, matched route and status.
+pub async fn track_requests(
+    req: ServiceRequest,
+    next: actix_web::middleware::Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let started = Instant::now();
+    let method = req.method().to_string();
+    let metrics = req
+        .app_data::<web::Data<AppState>>()
+        .map(|data| data.metrics.clone());
+    let res = next.call(req).await?;
+    if let Some(metrics) = metrics {
+        let route = res
+            .request()
+            .match_pattern()
+            .unwrap_or_else(|| "unmatched".to_string());
+        metrics.observe_request(
+            &method,
+            &route,
+            res.status().as_u16(),
+            started.elapsed().as_secs_f64(),
+        );
+    }
+    Ok(res)
+}
+
+async fn get_metrics(data: web::Data<AppState>) -> impl Responder {
+    let metrics = &data.metrics;
+    {
+        let jobs = data.jobs.lock().unwrap();
+        let mut counts: BTreeMap<&str, i64> = BTreeMap::new();
+        for job in jobs.iter() {
+            *counts.entry(job.status.as_str()).or_default() += 1;
+        }
+        metrics.jobs.reset();
+        for (status, count) in counts {
+            metrics.jobs.with_label_values(&[status]).set(count);
+        }
+    }
+    {
+        let nodes = data.nodes.lock().unwrap();
+        let mut counts: BTreeMap<&str, i64> = BTreeMap::new();
+        for node in nodes.iter() {
+            let state = if node.active { node.status.as_str() } else { "inactive" };
+            *counts.entry(state).or_default() += 1;
+        }
+        metrics.nodes.reset();
+        for (state, count) in counts {
+            metrics.nodes.with_label_values(&[state]).set(count);
+        }
+    }
+
+    let mut buffer = Vec::new();
+    let encoder = TextEncoder::new();
+    if let Err(e) = encoder.encode(&metrics.registry.gather(), &mut buffer) {
+        error!("Failed to encode metrics: {}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::Ok()
+        .content_type(encoder.format_type())
+        .body(buffer)
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.route("/metrics", web::get().to(get_metrics));
+}
@@ -1,17 +1,33 @@
-use chrono::Utc;
+use crate::allocation;
+use crate::amount::{self, Token, TokenAmount};
+use crate::dataset::{self, DatasetRef};
+use crate::images::ImagePolicy;
+use crate::locality::{CacheReport, NodeCache};
+use crate::pricing;
+use crate::usage::Resources;
+use crate::validation::{self, ValidationErrors};
+use chrono::{DateTime, Utc};
+use ethers::types::Address;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Job {
     #[serde(rename = "jobId")]
     pub job_id: String,
     pub owner: String,
-    #[serde(rename = "dataset")]
-    pub dataset_cid: String,
+    /// None until a dataset fed by a parent job is resolved.
+    #[serde(
+        rename = "dataset",
+        default,
+        deserialize_with = "dataset::deserialize_stored"
+    )]
+    pub dataset: Option<DatasetRef>,
     #[serde(rename = "containerCID")]
     pub container_cid: String,
-    #[serde(deserialize_with = "string_or_float")]
-    pub bounty: f64,
+    /// Escrowed on-chain in exactly these base units.
+    #[serde(deserialize_with = "amount::deserialize_stored")]
+    pub bounty: TokenAmount,
     pub deadline: String,
     #[serde(rename = "requiredSpecs")]
     pub required_specs: String,
@@ -30,16 +46,106 @@ pub struct Job {
     pub created_at: String,
     #[serde(default)]
     pub completed: bool,
+    /// Whole GPUs requested; ignored when `gpu_memory` asks for a slice.
+    #[serde(rename = "gpuCount", default = "default_gpu_count")]
+    pub gpu_count: u32,
+    /// GB of a single shared GPU.
+    #[serde(rename = "gpuMemory", default, skip_serializing_if = "Option::is_none")]
+    pub gpu_memory: Option<u64>,
+    /// Set by the submitter, "urgent" only with the owner's signature;
+    /// otherwise derived from the bounty rate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<Priority>,
+    /// When the job was assigned to its node.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<DateTime<Utc>>,
+    /// When the job finished, successfully or not.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<DateTime<Utc>>,
+    /// Runtime multiplied by the share of GPUs the job held.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gpu_hours: Option<f64>,
+    #[serde(rename = "workflowId", default, skip_serializing_if = "Option::is_none")]
+    pub workflow_id: Option<String>,
+    /// Jobs that must complete before this one leaves the "blocked" status.
+    #[serde(
+        rename = "dependsOn",
+        alias = "depends_on",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub depends_on: Vec<String>,
+    /// Input name to the parent job whose result hash feeds it. Result hashes
+    /// are not fetchable, so no input may stand in for the dataset.
+    #[serde(
+        rename = "inputMap",
+        alias = "input_map",
+        default,
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub input_map: BTreeMap<String, String>,
+    /// `input_map` resolved to result hashes once the parents complete.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub inputs: BTreeMap<String, String>,
+    /// Failure reported by the node instead of a result.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(rename = "arrayId", default, skip_serializing_if = "Option::is_none")]
+    pub array_id: Option<String>,
+    #[serde(rename = "arrayIndex", default, skip_serializing_if = "Option::is_none")]
+    pub array_index: Option<usize>,
+    /// Parameter set of a job array element, passed through to the node.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub params: BTreeMap<String, serde_json::Value>,
+    /// Compressed image size in bytes, fetched at submission when an image
+    /// policy limits size.
+    #[serde(rename = "imageSize", default, skip_serializing_if = "Option::is_none")]
+    pub image_size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Resources::is_empty")]
+    pub limits: Resources,
+    /// Hours the submitter expects the job to run, as priced by `/quotes`.
+    #[serde(rename = "expectedHours", default, skip_serializing_if = "Option::is_none")]
+    pub expected_hours: Option<f64>,
+    /// Peaks reported by the node with the job's result.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Resources>,
+    /// Limits the job went over, as `field: used X of Y`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overruns: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+    Urgent,
+}
+
+impl std::str::FromStr for Priority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "low" => Ok(Priority::Low),
+            "normal" => Ok(Priority::Normal),
+            "high" => Ok(Priority::High),
+            "urgent" => Ok(Priority::Urgent),
+            _ => Err("must be one of low, normal, high, urgent".to_string()),
+        }
+    }
 }
 
 
 impl Job {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         job_id: String,
         owner: String,
-        dataset_cid: String,
+        dataset: Option<DatasetRef>,
         container_cid: String,
-        bounty: f64,
+        bounty: TokenAmount,
         deadline: String,
         required_specs: String,
         min_memory: u64,
@@ -47,7 +153,7 @@ impl Job {
         Self {
             job_id,
             owner,
-            dataset_cid,
+            dataset,
             container_cid,
             bounty,
             deadline,
@@ -60,7 +166,243 @@ impl Job {
             retries: 0,
             created_at: Utc::now().to_rfc3339(),
             completed: false,
+            gpu_count: 1,
+            gpu_memory: None,
+            priority: None,
+            started_at: None,
+            completed_at: None,
+            gpu_hours: None,
+            workflow_id: None,
+            depends_on: Vec::new(),
+            input_map: BTreeMap::new(),
+            inputs: BTreeMap::new(),
+            error: None,
+            array_id: None,
+            array_index: None,
+            params: BTreeMap::new(),
+            image_size: None,
+            limits: Resources::default(),
+            expected_hours: None,
+            usage: None,
+            overruns: Vec::new(),
+        }
+    }
+}
+
+/// Body accepted by `POST /jobs`. Status and bookkeeping fields are always
+/// set by the scheduler, so any the client sends are ignored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateJobRequest {
+    #[serde(rename = "jobId")]
+    pub job_id: Option<String>,
+    pub owner: Option<String>,
+    /// A typed dataset object or a legacy CID/URL string.
+    #[serde(default)]
+    pub dataset: Option<serde_json::Value>,
+    #[serde(rename = "containerCID")]
+    pub container_cid: Option<String>,
+    /// Whole tokens as a decimal string, in `bountyToken` when set and the
+    /// native currency otherwise.
+    pub bounty: Option<serde_json::Value>,
+    /// ERC-20 contract the bounty is escrowed in.
+    #[serde(rename = "bountyToken", default, skip_serializing_if = "Option::is_none")]
+    pub bounty_token: Option<String>,
+    /// `bountyToken` as read from the chain by `erc20::admit`; never taken
+    /// from the client.
+    #[serde(skip)]
+    pub token: Option<Token>,
+    pub deadline: Option<String>,
+    #[serde(rename = "requiredSpecs")]
+    pub required_specs: Option<String>,
+    #[serde(rename = "minMemory")]
+    pub min_memory: Option<i64>,
+    #[serde(rename = "gpuCount", default)]
+    pub gpu_count: Option<i64>,
+    #[serde(rename = "gpuMemory", default)]
+    pub gpu_memory: Option<i64>,
+    #[serde(default)]
+    pub priority: Option<String>,
+    #[serde(rename = "workflowId", default)]
+    pub workflow_id: Option<String>,
+    #[serde(rename = "dependsOn", alias = "depends_on", default)]
+    pub depends_on: Option<Vec<String>>,
+    #[serde(rename = "inputMap", alias = "input_map", default)]
+    pub input_map: Option<BTreeMap<String, String>>,
+    #[serde(default)]
+    pub limits: Option<Resources>,
+    /// Run time the bounty was priced for; nodes with a price floor need it,
+    /// or `limits.runtimeSecs`, to work out the rate offered.
+    #[serde(rename = "expectedHours", default, skip_serializing_if = "Option::is_none")]
+    pub expected_hours: Option<f64>,
+}
+
+impl CreateJobRequest {
+    pub fn into_job(self, existing: &[Job]) -> Result<Job, ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+
+        let job_id = errors.check("jobId", validation::required(&self.job_id));
+        if let Some(id) = job_id {
+            errors.check("jobId", validation::identifier(id));
+            if existing.iter().any(|j| j.job_id == id) {
+                errors.add("jobId", "already exists");
+            }
+        }
+
+        let owner = errors
+            .check("owner", validation::required(&self.owner))
+            .and_then(|o| errors.check("owner", validation::checksummed_address(o)));
+
+        let input_map = self.input_map.unwrap_or_default();
+        let dataset = match &self.dataset {
+            None | Some(serde_json::Value::Null) => {
+                errors.add("dataset", "is required");
+                None
+            }
+            Some(d) => errors.check("dataset", DatasetRef::from_request(d)),
+        };
+
+        let container = errors.check("containerCID", validation::required(&self.container_cid));
+        if let Some(c) = container {
+            errors.check("containerCID", validation::container_ref(c));
+        }
+
+        let token = match (&self.bounty_token, self.token) {
+            (None, _) => Some(Token::native()),
+            (Some(_), Some(token)) => Some(token),
+            (Some(_), None) => {
+                errors.add("bountyToken", "could not be read from the chain");
+                None
+            }
+        };
+        let bounty = match (&self.bounty, &token) {
+            (Some(b), Some(token)) => errors.check("bounty", TokenAmount::from_request(b, token)),
+            (Some(_), None) => None,
+            (None, _) => {
+                errors.add("bounty", "is required");
+                None
+            }
+        };
+
+        let deadline = errors.check("deadline", validation::required(&self.deadline));
+        if let Some(d) = deadline {
+            errors.check("deadline", validation::future_deadline(d));
+        }
+
+        let specs = errors.check("requiredSpecs", validation::required(&self.required_specs));
+        if let Some(s) = specs {
+            errors.check(
+                "requiredSpecs",
+                validation::bounded_text(s, validation::MAX_SPECS_LEN),
+            );
+        }
+
+        let min_memory = match self.min_memory {
+            Some(m) => errors.check("minMemory", validation::memory_gb(m)),
+            None => {
+                errors.add("minMemory", "is required");
+                None
+            }
+        };
+
+        let gpu_count = match self.gpu_count {
+            Some(n) if !(1..=validation::MAX_GPUS as i64).contains(&n) => {
+                errors.add(
+                    "gpuCount",
+                    format!("must be between 1 and {}", validation::MAX_GPUS),
+                );
+                None
+            }
+            Some(n) => Some(n as u32),
+            None => None,
+        };
+        let gpu_memory = self
+            .gpu_memory
+            .and_then(|m| errors.check("gpuMemory", validation::memory_gb(m)));
+        if gpu_memory.is_some() && gpu_count.is_some_and(|n| n > 1) {
+            errors.add("gpuMemory", "a memory slice cannot span several GPUs");
+        }
+        let limits = self.limits.unwrap_or_default();
+        if let Err(limit_errors) = limits.validate_limits() {
+            errors.extend(limit_errors.prefixed("limits"));
+        }
+        if let (Some(limit), Some(slice)) = (limits.gpu_memory_gb, gpu_memory) {
+            if limit > slice as f64 {
+                errors.add("limits.gpuMemoryGb", "cannot exceed the gpuMemory slice");
+            }
+        }
+        let expected_hours = self
+            .expected_hours
+            .and_then(|h| errors.check("expectedHours", pricing::check_hours(h)));
+        let priority = self
+            .priority
+            .as_deref()
+            .and_then(|p| errors.check("priority", p.parse::<Priority>()));
+
+        let workflow_id = self.workflow_id.map(|w| w.trim().to_string());
+        if let Some(w) = &workflow_id {
+            errors.check("workflowId", validation::identifier(w));
+        }
+
+        let depends_on = self.depends_on.unwrap_or_default();
+        if depends_on.len() > validation::MAX_DEPENDENCIES {
+            errors.add(
+                "dependsOn",
+                format!("must list at most {} jobs", validation::MAX_DEPENDENCIES),
+            );
+        }
+        for (i, parent_id) in depends_on.iter().enumerate() {
+            let field = format!("dependsOn[{}]", i);
+            if job_id == Some(parent_id.as_str()) {
+                errors.add(&field, "a job cannot depend on itself");
+            } else if depends_on[..i].contains(parent_id) {
+                errors.add(&field, "is listed twice");
+            } else {
+                match existing.iter().find(|j| &j.job_id == parent_id) {
+                    None => errors.add(&field, format!("unknown job {}", parent_id)),
+                    Some(p) if matches!(p.status.as_str(), "failed" | "cancelled") => {
+                        errors.add(&field, format!("job {} has {}", parent_id, p.status))
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+        for (input, parent_id) in &input_map {
+            let field = format!("inputMap.{}", input);
+            errors.check(&field, validation::identifier(input));
+            if !depends_on.contains(parent_id) {
+                errors.add(&field, "must reference a job in dependsOn");
+            }
+            if input == "dataset" {
+                errors.add(
+                    &field,
+                    "is not supported: results are content hashes, not fetchable datasets",
+                );
+            }
         }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let mut job = Job::new(
+            job_id.unwrap().to_string(),
+            owner.unwrap(),
+            dataset,
+            container.unwrap().to_string(),
+            bounty.unwrap(),
+            deadline.unwrap().to_string(),
+            specs.unwrap().to_string(),
+            min_memory.unwrap(),
+        );
+        job.gpu_count = gpu_count.unwrap_or(1);
+        job.gpu_memory = gpu_memory;
+        job.limits = limits;
+        job.expected_hours = expected_hours;
+        job.priority = priority;
+        job.workflow_id = workflow_id;
+        job.depends_on = depends_on;
+        job.input_map = input_map;
+        Ok(job)
     }
 }
 
@@ -77,25 +419,504 @@ pub struct Node {
     #[serde(rename = "memoryAvailable")]
     pub memory: u64,
     #[serde(default)]
-    pub status: String, // "idle", "busy"
+    pub status: String, // "idle", "partial", "busy"
     #[serde(default)]
     pub active: bool,
+    #[serde(default)]
+    pub devices: Vec<GpuDevice>,
+    #[serde(default)]
+    pub allocations: Vec<GpuAllocation>,
+    /// Last comparison with the owner's `JobRegistry.providers` entry.
+    #[serde(default)]
+    pub chain: Option<ChainRegistration>,
+    /// The owner's restrictions on the images this node runs.
+    #[serde(rename = "imagePolicy", default, skip_serializing_if = "Option::is_none")]
+    pub image_policy: Option<ImagePolicy>,
+    #[serde(default, skip_serializing_if = "NodeCache::is_empty")]
+    pub cache: NodeCache,
+    /// Lowest native-currency bounty per GPU-hour the owner accepts; cheaper
+    /// jobs go elsewhere.
+    #[serde(rename = "minPricePerGpuHour", default, skip_serializing_if = "Option::is_none")]
+    pub min_price_per_gpu_hour: Option<f64>,
+    /// Floors for token bounties, in whole tokens per GPU-hour, keyed by
+    /// ERC-20 contract. See `pricing::node_accepts_price`.
+    #[serde(rename = "tokenPriceFloors", default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub token_price_floors: BTreeMap<Address, f64>,
+}
+
+impl Node {
+    /// `claimJob` reverts for providers without an active on-chain entry.
+    pub fn is_chain_registered(&self) -> bool {
+        self.chain.as_ref().is_some_and(|c| c.registered)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GpuDevice {
+    pub index: u32,
+    #[serde(default)]
+    pub name: String,
+    /// Device memory in GB.
+    pub memory: u64,
+}
+
+/// Memory on one device reserved for a job; whole-GPU jobs reserve all of it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GpuAllocation {
+    pub job_id: String,
+    pub device: u32,
+    pub memory: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainRegistration {
+    pub registered: bool,
+    /// Fields whose value differs between `nodes.json` and the contract.
+    #[serde(default)]
+    pub drift: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub checked_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lease {
+    pub lease_id: String,
+    pub job_id: String,
+    pub node_id: String,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    #[serde(default)]
+    pub renewals: u32,
+}
+
+/// Body accepted by `POST /nodes/register`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RegisterNodeRequest {
+    #[serde(rename = "nodeId", default)]
+    pub node_id: Option<String>,
+    #[serde(rename = "gpuName", default)]
+    pub gpu_name: Option<String>,
+    #[serde(rename = "gpuSpecs")]
+    pub gpu_specs: Option<String>,
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(rename = "memoryAvailable")]
+    pub memory: Option<i64>,
+    /// Per-GPU details; when omitted the node is a single GPU with
+    /// `memoryAvailable` GB.
+    #[serde(default)]
+    pub devices: Option<Vec<DeviceRequest>>,
+    #[serde(rename = "imagePolicy", default)]
+    pub image_policy: Option<ImagePolicy>,
+    /// Datasets and images already on the node; replaces any earlier report.
+    #[serde(default)]
+    pub cache: Option<CacheReport>,
+    #[serde(rename = "minPricePerGpuHour", default)]
+    pub min_price_per_gpu_hour: Option<f64>,
+    #[serde(rename = "tokenPriceFloors", default)]
+    pub token_price_floors: Option<BTreeMap<Address, f64>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeviceRequest {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub memory: Option<i64>,
+}
+
+/// Body accepted by `PATCH /nodes/{id}`; absent fields are left unchanged.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct NodeUpdate {
+    #[serde(rename = "gpuName", default)]
+    pub gpu_name: Option<String>,
+    #[serde(rename = "gpuSpecs", default)]
+    pub gpu_specs: Option<String>,
+    #[serde(rename = "memoryAvailable", default)]
+    pub memory: Option<i64>,
+    #[serde(default)]
+    pub active: Option<bool>,
+    /// Replaces the node's image policy; an empty policy removes it.
+    #[serde(rename = "imagePolicy", default)]
+    pub image_policy: Option<ImagePolicy>,
+    /// Replaces the node's price floor; 0 removes it.
+    #[serde(rename = "minPricePerGpuHour", default)]
+    pub min_price_per_gpu_hour: Option<f64>,
+    /// Replaces the node's token floors; an empty map removes them.
+    #[serde(rename = "tokenPriceFloors", default)]
+    pub token_price_floors: Option<BTreeMap<Address, f64>>,
+}
+
+fn validate_gpu_name(errors: &mut ValidationErrors, name: &Option<String>) -> Option<String> {
+    let name = name.as_deref().map(str::trim).filter(|n| !n.is_empty())?;
+    errors.check(
+        "gpuName",
+        validation::bounded_text(name, validation::MAX_SPECS_LEN),
+    )?;
+    Some(name.to_string())
+}
+
+/// A floor of 0 is the same as none.
+fn validate_min_price(errors: &mut ValidationErrors, price: Option<f64>) -> Option<Option<f64>> {
+    let price = price?;
+    if !price.is_finite() || price < 0.0 {
+        errors.add("minPricePerGpuHour", "must be a non-negative number");
+        return None;
+    }
+    Some(Some(price).filter(|p| *p > 0.0))
+}
+
+/// Token floors must be positive; a token without a floor is left out.
+fn validate_token_floors(
+    errors: &mut ValidationErrors,
+    floors: Option<BTreeMap<Address, f64>>,
+) -> Option<BTreeMap<Address, f64>> {
+    let floors = floors?;
+    for (token, floor) in &floors {
+        if !floor.is_finite() || *floor <= 0.0 {
+            errors.add(
+                &format!("tokenPriceFloors.{:?}", token),
+                "must be a positive number",
+            );
+        }
+    }
+    Some(floors)
+}
+
+fn validate_gpu_specs(errors: &mut ValidationErrors, specs: &str) -> Option<String> {
+    errors.check(
+        "gpuSpecs",
+        validation::bounded_text(specs, validation::MAX_SPECS_LEN),
+    )?;
+    Some(specs.to_string())
 }
 
-fn string_or_float<'de, D>(deserializer: D) -> Result<f64, D::Error>
-where
-    D: serde::Deserializer<'de>,
-{
-    let v: serde_json::Value = Deserialize::deserialize(deserializer)?;
-    match v {
-        serde_json::Value::Number(num) => num
-            .as_f64()
-            .ok_or_else(|| serde::de::Error::custom("invalid number")),
-        serde_json::Value::String(s) => s.parse::<f64>().map_err(serde::de::Error::custom),
-        _ => Err(serde::de::Error::custom("expected float or string")),
+impl RegisterNodeRequest {
+    /// Validates the request and builds the node it describes. The id is left
+    /// empty when the client did not supply one.
+    pub fn into_node(self) -> Result<Node, ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+
+        let node_id = self
+            .node_id
+            .as_deref()
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(str::to_string);
+        if let Some(id) = &node_id {
+            errors.check("nodeId", validation::identifier(id));
+        }
+
+        let gpu_name = validate_gpu_name(&mut errors, &self.gpu_name);
+        let gpu_specs = errors
+            .check("gpuSpecs", validation::required(&self.gpu_specs))
+            .and_then(|s| validate_gpu_specs(&mut errors, s));
+        let devices = self.devices.as_deref().map(|devices| {
+            if devices.is_empty() || devices.len() > validation::MAX_GPUS {
+                errors.add(
+                    "devices",
+                    format!("must list between 1 and {} GPUs", validation::MAX_GPUS),
+                );
+            }
+            devices
+                .iter()
+                .enumerate()
+                .filter_map(|(i, d)| {
+                    let field = format!("devices[{}].memory", i);
+                    let memory = match d.memory {
+                        Some(m) => errors.check(&field, validation::memory_gb(m))?,
+                        None => {
+                            errors.add(&field, "is required");
+                            return None;
+                        }
+                    };
+                    let name = d.name.as_deref().map(str::trim).unwrap_or_default();
+                    errors.check(
+                        &format!("devices[{}].name", i),
+                        validation::bounded_text(name, validation::MAX_SPECS_LEN),
+                    )?;
+                    Some(GpuDevice {
+                        index: i as u32,
+                        name: name.to_string(),
+                        memory,
+                    })
+                })
+                .collect::<Vec<_>>()
+        });
+        let memory = match (self.memory, &devices) {
+            (Some(m), _) => errors.check("memoryAvailable", validation::memory_gb(m)),
+            (None, Some(devices)) => Some(devices.iter().map(|d| d.memory).sum()),
+            (None, None) => {
+                errors.add("memoryAvailable", "is required");
+                None
+            }
+        };
+        let owner = match self.owner.as_deref().map(str::trim).filter(|o| !o.is_empty()) {
+            Some(o) => errors.check("owner", validation::checksummed_address(o)),
+            None => None,
+        };
+        if let Some(policy) = &self.image_policy {
+            errors.check("imagePolicy", policy.validate());
+        }
+        let min_price = validate_min_price(&mut errors, self.min_price_per_gpu_hour);
+        let token_floors = validate_token_floors(&mut errors, self.token_price_floors);
+        let cache = match self.cache.map(CacheReport::into_cache).transpose() {
+            Ok(cache) => cache,
+            Err(cache_errors) => {
+                errors.extend(cache_errors);
+                None
+            }
+        };
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let mut node = Node {
+            node_id: node_id.unwrap_or_default(),
+            gpu_name,
+            gpu_specs: gpu_specs.unwrap(),
+            owner,
+            memory: memory.unwrap(),
+            status: "idle".to_string(),
+            active: true,
+            devices: devices.unwrap_or_default(),
+            allocations: Vec::new(),
+            chain: None,
+            image_policy: self.image_policy.filter(|p| *p != ImagePolicy::default()),
+            cache: cache.unwrap_or_default(),
+            min_price_per_gpu_hour: min_price.flatten(),
+            token_price_floors: token_floors.unwrap_or_default(),
+        };
+        allocation::ensure_devices(&mut node);
+        Ok(node)
+    }
+}
+
+impl NodeUpdate {
+    pub fn apply(self, node: &mut Node) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+
+        let gpu_name = validate_gpu_name(&mut errors, &self.gpu_name);
+        let gpu_specs = match self.gpu_specs.as_deref().map(str::trim) {
+            Some("") => {
+                errors.add("gpuSpecs", "must not be empty");
+                None
+            }
+            Some(s) => validate_gpu_specs(&mut errors, s),
+            None => None,
+        };
+        let memory = self
+            .memory
+            .and_then(|m| errors.check("memoryAvailable", validation::memory_gb(m)));
+        if let Some(policy) = &self.image_policy {
+            errors.check("imagePolicy", policy.validate());
+        }
+        let min_price = validate_min_price(&mut errors, self.min_price_per_gpu_hour);
+        let token_floors = validate_token_floors(&mut errors, self.token_price_floors);
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        if gpu_name.is_some() {
+            node.gpu_name = gpu_name;
+        }
+        if let Some(specs) = gpu_specs {
+            node.gpu_specs = specs;
+        }
+        if let Some(memory) = memory {
+            node.memory = memory;
+        }
+        if let Some(active) = self.active {
+            node.active = active;
+        }
+        if let Some(policy) = self.image_policy {
+            node.image_policy = Some(policy).filter(|p| *p != ImagePolicy::default());
+        }
+        if let Some(price) = min_price {
+            node.min_price_per_gpu_hour = price;
+        }
+        if let Some(floors) = token_floors {
+            node.token_price_floors = floors;
+        }
+        Ok(())
     }
 }
 
+fn default_gpu_count() -> u32 {
+    1
+}
+
 fn default_created_at() -> String {
     Utc::now().to_rfc3339()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::U256;
+    use serde_json::json;
+
+    const OWNER: &str = "0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266";
+
+    fn request(overrides: serde_json::Value) -> CreateJobRequest {
+        let mut body = json!({
+            "jobId": "job-1",
+            "owner": OWNER,
+            "dataset": "QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG",
+            "containerCID": "ghcr.io/acme/train:1.0",
+            "bounty": "1.5",
+            "deadline": "2999-01-01",
+            "requiredSpecs": "RTX 4090",
+            "minMemory": 16,
+        });
+        for (key, value) in overrides.as_object().unwrap() {
+            body[key] = value.clone();
+        }
+        serde_json::from_value(body).unwrap()
+    }
+
+    fn error_fields(request: CreateJobRequest, existing: &[Job]) -> Vec<String> {
+        request
+            .into_job(existing)
+            .unwrap_err()
+            .iter()
+            .map(|e| e.field.clone())
+            .collect()
+    }
+
+    fn existing(job_id: &str, status: &str) -> Job {
+        let mut job = request(json!({ "jobId": job_id })).into_job(&[]).unwrap();
+        job.status = status.to_string();
+        job
+    }
+
+    #[test]
+    fn valid_request_becomes_a_pending_job() {
+        let job = request(json!({})).into_job(&[]).unwrap();
+        assert_eq!(job.status, "pending");
+        assert_eq!(job.owner, "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266");
+        assert_eq!(job.bounty.units, U256::from(15u64) * U256::exp10(17));
+        assert!(job.bounty.token.is_native());
+        assert_eq!(job.gpu_count, 1);
+        assert!(job.assigned_node.is_none());
+    }
+
+    #[test]
+    fn every_missing_field_is_reported() {
+        let empty: CreateJobRequest = serde_json::from_value(json!({})).unwrap();
+        assert_eq!(
+            error_fields(empty, &[]),
+            vec![
+                "jobId",
+                "owner",
+                "dataset",
+                "containerCID",
+                "bounty",
+                "deadline",
+                "requiredSpecs",
+                "minMemory"
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_values() {
+        let fields = error_fields(
+            request(json!({
+                "jobId": "job 1",
+                "owner": "0xF39fd6e51aad88f6f4ce6ab8827279cfffb92266",
+                "containerCID": "Not An Image",
+                "bounty": "0",
+                "deadline": "2000-01-01",
+                "minMemory": 0,
+                "gpuCount": 17,
+                "priority": "asap",
+            })),
+            &[],
+        );
+        assert_eq!(
+            fields,
+            vec![
+                "jobId",
+                "owner",
+                "containerCID",
+                "bounty",
+                "deadline",
+                "minMemory",
+                "gpuCount",
+                "priority"
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_a_duplicate_job_id() {
+        let fields = error_fields(request(json!({})), &[existing("job-1", "pending")]);
+        assert_eq!(fields, vec!["jobId"]);
+    }
+
+    #[test]
+    fn memory_slice_cannot_span_gpus() {
+        let fields = error_fields(request(json!({ "gpuCount": 2, "gpuMemory": 8 })), &[]);
+        assert_eq!(fields, vec!["gpuMemory"]);
+
+        let job = request(json!({ "gpuMemory": 8 })).into_job(&[]).unwrap();
+        assert_eq!(job.gpu_memory, Some(8));
+    }
+
+    #[test]
+    fn token_bounty_needs_the_token_read_from_chain() {
+        let token = "0x5FbDB2315678afecb367f032d93F642f64180aa3";
+        let fields = error_fields(request(json!({ "bountyToken": token })), &[]);
+        assert_eq!(fields, vec!["bountyToken"]);
+
+        let mut read = request(json!({ "bountyToken": token, "bounty": "2.5" }));
+        read.token = Some(Token {
+            symbol: "USDC".to_string(),
+            decimals: 6,
+            address: Some(token.parse().unwrap()),
+        });
+        let job = read.into_job(&[]).unwrap();
+        assert_eq!(job.bounty.units, U256::from(2_500_000u64));
+    }
+
+    #[test]
+    fn checks_each_dependency() {
+        let jobs = [existing("parent", "completed"), existing("dead", "failed")];
+        let fields = error_fields(
+            request(json!({
+                "dependsOn": ["parent", "job-1", "parent", "missing", "dead"],
+            })),
+            &jobs,
+        );
+        assert_eq!(
+            fields,
+            vec![
+                "dependsOn[1]",
+                "dependsOn[2]",
+                "dependsOn[3]",
+                "dependsOn[4]"
+            ]
+        );
+
+        let job = request(json!({ "dependsOn": ["parent"] }))
+            .into_job(&jobs)
+            .unwrap();
+        assert_eq!(job.depends_on, vec!["parent"]);
+    }
+
+    #[test]
+    fn input_map_must_name_a_parent_and_not_the_dataset() {
+        let jobs = [existing("parent", "completed")];
+        let fields = error_fields(
+            request(json!({
+                "dependsOn": ["parent"],
+                "inputMap": { "weights": "other", "dataset": "parent" },
+            })),
+            &jobs,
+        );
+        assert_eq!(fields, vec!["inputMap.dataset", "inputMap.weights"]);
+    }
+}
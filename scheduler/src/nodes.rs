@@ -1,23 +1,37 @@
-use crate::config::YourContractError;
+use crate::allocation::{self, get_node_capacity};
+use crate::auth::verify_owner;
+use crate::fairshare::FairShare;
+use crate::chain::{get_node_chain_status, sync_node};
+use crate::amount::TokenAmount;
+use crate::config::{storage, YourContractError};
+use crate::erc20;
 use crate::helper::save_jobs;
-use crate::models::{Job, Node};
+use crate::history::{JobEvent, JobEventKind};
+use crate::images;
+use crate::locality::{self, node_heartbeat};
+use crate::leases::{acquire_lease, release_job_leases, renew_lease, unclaim_job};
+use crate::models::{Job, Node, NodeUpdate, RegisterNodeRequest};
+use crate::pricing;
 use crate::state::AppState;
-use actix_web;
-use actix_web::{web, HttpResponse, Responder};
+use crate::usage::{self, LimitEnforcement, Resources};
+use crate::validation::ValidationErrors;
+use crate::workflow;
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
 use ethers::abi::AbiDecode;
 use ethers::contract::ContractError;
-use ethers::types::{Address, H256};
+use ethers::types::{Address, TransactionReceipt, H256};
 use ethers_contract::AbiError;
-use log::{error, info};
 use serde::Deserialize;
 use serde_json::json;
 use std::fs::{self, OpenOptions};
 use std::io::Write;
 use std::str::FromStr;
+use tracing::{error, field, info, instrument, warn, Span};
 use uuid::Uuid;
 
-fn save_nodes(nodes: &Vec<Node>) {
-    let file_path = "nodes.json";
+pub fn save_nodes(nodes: &[Node]) {
+    let file_path = storage().nodes_path();
     let mut file = OpenOptions::new()
         .write(true)
         .create(true)
@@ -28,23 +42,212 @@ fn save_nodes(nodes: &Vec<Node>) {
     file.write_all(json.as_bytes()).unwrap();
 }
 
-pub async fn register_node(node: web::Json<Node>, data: web::Data<AppState>) -> impl Responder {
-    let mut nodes = data.nodes.lock().unwrap();
-    let mut new_node = node.into_inner();
+fn same_owner(a: &Option<String>, b: &Option<String>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a.eq_ignore_ascii_case(b),
+        _ => false,
+    }
+}
+
+/// Statuses in which a job still occupies the node it was given to.
+pub fn holds_node(job: &Job) -> bool {
+    matches!(job.status.as_str(), "assigning" | "assigned" | "running")
+}
+
+pub async fn register_node(
+    http_req: HttpRequest,
+    req: web::Json<RegisterNodeRequest>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let incoming = match req.into_inner().into_node() {
+        Ok(node) => node,
+        Err(errors) => {
+            warn!("Rejected node registration: validation failed");
+            return errors.into_response();
+        }
+    };
+
+    let (node_id, created) = {
+        let mut nodes = data.nodes.lock().unwrap();
+
+        // Upsert on the node id, or on the owner's node with the same GPU name
+        // when the client did not pick an id.
+        let existing = nodes.iter().position(|n| {
+            if incoming.node_id.is_empty() {
+                same_owner(&n.owner, &incoming.owner) && n.gpu_name == incoming.gpu_name
+            } else {
+                n.node_id == incoming.node_id
+            }
+        });
+
+        match existing {
+            Some(idx) => {
+                let node = &mut nodes[idx];
+                if let Some(current_owner) = node.owner.clone() {
+                    if !same_owner(&node.owner, &incoming.owner) {
+                        warn!(
+                            "Node {} is registered to a different owner",
+                            node.node_id
+                        );
+                        return HttpResponse::Conflict()
+                            .json(json!({ "error": "Node is registered to a different owner" }));
+                    }
+                    if let Err(resp) = verify_owner(&http_req, &current_owner) {
+                        return *resp;
+                    }
+                } else if let Some(claimed) = &incoming.owner {
+                    // Claiming an ownerless node needs the claimant's signature.
+                    if let Err(resp) = verify_owner(&http_req, claimed) {
+                        return *resp;
+                    }
+                }
+
+                if node.devices != incoming.devices && !node.allocations.is_empty() {
+                    warn!(
+                        "Refusing to change devices of node {} while jobs hold them",
+                        node.node_id
+                    );
+                    return HttpResponse::Conflict()
+                        .json(json!({ "error": "Node devices are allocated to running jobs" }));
+                }
 
-    // Generate server-side node ID if not provided
-    if new_node.node_id.is_empty() {
-        new_node.node_id = Uuid::new_v4().to_string();
+                node.gpu_name = incoming.gpu_name;
+                node.gpu_specs = incoming.gpu_specs;
+                node.memory = incoming.memory;
+                node.devices = incoming.devices;
+                node.image_policy = incoming.image_policy.or(node.image_policy.take());
+                node.min_price_per_gpu_hour = incoming
+                    .min_price_per_gpu_hour
+                    .or(node.min_price_per_gpu_hour.take());
+                if !incoming.token_price_floors.is_empty() {
+                    node.token_price_floors = incoming.token_price_floors;
+                }
+                if !incoming.cache.is_empty() {
+                    node.cache = incoming.cache;
+                }
+                allocation::refresh_status(node);
+                node.owner = incoming.owner.or(node.owner.take());
+                node.active = true;
+                let node_id = node.node_id.clone();
+
+                save_nodes(&nodes);
+                info!("Updated registration for node {}", node_id);
+                (node_id, false)
+            }
+            None => {
+                // The first registration binds the node to its owner, so it
+                // must come from that owner.
+                if let Some(owner) = &incoming.owner {
+                    if let Err(resp) = verify_owner(&http_req, owner) {
+                        return *resp;
+                    }
+                }
+                let mut new_node = incoming;
+                // Generate server-side node ID if not provided
+                if new_node.node_id.is_empty() {
+                    new_node.node_id = Uuid::new_v4().to_string();
+                }
+
+                let node_id = new_node.node_id.clone();
+                nodes.push(new_node);
+                save_nodes(&nodes);
+                info!("Registered node {}", node_id);
+                (node_id, true)
+            }
+        }
+    };
+
+    // Compare with (or create) the on-chain provider entry before the node
+    // is offered any work; the result is published with the node.
+    let node = match sync_node(&data, &node_id).await {
+        Some(node) => node,
+        None => return HttpResponse::NotFound().json(json!({ "error": "Node not found" })),
+    };
+    if created {
+        HttpResponse::Created().json(node)
+    } else {
+        HttpResponse::Ok().json(node)
     }
+}
+
+pub async fn update_node(
+    http_req: HttpRequest,
+    node_id: web::Path<String>,
+    req: web::Json<NodeUpdate>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let node_id = node_id.into_inner();
+    let mut nodes = data.nodes.lock().unwrap();
 
-    new_node.status = "idle".to_string();
-    new_node.active = true;
+    let node = match nodes.iter_mut().find(|n| n.node_id == node_id) {
+        Some(n) => n,
+        None => return HttpResponse::NotFound().json(json!({ "error": "Node not found" })),
+    };
+    let owner = match node.owner.clone() {
+        Some(o) => o,
+        None => {
+            return HttpResponse::Forbidden()
+                .json(json!({ "error": "Node has no owner to authenticate" }))
+        }
+    };
+    if let Err(resp) = verify_owner(&http_req, &owner) {
+        return *resp;
+    }
 
-    nodes.push(new_node.clone());
+    if let Err(errors) = req.into_inner().apply(node) {
+        return errors.into_response();
+    }
+    let updated = node.clone();
 
     save_nodes(&nodes);
+    data.events.publish_node(&updated);
+    info!("Node {} updated by owner", node_id);
+    HttpResponse::Ok().json(updated)
+}
+
+pub async fn delete_node(
+    http_req: HttpRequest,
+    node_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let node_id = node_id.into_inner();
+    let jobs = data.jobs.lock().unwrap();
+    let mut nodes = data.nodes.lock().unwrap();
+
+    let idx = match nodes.iter().position(|n| n.node_id == node_id) {
+        Some(i) => i,
+        None => return HttpResponse::NotFound().json(json!({ "error": "Node not found" })),
+    };
+    let owner = match nodes[idx].owner.clone() {
+        Some(o) => o,
+        None => {
+            return HttpResponse::Forbidden()
+                .json(json!({ "error": "Node has no owner to authenticate" }))
+        }
+    };
+    if let Err(resp) = verify_owner(&http_req, &owner) {
+        return *resp;
+    }
 
-    HttpResponse::Ok().json(new_node)
+    if let Some(job) = jobs
+        .iter()
+        .find(|j| j.assigned_node.as_deref() == Some(node_id.as_str()) && holds_node(j))
+    {
+        warn!(
+            "Refusing to remove node {} while it holds job {}",
+            node_id, job.job_id
+        );
+        return HttpResponse::Conflict().json(json!({
+            "error": "Node holds an assigned job",
+            "job_id": job.job_id,
+        }));
+    }
+
+    let removed = nodes.remove(idx);
+    save_nodes(&nodes);
+    data.events.publish_node_removed(&removed);
+    info!("Node {} removed by owner", node_id);
+    HttpResponse::Ok().json(json!({ "success": true, "node": removed }))
 }
 
 pub async fn get_all_nodes(data: web::Data<AppState>) -> impl Responder {
@@ -57,11 +260,11 @@ pub async fn get_node_jobs(
     data: web::Data<AppState>,
 ) -> impl Responder {
     let node_id = node_id.into_inner();
-    println!("Received request for node_id: {}", node_id);
+    info!("Received request for node_id: {}", node_id);
 
-    let file_content = match fs::read_to_string("nodes.json") {
+    let file_content = match fs::read_to_string(storage().nodes_path()) {
         Ok(content) => {
-            println!("Successfully read nodes.json");
+            info!("Successfully read nodes.json");
             content
         }
         Err(err) => {
@@ -72,7 +275,7 @@ pub async fn get_node_jobs(
     };
     let nodes: Vec<Node> = match serde_json::from_str::<Vec<Node>>(&file_content) {
         Ok(nodes) => {
-            println!("Parsed {} nodes from nodes.json", nodes.len());
+            info!("Parsed {} nodes from nodes.json", nodes.len());
             nodes
         }
         Err(err) => {
@@ -84,46 +287,69 @@ pub async fn get_node_jobs(
 
     let node = nodes.iter().find(|n| n.node_id == node_id);
     if node.is_none() {
-        println!("Node not found or inactive: {}", node_id);
+        info!("Node not found or inactive: {}", node_id);
         return HttpResponse::NotFound().json(json!({ "error": "Node not found or inactive" }));
     }
     let node = node.unwrap();
-    println!(
+    info!(
         "Found active node: {} (specs: {})",
         node.node_id, node.gpu_specs
     );
 
     let jobs = data.jobs.lock().unwrap();
-    println!("Loaded {} jobs from in-memory state", jobs.len());
+    let leases = data.leases.lock().unwrap();
+    info!("Loaded {} jobs from in-memory state", jobs.len());
 
-    let assigned_jobs: Vec<Job> = jobs
+    // Work reaches a node only through a lease, so these are the jobs it is
+    // currently running.
+    let leased_jobs: Vec<Job> = jobs
         .iter()
-        .filter(|j| j.required_specs == node.gpu_specs)
+        .filter(|j| {
+            leases
+                .iter()
+                .any(|l| l.job_id == j.job_id && l.node_id == node.node_id)
+        })
         .cloned()
         .collect();
 
-    println!(
-        "Returning {} assigned jobs for node {}",
-        assigned_jobs.len(),
+    info!(
+        "Returning {} leased jobs for node {}",
+        leased_jobs.len(),
         node.node_id
     );
 
-    HttpResponse::Ok().json(assigned_jobs)
+    HttpResponse::Ok().json(leased_jobs)
 }
 
-fn select_node_for_job(job: &Job, nodes: &Vec<Node>) -> Option<Node> {
-    nodes
+/// Among active, chain-registered nodes whose specs, image policy, price
+/// floor and free GPUs admit the job, the one `locality::best_node` ranks
+/// first: a tight fit, weighed against the datasets and images it has cached.
+fn select_node_for_job(job: &Job, nodes: &[Node], locality_weight: f64) -> Option<Node> {
+    let candidates = nodes
         .iter()
-        .find(|n| n.active && n.status == "idle" && n.gpu_specs == job.required_specs)
-        .cloned()
+        .filter(|n| {
+            n.active
+                && n.gpu_specs == job.required_specs
+                && n.is_chain_registered()
+                && images::node_accepts(n, job)
+                && pricing::node_accepts_price(n, job)
+                && allocation::fits(n, job)
+        })
+        .collect();
+    locality::best_node(job, candidates, locality_weight).cloned()
 }
 
 #[derive(Deserialize)]
 pub struct AssignRequest {
     pub job_id: String,
     pub address: Option<String>, 
+    /// The node that will run the job. Agents send their own id so the job
+    /// lands where they execute it; without it any eligible node is chosen.
+    #[serde(default)]
+    pub node_id: Option<String>,
 }
 
+#[instrument(skip_all, fields(job_id = %req.job_id, node_id = field::Empty))]
 pub async fn assign_provider(
     req: web::Json<AssignRequest>,
     data: web::Data<AppState>,
@@ -135,36 +361,53 @@ pub async fn assign_provider(
 
     let wallet_address = match validate_wallet(&req) {
         Ok(addr) => addr,
-        Err(resp) => return resp,
+        Err(resp) => return *resp,
     };
 
     let job_id = req.job_id.clone();
 
-    let selected_node_opt = match find_and_select_node(&data, &job_id) {
+    let selected_node_opt = match find_and_select_node(&data, &job_id, req.node_id.as_deref()) {
         Ok(opt) => opt,
-        Err(resp) => return resp,
+        Err(resp) => return *resp,
     };
+    data.history.record(JobEvent::new(
+        &job_id,
+        JobEventKind::AssignmentAttempted,
+        format!("Provider {:?} requested assignment", wallet_address),
+    ));
 
     if let Some(selected_node) = selected_node_opt {
+        Span::current().record("node_id", selected_node.node_id.as_str());
         if let Err(resp) = assign_on_chain(&data, &job_id, wallet_address).await {
+            unclaim_job(&data, &job_id, "On-chain assignment failed");
             return resp;
         }
 
-        let response_payload =
-            update_job_and_node_state(&data, &job_id, wallet_address, &selected_node);
-        HttpResponse::Ok().json(response_payload)
+        let reason = format!(
+            "Best-placed active node matching specs '{}'",
+            selected_node.gpu_specs
+        );
+        match update_job_and_node_state(&data, &job_id, wallet_address, &selected_node, &reason) {
+            Ok(payload) => HttpResponse::Ok().json(payload),
+            Err(e) => HttpResponse::Conflict().json(json!({ "error": e })),
+        }
     } else {
-        println!("No eligible nodes found for job_id: {}", job_id);
+        info!("No eligible nodes found for job_id: {}", job_id);
+        data.history.record(JobEvent::new(
+            &job_id,
+            JobEventKind::AssignmentFailed,
+            "No eligible nodes available",
+        ));
         HttpResponse::ServiceUnavailable().json("No eligible nodes available")
     }
 }
 
-fn validate_wallet(req: &AssignRequest) -> Result<Address, HttpResponse> {
+fn validate_wallet(req: &AssignRequest) -> Result<Address, Box<HttpResponse>> {
     let address_str = match req.address.as_ref().filter(|s| !s.is_empty()) {
         Some(s) => s,
         None => {
             error!("Wallet address missing");
-            return Err(HttpResponse::BadRequest().json("Wallet address required"));
+            return Err(Box::new(HttpResponse::BadRequest().json("Wallet address required")));
         }
     };
 
@@ -172,196 +415,425 @@ fn validate_wallet(req: &AssignRequest) -> Result<Address, HttpResponse> {
         Ok(addr) => Ok(addr),
         Err(_) => {
             error!("Invalid wallet address: {}", address_str);
-            Err(HttpResponse::BadRequest().json("Invalid wallet address"))
+            Err(Box::new(HttpResponse::BadRequest().json("Invalid wallet address")))
         }
     }
 }
 
+/// Picks a node for a pending job and claims it: the job is marked
+/// "assigning" and the node's GPUs are reserved under the locks, so neither a
+/// concurrent assignment nor a lease can take them while the chain call runs.
 fn find_and_select_node(
     data: &web::Data<AppState>,
     job_id: &str,
-) -> Result<Option<crate::nodes::Node>, HttpResponse> {
+    node_id: Option<&str>,
+) -> Result<Option<crate::nodes::Node>, Box<HttpResponse>> {
     let mut jobs = data.jobs.lock().unwrap();
-    let nodes = data.nodes.lock().unwrap();
-
-    let job_opt = jobs.iter_mut().find(|j| j.job_id == job_id);
+    let mut nodes = data.nodes.lock().unwrap();
 
-    if job_opt.is_none() {
-        println!("Job not found: {}", job_id);
-        return Err(HttpResponse::NotFound().json("Job not found"));
+    if let Some(node_id) = node_id {
+        if !nodes.iter().any(|n| n.node_id == node_id) {
+            info!("Node not found: {}", node_id);
+            return Err(Box::new(HttpResponse::NotFound().json("Node not found")));
+        }
     }
 
-    let job = job_opt.unwrap();
-    if job.status != "pending" {
-        println!("Job {} not pending", job_id);
-        return Err(HttpResponse::BadRequest().json("Job not pending"));
+    let idx = match jobs.iter().position(|j| j.job_id == job_id) {
+        Some(i) => i,
+        None => {
+            info!("Job not found: {}", job_id);
+            return Err(Box::new(HttpResponse::NotFound().json("Job not found")));
+        }
+    };
+
+    if jobs[idx].status != "pending" {
+        info!("Job {} not pending", job_id);
+        return Err(Box::new(HttpResponse::BadRequest().json("Job not pending")));
+    }
+    if FairShare::compute(&jobs, &data.cfg).at_limit(&jobs[idx].owner) {
+        info!("Owner of job {} is at their concurrency limit", job_id);
+        return Err(Box::new(
+            HttpResponse::TooManyRequests().json("Owner is at their concurrency limit"),
+        ));
     }
 
-    println!("Selecting node for job_id: {}", job_id);
-    Ok(select_node_for_job(job, &nodes))
+    info!("Selecting node for job_id: {}", job_id);
+    let candidates: Vec<Node> = nodes
+        .iter()
+        .filter(|n| node_id.is_none_or(|id| n.node_id == id))
+        .cloned()
+        .collect();
+    let node_id = match select_node_for_job(&jobs[idx], &candidates, data.cfg.locality_weight) {
+        Some(n) => n.node_id,
+        None => return Ok(None),
+    };
+    let job = &mut jobs[idx];
+    let node = match nodes.iter_mut().find(|n| n.node_id == node_id) {
+        Some(n) => n,
+        None => return Ok(None),
+    };
+    if !allocation::allocate(node, job) {
+        warn!("Job {} no longer fits on node {}", job_id, node_id);
+        return Err(Box::new(
+            HttpResponse::Conflict()
+                .json(json!({ "error": "Selected node no longer has room for the job" })),
+        ));
+    }
+    job.status = "assigning".to_string();
+    job.assigned_node = Some(node_id);
+    data.events.publish_node(node);
+    data.events.publish_job(job);
+    let selected = node.clone();
+    save_jobs(&jobs);
+    save_nodes(&nodes);
+    Ok(Some(selected))
 }
 
-async fn assign_on_chain(
+#[instrument(skip_all, fields(job_id = %job_id, tx_hash = field::Empty))]
+pub async fn assign_on_chain(
     data: &web::Data<AppState>,
     job_id: &str,
     wallet_address: Address,
 ) -> Result<(), HttpResponse> {
     let contract = &data.cfg.contract;
+    let timer = data.metrics.contract_call("assignProvider");
     match contract.method::<_, ()>("assignProvider", (job_id.to_string(), wallet_address)) {
         Ok(call) => {
-            println!(
+            info!(
                 "Calling blockchain contract assignProvider for job_id: {}",
                 job_id
             );
             match call.send().await {
                 Ok(tx) => {
-                    if tx.await.is_err() {
-                        println!("Transaction failed to confirm for job_id: {}", job_id);
-                        return Err(HttpResponse::InternalServerError()
-                            .json("Transaction failed to confirm"));
+                    let tx_hash = Some(format!("{:?}", tx.tx_hash()));
+                    Span::current().record("tx_hash", tx_hash.as_deref());
+                    data.history.record(JobEvent::transaction(
+                        job_id,
+                        "assignProvider",
+                        tx_hash.clone(),
+                        "submitted",
+                    ));
+                    match tx.await {
+                        Ok(receipt) => {
+                            let status = receipt_status(receipt.as_ref());
+                            data.history.record(JobEvent::transaction(
+                                job_id,
+                                "assignProvider",
+                                tx_hash,
+                                status,
+                            ));
+                            if status != "confirmed" {
+                                warn!("Transaction {} for job_id: {}", status, job_id);
+                                return Err(HttpResponse::InternalServerError()
+                                    .json("Transaction failed to confirm"));
+                            }
+                        }
+                        Err(e) => {
+                            error!("Transaction failed to confirm for job_id: {} ({:?})", job_id, e);
+                            data.history.record(JobEvent::transaction(
+                                job_id,
+                                "assignProvider",
+                                tx_hash,
+                                "failed",
+                            ));
+                            return Err(HttpResponse::InternalServerError()
+                                .json("Transaction failed to confirm"));
+                        }
                     }
-                    println!("Transaction confirmed for job_id: {}", job_id);
+                    info!("Transaction confirmed for job_id: {}", job_id);
+                    timer.succeeded();
                     Ok(())
                 }
                 Err(e) => {
-                    println!("Blockchain assignment failed: {:?}", e);
+                    error!("Blockchain assignment failed: {:?}", e);
+                    data.history.record(JobEvent::transaction(
+                        job_id,
+                        "assignProvider",
+                        None,
+                        "failed",
+                    ));
                     Err(HttpResponse::InternalServerError().json("Blockchain assignment failed"))
                 }
             }
         }
         Err(e) => {
-            println!("Contract call preparation failed: {:?}", e);
+            error!("Contract call preparation failed: {:?}", e);
             Err(HttpResponse::InternalServerError().json("Contract call preparation failed"))
         }
     }
 }
 
-fn update_job_and_node_state(
+/// Completes an assignment once the chain call has confirmed: the claimed job
+/// becomes "assigned" to the node and starts its clock. Fails, and hands the
+/// claim back to the pending queue, when the job was cancelled or changed
+/// meanwhile or no longer fits on the node.
+pub fn update_job_and_node_state(
     data: &web::Data<AppState>,
     job_id: &str,
     wallet_address: Address,
     selected_node: &crate::nodes::Node,
-) -> serde_json::Value {
-    println!("[LOG] Acquiring jobs lock");
+    reason: &str,
+) -> Result<serde_json::Value, String> {
     let mut jobs = data.jobs.lock().unwrap();
-    println!("[LOG] Acquiring nodes lock");
     let mut nodes = data.nodes.lock().unwrap();
 
-    println!("[LOG] Searching for job with id: {}", job_id);
-    let job = jobs.iter_mut().find(|j| j.job_id == job_id).unwrap();
-    println!("[LOG] Updating job provider_address, assigned_node, status");
-    job.provider_address = Some(wallet_address.to_string());
-    job.assigned_node = Some(selected_node.node_id.clone());
-    job.status = "assigned".to_string();
-
-    println!(
-        "[LOG] Searching for node with id: {} and setting status to 'busy'",
-        selected_node.node_id
-    );
-    if let Some(n) = nodes
+    let job = match jobs.iter_mut().find(|j| j.job_id == job_id) {
+        Some(j) => j,
+        None => {
+            warn!("Job {} disappeared while being assigned", job_id);
+            return Err(format!("Job {} no longer exists", job_id));
+        }
+    };
+    if job.status != "assigning"
+        || job.assigned_node.as_deref() != Some(selected_node.node_id.as_str())
+    {
+        warn!(
+            "Job {} changed while being assigned (now {})",
+            job_id, job.status
+        );
+        return Err(format!("Job {} is now {}", job_id, job.status));
+    }
+    let fits = nodes
         .iter_mut()
         .find(|n| n.node_id == selected_node.node_id)
-    {
-        n.status = "busy".to_string();
-    } else {
-        println!(
-            "[WARN] Selected node {} not found in nodes list",
-            selected_node.node_id
+        .is_some_and(|n| allocation::allocate(n, job));
+    if !fits {
+        let reason = format!(
+            "Job {} no longer fits on node {}",
+            job_id, selected_node.node_id
         );
+        drop(nodes);
+        drop(jobs);
+        unclaim_job(data, job_id, &reason);
+        return Err(reason);
     }
 
-    println!("[LOG] Cloning job and node for response");
-    let job_clone = job.clone();
-    let selected_node_clone = selected_node.clone();
-
-    println!("[LOG] Dropping jobs and nodes locks");
-    drop(jobs);
-    drop(nodes);
-
-    println!("[LOG] Reacquiring jobs and nodes locks for saving");
-    let jobs = data.jobs.lock().unwrap();
-    let nodes = data.nodes.lock().unwrap();
-    println!("[LOG] Saving jobs");
-    save_jobs(&jobs);
-    println!("[LOG] Saving nodes");
-    crate::nodes::save_nodes(&nodes);
-
-    println!(
-        "[LOG] Job {} assigned to node {} successfully",
-        job_clone.job_id, selected_node_clone.node_id
+    job.provider_address = Some(wallet_address.to_string());
+    job.status = "assigned".to_string();
+    // GPU hours, and so fair-share usage, are counted from here.
+    job.started_at = Some(Utc::now());
+    data.metrics.observe_job_wait(&job.created_at);
+    data.history.record(
+        JobEvent::new(job_id, JobEventKind::Assigned, reason).node(&selected_node.node_id),
     );
+    let job = job.clone();
+    data.events.publish_job(&job);
+    let node = nodes
+        .iter()
+        .find(|n| n.node_id == selected_node.node_id)
+        .cloned();
+    save_jobs(&jobs);
+    save_nodes(&nodes);
 
-    serde_json::json!({
+    info!("Job {} assigned to node {}", job_id, selected_node.node_id);
+    Ok(serde_json::json!({
         "success": true,
-        "job": job_clone,
-        "node": selected_node_clone
-    })
+        "job": job,
+        "node": node
+    }))
 }
 
 #[derive(Deserialize)]
 pub struct JobResult {
     pub node_id: String,
-    pub result_hash: String,
+    #[serde(default)]
+    pub result_hash: Option<String>,
+    /// Reported instead of a result hash when the job failed.
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub usage: Option<Resources>,
 }
 
+#[instrument(skip_all, fields(job_id = %job_id, node_id = %req.node_id))]
 pub async fn submit_job_result(
     job_id: web::Path<String>,
     req: web::Json<JobResult>,
     data: web::Data<AppState>,
 ) -> impl Responder {
-    let node_id = &req.node_id.clone();
-    let result_hash = &req.result_hash.clone();
-    println!("[INFO] Submit job result called for job_id: {}", *job_id);
-
-    let mut jobs = data.jobs.lock().unwrap();
-    let mut nodes = data.nodes.lock().unwrap();
+    let JobResult {
+        node_id,
+        result_hash,
+        error,
+        usage,
+    } = req.into_inner();
+    let node_id = &node_id;
+    info!("Submit job result called for job_id: {}", *job_id);
 
-    let job_opt = jobs.iter_mut().find(|j| j.job_id == *job_id);
-    if job_opt.is_none() {
-        println!("[WARN] Job not found: {}", *job_id);
-        return HttpResponse::NotFound().json(json!({"error": "Job not found"}));
+    if let Some(Err(errors)) = usage.as_ref().map(Resources::validate_usage) {
+        return errors.prefixed("usage").into_response();
     }
-    let job = job_opt.unwrap();
-    println!("[INFO] Found job {}, status: {}", job.job_id, job.status);
 
-    update_job_state_to_completed(job, &result_hash);
+    let mut outcome = match (result_hash, error) {
+        (_, Some(error)) => Err(error),
+        (Some(hash), None) => Ok(hash),
+        (None, None) => {
+            let mut errors = ValidationErrors::default();
+            errors.add("result_hash", "is required unless an error is reported");
+            return errors.into_response();
+        }
+    };
+
+    let job_clone = {
+        let mut jobs = data.jobs.lock().unwrap();
+        let mut nodes = data.nodes.lock().unwrap();
+
+        let job_opt = jobs.iter_mut().find(|j| j.job_id == *job_id);
+        if job_opt.is_none() {
+            warn!("Job not found: {}", *job_id);
+            return HttpResponse::NotFound().json(json!({"error": "Job not found"}));
+        }
+        let job = job_opt.unwrap();
+        info!("Found job {}, status: {}", job.job_id, job.status);
+        // An operator may have cancelled or requeued the job since it was
+        // placed, and only the node it was placed on may report its result.
+        if !holds_node(job) || job.assigned_node.as_deref() != Some(node_id.as_str()) {
+            warn!(
+                "Rejected result for job {} from node {}: job is {} on {:?}",
+                job.job_id, node_id, job.status, job.assigned_node
+            );
+            return HttpResponse::Conflict().json(json!({
+                "error": "Job is not assigned to this node",
+                "status": job.status,
+            }));
+        }
 
-    update_node_status(&mut nodes, node_id);
+        let gpu_share = nodes
+            .iter()
+            .find(|n| n.node_id == *node_id)
+            .map(|n| allocation::gpu_share(n, &job.job_id))
+            .filter(|share| *share > 0.0)
+            .unwrap_or(job.gpu_count as f64);
 
-    let job_clone = job.clone();
-    drop(jobs);
-    drop(nodes);
+        if let Some(usage) = usage {
+            let over = usage::overruns(&job.limits, &usage, usage::measured_runtime(job));
+            // A runtime overrun flagged while the job ran is updated in place.
+            for overrun in &over {
+                let field = overrun.split(':').next();
+                match job.overruns.iter().position(|o| o.split(':').next() == field) {
+                    Some(i) => job.overruns[i] = overrun.clone(),
+                    None => {
+                        data.history.record(
+                            JobEvent::new(
+                                &job.job_id,
+                                JobEventKind::LimitExceeded,
+                                overrun.clone(),
+                            )
+                            .node(node_id),
+                        );
+                        job.overruns.push(overrun.clone());
+                    }
+                }
+            }
+            if !over.is_empty()
+                && outcome.is_ok()
+                && data.cfg.limit_enforcement == LimitEnforcement::Fail
+            {
+                outcome = Err(format!("Exceeded resource limits: {}", over.join("; ")));
+            }
+            job.usage = Some(usage);
+        }
 
+        match &outcome {
+            Ok(result_hash) => {
+                update_job_state_to_completed(job, result_hash, gpu_share);
+                data.metrics.observe_job_completion(&job.created_at);
+                data.history.record(
+                    JobEvent::new(
+                        &job.job_id,
+                        JobEventKind::ResultSubmitted,
+                        format!("Result hash {}", result_hash),
+                    )
+                    .node(node_id),
+                );
+            }
+            Err(error) => {
+                update_job_state_to_failed(job, error, gpu_share);
+                data.history.record(
+                    JobEvent::new(&job.job_id, JobEventKind::Failed, error.clone())
+                        .node(node_id),
+                );
+            }
+        }
+        data.events.publish_job(job);
+
+        if let Some(node) = update_node_status(&mut nodes, node_id, &job.job_id) {
+            data.events.publish_node(node);
+        }
+
+        let job_clone = job.clone();
+        if outcome.is_ok() {
+            workflow::release_dependents(&data, &mut jobs, &job_clone.job_id);
+        } else {
+            workflow::cancel_dependents(&data, &mut jobs, &job_clone.job_id);
+        }
+        job_clone
+    };
+
+    release_job_leases(&data, &job_clone.job_id);
     persist_state(&data);
 
-    if let Err(e) = submit_result_to_blockchain(
-        &data,
-        &job_clone.job_id,
-        job_clone.result_hash.as_ref().unwrap(),
-    )
-    .await
-    {
-        println!("[ERROR] Blockchain transaction error: {:?}", e);
+    // The contract has no failure path; a failed job keeps its on-chain
+    // assignment and bounty until the owner intervenes.
+    if let Ok(result_hash) = &outcome {
+        if let Err(e) =
+            submit_result_to_blockchain(&data, &job_clone.job_id, &job_clone.bounty, result_hash)
+                .await
+        {
+            error!("Blockchain transaction error: {:?}", e);
+        }
     }
 
     HttpResponse::Ok().json(job_clone)
 }
 
-fn update_job_state_to_completed(job: &mut Job, result_hash: &str) {
-    job.status = "completed".to_string();
+fn finish_job(job: &mut Job, status: &str, gpu_share: f64) {
+    let now = Utc::now();
+    job.status = status.to_string();
+    job.completed_at = Some(now);
+    job.gpu_hours = job
+        .started_at
+        .map(|started| (now - started).num_seconds().max(0) as f64 / 3600.0 * gpu_share);
+}
+
+fn update_job_state_to_completed(job: &mut Job, result_hash: &str, gpu_share: f64) {
+    finish_job(job, "completed", gpu_share);
     job.result_hash = Some(result_hash.to_owned());
-    println!(
-        "[INFO] Job marked completed with result_hash: {}",
+    info!(
+        "Job marked completed with result_hash: {}",
         result_hash
     );
 }
 
-fn update_node_status(nodes: &mut Vec<Node>, node_id: &str) {
+pub fn update_job_state_to_failed(job: &mut Job, error: &str, gpu_share: f64) {
+    finish_job(job, "failed", gpu_share);
+    job.error = Some(error.to_owned());
+    warn!("Job {} failed: {}", job.job_id, error);
+}
+
+pub fn update_node_status<'a>(
+    nodes: &'a mut [Node],
+    node_id: &str,
+    job_id: &str,
+) -> Option<&'a Node> {
     if let Some(node) = nodes.iter_mut().find(|n| n.node_id == node_id) {
-        node.status = "idle".to_string();
-        println!("[INFO] Node {} marked as idle", node.node_id);
+        allocation::release(node, job_id);
+        info!(
+            "Released GPUs of job {} on node {} (now {})",
+            job_id, node.node_id, node.status
+        );
+        Some(node)
     } else {
-        println!("[WARN] Node {} not found while updating status", node_id);
+        warn!("Node {} not found while updating status", node_id);
+        None
+    }
+}
+
+pub fn receipt_status(receipt: Option<&TransactionReceipt>) -> &'static str {
+    match receipt {
+        Some(r) if r.status == Some(1.into()) => "confirmed",
+        Some(_) => "reverted",
+        None => "dropped",
     }
 }
 
@@ -370,76 +842,101 @@ fn persist_state(data: &web::Data<AppState>) {
     let nodes = data.nodes.lock().unwrap();
     save_jobs(&jobs);
     crate::nodes::save_nodes(&nodes);
-    println!("[INFO] Persisted jobs and nodes to storage");
+    info!("Persisted jobs and nodes to storage");
 }
 
+#[instrument(skip_all, fields(job_id = %job_id, tx_hash = field::Empty))]
 async fn submit_result_to_blockchain(
     data: &web::Data<AppState>,
     job_id: &str,
+    bounty: &TokenAmount,
     result_hash: &str,
 ) -> Result<(), YourContractError> {
-    println!(
-        "[INFO] Submitting result to blockchain for job: {}, hash: {}",
+    info!(
+        "Submitting result to blockchain for job: {}, hash: {}",
         job_id, result_hash
     );
-    println!(
-        "[INFO] Using contract at address: {:?}",
+    info!(
+        "Using contract at address: {:?}",
         data.cfg.contract.address()
     );
 
     let contract = &data.cfg.contract;
-    println!("[INFO] Preparing to convert result_hash to H256");
+    info!("Preparing to convert result_hash to H256");
     let result_hash_bytes = match H256::from_str(result_hash) {
         Ok(val) => {
-            println!("[INFO] Converted result_hash to H256: {:?}", val);
+            info!("Converted result_hash to H256: {:?}", val);
             val
         }
         Err(e) => {
-            eprintln!("[ERROR] Failed to parse result_hash as H256: {:?}", e);
+            error!("Failed to parse result_hash as H256: {:?}", e);
             return Err(ContractError::AbiError(AbiError::DecodingError(
                 ethers::abi::Error::InvalidData,
             )));
         }
     };
-    println!("[INFO] Calling contract method submitResult");
+    info!("Calling contract method submitResult");
+    let submit_timer = data.metrics.contract_call("submitResult");
     let submit_call =
         match contract.method::<_, ()>("submitResult", (job_id.to_string(), result_hash_bytes)) {
             Ok(call) => {
-                println!("[INFO] Prepared contract call for submitResult");
+                info!("Prepared contract call for submitResult");
                 call
             }
             Err(e) => {
-                eprintln!("[ERROR] Failed to prepare contract method call: {:?}", e);
+                error!("Failed to prepare contract method call: {:?}", e);
                 return Err(ContractError::AbiError(e));
             }
         };
-    println!("[INFO] Sending transaction to blockchain");
+    info!("Sending transaction to blockchain");
     let pending_tx = match submit_call.send().await {
         Ok(tx) => {
-            println!("[INFO] Transaction sent with hash: {:?}", tx.tx_hash());
+            info!("Transaction sent with hash: {:?}", tx.tx_hash());
             tx
         }
         Err(e) => {
+            data.history.record(JobEvent::transaction(job_id, "submitResult", None, "failed"));
             if let Some(revert_data) = e.as_revert() {
                 if let Ok(reason) = String::decode(&revert_data.0[4..]) {
-                    eprintln!("[ERROR] Transaction reverted: {}", reason);
+                    error!("Transaction reverted: {}", reason);
                 } else {
-                    eprintln!("[ERROR] Transaction reverted, but failed to decode reason");
+                    error!("Transaction reverted, but failed to decode reason");
                 }
             } else {
-                eprintln!("[ERROR] Transaction send failed: {:?}", e);
+                error!("Transaction send failed: {:?}", e);
             }
-            return Err(e.into());
+            return Err(e);
         }
     };
-    println!("[INFO] Awaiting transaction confirmation");
-    let receipt = match pending_tx.await {
+    let submit_hash = Some(format!("{:?}", pending_tx.tx_hash()));
+    Span::current().record("tx_hash", submit_hash.as_deref());
+    data.history.record(JobEvent::transaction(
+        job_id,
+        "submitResult",
+        submit_hash.clone(),
+        "submitted",
+    ));
+    info!("Awaiting transaction confirmation");
+    let submit_receipt = pending_tx.await;
+    let submit_status = match &submit_receipt {
+        Ok(r) => receipt_status(r.as_ref()),
+        Err(_) => "failed",
+    };
+    data.history.record(JobEvent::transaction(
+        job_id,
+        "submitResult",
+        submit_hash,
+        submit_status,
+    ));
+    match submit_receipt {
         Ok(Some(r)) => {
-            println!("[INFO] Transaction confirmed: {:?}", r.transaction_hash);
-            r
+            info!("Transaction confirmed: {:?}", r.transaction_hash);
+            if submit_status == "confirmed" {
+                submit_timer.succeeded();
+            }
         }
         Ok(None) => {
-            println!("[WARN] Transaction receipt is None");
+            warn!("Transaction receipt is None");
             return Err(ContractError::ProviderError {
                 e: ethers::providers::ProviderError::CustomError(
                     "Transaction receipt was None".into(),
@@ -447,51 +944,82 @@ async fn submit_result_to_blockchain(
             });
         }
         Err(e) => {
-            eprintln!("[ERROR] Awaiting transaction receipt failed: {:?}", e);
+            error!("Awaiting transaction receipt failed: {:?}", e);
             return Err(e.into());
         }
     };
-    println!("[INFO] Result submission transaction completed");
+    info!("Result submission transaction completed");
+    // Token bounties are paid out by the token's own transfer.
+    let method = if bounty.token.is_native() { "release" } else { "releaseToken" };
+    if let Err(e) = erc20::check_escrow(&data.cfg, job_id, bounty).await {
+        error!("Not releasing bounty for job {}: {}", job_id, e);
+        data.history
+            .record(JobEvent::transaction(job_id, method, None, "failed").amount(bounty));
+        return Err(ContractError::ProviderError {
+            e: ethers::providers::ProviderError::CustomError(e),
+        });
+    }
+    let release_timer = data.metrics.contract_call(method);
     let owner_contract = &data.cfg.owner_contract;
-    println!(
-        "[INFO] Preparing to call owner contract release for job_id: {}",
-        job_id
+    info!(
+        "Preparing to call owner contract {} for job_id: {} ({} {})",
+        method,
+        job_id,
+        bounty.to_decimal(),
+        bounty.token.symbol
     );
-    let release_call = match owner_contract.method::<_, ()>("release", job_id.to_string()) {
+    let release_call = match owner_contract.method::<_, ()>(method, job_id.to_string()) {
         Ok(call) => call,
         Err(e) => {
-            eprintln!(
-                "[ERROR] Failed to prepare owner contract method call: {:?}",
+            error!(
+                "Failed to prepare owner contract method call: {:?}",
                 e
             );
             return Err(ContractError::AbiError(e));
         }
     };
-    println!("[INFO] Sending release transaction to blockchain");
+    info!("Sending release transaction to blockchain");
     let pending_release_tx = match release_call.send().await {
         Ok(tx) => {
-            println!(
-                "[INFO] Release transaction sent with hash: {:?}",
+            info!(
+                "Release transaction sent with hash: {:?}",
                 tx.tx_hash()
             );
             tx
         }
         Err(e) => {
-            eprintln!("[ERROR] Release transaction send failed: {:?}", e);
-            return Err(e.into());
+            data.history
+                .record(JobEvent::transaction(job_id, method, None, "failed").amount(bounty));
+            error!("Release transaction send failed: {:?}", e);
+            return Err(e);
         }
     };
-    println!("[INFO] Awaiting release transaction confirmation");
-    let release_receipt = match pending_release_tx.await {
+    let release_hash = Some(format!("{:?}", pending_release_tx.tx_hash()));
+    Span::current().record("tx_hash", release_hash.as_deref());
+    data.history.record(
+        JobEvent::transaction(job_id, method, release_hash.clone(), "submitted").amount(bounty),
+    );
+    info!("Awaiting release transaction confirmation");
+    let release_receipt = pending_release_tx.await;
+    let release_status = match &release_receipt {
+        Ok(r) => receipt_status(r.as_ref()),
+        Err(_) => "failed",
+    };
+    data.history.record(
+        JobEvent::transaction(job_id, method, release_hash, release_status).amount(bounty),
+    );
+    match release_receipt {
         Ok(Some(r)) => {
-            println!(
-                "[INFO] Release transaction confirmed: {:?}",
+            info!(
+                "Release transaction confirmed: {:?}",
                 r.transaction_hash
             );
-            r
+            if release_status == "confirmed" {
+                release_timer.succeeded();
+            }
         }
         Ok(None) => {
-            println!("[WARN] Release transaction receipt is None");
+            warn!("Release transaction receipt is None");
             return Err(ContractError::ProviderError {
                 e: ethers::providers::ProviderError::CustomError(
                     "Transaction receipt was None".into(),
@@ -499,8 +1027,8 @@ async fn submit_result_to_blockchain(
             });
         }
         Err(e) => {
-            eprintln!(
-                "[ERROR] Awaiting release transaction receipt failed: {:?}",
+            error!(
+                "Awaiting release transaction receipt failed: {:?}",
                 e
             );
             return Err(e.into());
@@ -515,8 +1043,15 @@ pub fn config(cfg: &mut web::ServiceConfig) {
         web::scope("/nodes")
             .route("", web::get().to(get_all_nodes))
             .route("/register", web::post().to(register_node))
+            .route("/{id}", web::patch().to(update_node))
+            .route("/{id}", web::delete().to(delete_node))
             .route("/{id}/jobs", web::get().to(get_node_jobs))
+            .route("/{id}/chain", web::get().to(get_node_chain_status))
+            .route("/{id}/capacity", web::get().to(get_node_capacity))
+            .route("/{id}/heartbeat", web::post().to(node_heartbeat))
             .route("/{id}/result", web::post().to(submit_job_result))
+            .route("/{id}/lease", web::post().to(acquire_lease))
+            .route("/{id}/lease/{lease_id}/renew", web::post().to(renew_lease))
             .route("/assign-provider", web::post().to(assign_provider)),
     );
 }
@@ -0,0 +1,438 @@
+use crate::config::AppConfig;
+use crate::models::{Job, Node};
+use crate::state::AppState;
+use crate::validation::{self, ValidationErrors};
+use actix_web::{web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+
+/// Longest run a quote can be requested for.
+const MAX_QUOTE_HOURS: f64 = 720.0;
+
+/// Body accepted by `POST /quotes`.
+#[derive(Debug, Deserialize)]
+pub struct QuoteRequest {
+    #[serde(rename = "requiredSpecs")]
+    pub required_specs: Option<String>,
+    #[serde(rename = "gpuCount", default)]
+    pub gpu_count: Option<i64>,
+    #[serde(rename = "gpuMemory", default)]
+    pub gpu_memory: Option<i64>,
+    #[serde(rename = "minMemory", default)]
+    pub min_memory: Option<i64>,
+    #[serde(rename = "expectedHours")]
+    pub expected_hours: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Quote {
+    #[serde(rename = "suggestedBounty")]
+    pub suggested_bounty: f64,
+    /// Lowest bounty at least one matching node's price floor accepts; None
+    /// when no matching node publishes a price.
+    #[serde(rename = "minimumBounty")]
+    pub minimum_bounty: Option<f64>,
+    #[serde(rename = "gpuHourRate")]
+    pub gpu_hour_rate: f64,
+    #[serde(rename = "gpuHours")]
+    pub gpu_hours: f64,
+    #[serde(rename = "memoryCost")]
+    pub memory_cost: f64,
+    #[serde(rename = "surgeMultiplier")]
+    pub surge_multiplier: f64,
+    #[serde(rename = "queueDepth")]
+    pub queue_depth: usize,
+    #[serde(rename = "availableNodes")]
+    pub available_nodes: usize,
+}
+
+fn round_amount(value: f64) -> f64 {
+    (value * 1e6).ceil() / 1e6
+}
+
+/// Rate per GPU-hour for a spec class, falling back to the base rate.
+pub fn class_rate(cfg: &AppConfig, specs: &str) -> f64 {
+    cfg.pricing_class_rates
+        .get(&specs.trim().to_lowercase())
+        .copied()
+        .unwrap_or(cfg.pricing_base_gpu_hour)
+}
+
+/// Checks a run time in hours, as quoted or submitted with a job.
+pub fn check_hours(hours: f64) -> Result<f64, String> {
+    if hours.is_finite() && hours > 0.0 && hours <= MAX_QUOTE_HOURS {
+        Ok(hours)
+    } else {
+        Err(format!(
+            "must be greater than 0 and at most {}",
+            MAX_QUOTE_HOURS
+        ))
+    }
+}
+
+/// Whole GPUs a request occupies; a memory slice counts as its share of the
+/// largest matching device.
+fn gpu_units(gpu_count: u32, gpu_memory: Option<u64>, nodes: &[&Node]) -> f64 {
+    let largest_device = nodes
+        .iter()
+        .flat_map(|n| n.devices.iter().map(|d| d.memory))
+        .max()
+        .filter(|m| *m > 0);
+    match (gpu_memory, largest_device) {
+        (Some(slice), Some(device)) => (slice as f64 / device as f64).min(1.0),
+        _ => gpu_count as f64,
+    }
+}
+
+/// GPU-hours a job is expected to take on `node`, counted in the same units
+/// as `/quotes`: its wall-clock limit when set, otherwise the hours it was
+/// submitted with. None when the job states neither.
+pub fn expected_gpu_hours(job: &Job, node: &Node) -> Option<f64> {
+    let hours = match job.limits.runtime_secs {
+        Some(secs) => secs / 3600.0,
+        None => job.expected_hours?,
+    };
+    Some(gpu_units(job.gpu_count.max(1), job.gpu_memory, &[node]) * hours)
+}
+
+/// What the job pays per GPU-hour on `node`, in whole units of its bounty's
+/// currency, compared against provider price floors.
+pub fn offered_rate(job: &Job, node: &Node) -> Option<f64> {
+    expected_gpu_hours(job, node)
+        .filter(|h| *h > 0.0)
+        .map(|hours| job.bounty.to_f64() / hours)
+}
+
+/// Whether the job's bounty meets the node's price floor in the bounty's
+/// currency: `minPricePerGpuHour` for native bounties, the token's entry in
+/// `tokenPriceFloors` otherwise. There is no exchange rate between
+/// currencies, so a node that publishes any floor but none for the job's
+/// token declines it; a node without floors takes every job. A job with no
+/// runtime limit or expected hours cannot be priced and only goes to nodes
+/// without a floor in its currency.
+pub fn node_accepts_price(node: &Node, job: &Job) -> bool {
+    let floor = match job.bounty.token.address {
+        None => node.min_price_per_gpu_hour,
+        Some(token) => match node.token_price_floors.get(&token) {
+            Some(floor) => Some(*floor),
+            None if node.min_price_per_gpu_hour.is_some()
+                || !node.token_price_floors.is_empty() =>
+            {
+                return false
+            }
+            None => None,
+        },
+    };
+    match floor {
+        None => true,
+        Some(floor) => offered_rate(job, node).is_some_and(|rate| rate >= floor),
+    }
+}
+
+/// A validated quote request.
+pub struct Demand {
+    pub specs: String,
+    pub gpu_count: u32,
+    pub gpu_memory: Option<u64>,
+    pub min_memory: u64,
+    pub hours: f64,
+}
+
+impl QuoteRequest {
+    pub fn into_demand(self) -> Result<Demand, ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+
+        let specs = errors
+            .check("requiredSpecs", validation::required(&self.required_specs))
+            .and_then(|s| {
+                errors.check(
+                    "requiredSpecs",
+                    validation::bounded_text(s, validation::MAX_SPECS_LEN).map(|_| s),
+                )
+            });
+        let gpu_count = match self.gpu_count {
+            Some(n) if !(1..=validation::MAX_GPUS as i64).contains(&n) => {
+                errors.add(
+                    "gpuCount",
+                    format!("must be between 1 and {}", validation::MAX_GPUS),
+                );
+                None
+            }
+            Some(n) => Some(n as u32),
+            None => Some(1),
+        };
+        let gpu_memory = self
+            .gpu_memory
+            .and_then(|m| errors.check("gpuMemory", validation::memory_gb(m)));
+        let min_memory = match self.min_memory {
+            Some(m) => errors.check("minMemory", validation::memory_gb(m)),
+            None => Some(0),
+        };
+        let hours = match self.expected_hours {
+            Some(h) => errors.check("expectedHours", check_hours(h)),
+            None => {
+                errors.add("expectedHours", "is required");
+                None
+            }
+        };
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(Demand {
+            specs: specs.unwrap().trim().to_string(),
+            gpu_count: gpu_count.unwrap(),
+            gpu_memory,
+            min_memory: min_memory.unwrap(),
+            hours: hours.unwrap(),
+        })
+    }
+}
+
+/// Prices `demand` against the configured rates, surging with the backlog of
+/// pending jobs per available node of the same specs. The suggestion never
+/// falls below what the cheapest matching node accepts.
+pub fn quote(cfg: &AppConfig, jobs: &[Job], nodes: &[Node], demand: &Demand) -> Quote {
+    let specs = demand.specs.as_str();
+    let matching: Vec<&Node> = nodes
+        .iter()
+        .filter(|n| n.active && n.gpu_specs == specs)
+        .collect();
+    let available_nodes = matching
+        .iter()
+        .filter(|n| n.is_chain_registered() && n.status != "busy")
+        .count();
+    let queue_depth = jobs
+        .iter()
+        .filter(|j| j.status == "pending" && j.required_specs == specs)
+        .count();
+
+    let gpu_hour_rate = class_rate(cfg, specs);
+    let gpu_hours = gpu_units(demand.gpu_count, demand.gpu_memory, &matching) * demand.hours;
+    let memory_cost = cfg.pricing_memory_gb_hour * demand.min_memory as f64 * demand.hours;
+    let surge_multiplier = (1.0
+        + cfg.pricing_queue_surge * queue_depth as f64 / available_nodes.max(1) as f64)
+        .min(cfg.pricing_max_surge.max(1.0));
+
+    let minimum_bounty = matching
+        .iter()
+        .filter_map(|n| n.min_price_per_gpu_hour)
+        .min_by(f64::total_cmp)
+        .map(|floor| round_amount(floor * gpu_hours));
+    let computed = (gpu_hour_rate * gpu_hours + memory_cost) * surge_multiplier;
+    let suggested_bounty = round_amount(computed.max(minimum_bounty.unwrap_or(0.0)));
+
+    Quote {
+        suggested_bounty,
+        minimum_bounty,
+        gpu_hour_rate,
+        gpu_hours,
+        memory_cost,
+        surge_multiplier,
+        queue_depth,
+        available_nodes,
+    }
+}
+
+async fn create_quote(req: web::Json<QuoteRequest>, data: web::Data<AppState>) -> impl Responder {
+    let demand = match req.into_inner().into_demand() {
+        Ok(d) => d,
+        Err(errors) => return errors.into_response(),
+    };
+
+    let jobs = data.jobs.lock().unwrap();
+    let nodes = data.nodes.lock().unwrap();
+    HttpResponse::Ok().json(quote(&data.cfg, &jobs, &nodes, &demand))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.route("/quotes", web::post().to(create_quote));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::{Token, TokenAmount};
+    use crate::models::ChainRegistration;
+    use chrono::Utc;
+    use ethers::types::{Address, U256};
+    use serde_json::json;
+
+    fn cfg() -> AppConfig {
+        let mut cfg = AppConfig::for_tests();
+        cfg.pricing_base_gpu_hour = 0.5;
+        cfg.pricing_class_rates.insert("a100".to_string(), 2.0);
+        cfg.pricing_memory_gb_hour = 0.01;
+        cfg.pricing_queue_surge = 0.1;
+        cfg.pricing_max_surge = 3.0;
+        cfg
+    }
+
+    fn node(node_id: &str, device_memory: u64) -> Node {
+        let mut node: Node = serde_json::from_value(json!({
+            "nodeId": node_id,
+            "gpuName": null,
+            "gpuSpecs": "A100",
+            "owner": null,
+            "memoryAvailable": device_memory,
+            "active": true,
+            "status": "idle",
+            "devices": [{ "index": 0, "memory": device_memory }],
+        }))
+        .unwrap();
+        node.chain = Some(ChainRegistration {
+            registered: true,
+            drift: Vec::new(),
+            error: None,
+            checked_at: Utc::now(),
+        });
+        node
+    }
+
+    fn job(bounty: TokenAmount, expected_hours: Option<f64>) -> Job {
+        let mut job = Job::new(
+            "job".to_string(),
+            "0x0".to_string(),
+            None,
+            "train:1".to_string(),
+            bounty,
+            "2999-01-01".to_string(),
+            "A100".to_string(),
+            16,
+        );
+        job.expected_hours = expected_hours;
+        job
+    }
+
+    fn eth(whole: u64) -> TokenAmount {
+        TokenAmount::native(U256::exp10(18) * whole)
+    }
+
+    fn usdc(whole: u64) -> TokenAmount {
+        let token = Token {
+            symbol: "USDC".to_string(),
+            decimals: 6,
+            address: Some(Address::repeat_byte(1)),
+        };
+        TokenAmount::new(U256::exp10(6) * whole, &token)
+    }
+
+    fn demand(specs: &str, gpu_count: u32, gpu_memory: Option<u64>) -> Demand {
+        Demand {
+            specs: specs.to_string(),
+            gpu_count,
+            gpu_memory,
+            min_memory: 10,
+            hours: 3.0,
+        }
+    }
+
+    fn pending(count: usize) -> Vec<Job> {
+        (0..count).map(|_| job(eth(1), None)).collect()
+    }
+
+    #[test]
+    fn prices_gpu_hours_at_the_class_rate_plus_memory() {
+        let quote = quote(&cfg(), &[], &[node("n", 80)], &demand("A100", 2, None));
+        assert_eq!(quote.gpu_hour_rate, 2.0);
+        assert_eq!(quote.gpu_hours, 6.0);
+        assert_eq!(quote.surge_multiplier, 1.0);
+        assert_eq!(quote.suggested_bounty, 12.3);
+        assert_eq!(quote.minimum_bounty, None);
+        assert_eq!(quote.available_nodes, 1);
+    }
+
+    #[test]
+    fn unknown_specs_use_the_base_rate() {
+        let quote = quote(&cfg(), &[], &[], &demand(" H100 ", 1, None));
+        assert_eq!(quote.gpu_hour_rate, 0.5);
+        assert_eq!(quote.available_nodes, 0);
+    }
+
+    #[test]
+    fn a_slice_counts_as_its_share_of_the_largest_device() {
+        let nodes = [node("small", 40), node("large", 80)];
+        let quote = quote(&cfg(), &[], &nodes, &demand("A100", 1, Some(20)));
+        assert_eq!(quote.gpu_hours, 0.75);
+
+        let quote = super::quote(&cfg(), &[], &nodes, &demand("A100", 1, Some(160)));
+        assert_eq!(quote.gpu_hours, 3.0);
+    }
+
+    #[test]
+    fn surge_follows_the_backlog_per_node_up_to_the_cap() {
+        let mut busy = node("busy", 80);
+        busy.status = "busy".to_string();
+        let nodes = [node("n", 80), busy];
+        let quote = quote(&cfg(), &pending(5), &nodes, &demand("A100", 1, None));
+        assert_eq!(quote.queue_depth, 5);
+        assert_eq!(quote.available_nodes, 1);
+        assert_eq!(quote.surge_multiplier, 1.5);
+
+        let quote = super::quote(&cfg(), &pending(50), &[], &demand("A100", 1, None));
+        assert_eq!(quote.surge_multiplier, 3.0);
+    }
+
+    #[test]
+    fn the_cheapest_node_floor_sets_the_minimum() {
+        let mut cheap = node("cheap", 80);
+        cheap.min_price_per_gpu_hour = Some(10.0);
+        let mut dear = node("dear", 80);
+        dear.min_price_per_gpu_hour = Some(20.0);
+        let quote = quote(&cfg(), &[], &[cheap, dear], &demand("A100", 1, None));
+        assert_eq!(quote.minimum_bounty, Some(30.0));
+        assert_eq!(quote.suggested_bounty, 30.0);
+    }
+
+    #[test]
+    fn demand_needs_hours_and_a_sane_gpu_count() {
+        let request: QuoteRequest = serde_json::from_value(json!({
+            "requiredSpecs": "A100",
+            "gpuCount": 0,
+        }))
+        .unwrap();
+        let fields: Vec<String> = request
+            .into_demand()
+            .err()
+            .unwrap()
+            .iter()
+            .map(|e| e.field.clone())
+            .collect();
+        assert_eq!(fields, vec!["gpuCount", "expectedHours"]);
+        assert!(check_hours(f64::NAN).is_err());
+        assert!(check_hours(0.0).is_err());
+        assert!(check_hours(MAX_QUOTE_HOURS).is_ok());
+    }
+
+    #[test]
+    fn native_floors_compare_the_rate_offered() {
+        let mut node = node("n", 80);
+        assert!(node_accepts_price(&node, &job(eth(2), None)));
+
+        node.min_price_per_gpu_hour = Some(1.5);
+        assert!(node_accepts_price(&node, &job(eth(2), Some(1.0))));
+        assert!(!node_accepts_price(&node, &job(eth(2), Some(2.0))));
+        assert!(!node_accepts_price(&node, &job(eth(2), None)));
+
+        let mut limited = job(eth(2), Some(2.0));
+        limited.limits.runtime_secs = Some(3600.0);
+        assert!(node_accepts_price(&node, &limited));
+    }
+
+    #[test]
+    fn token_floors_never_fall_back_to_the_native_one() {
+        let token = Address::repeat_byte(1);
+        let mut node = node("n", 80);
+        assert!(node_accepts_price(&node, &job(usdc(5), None)));
+
+        node.min_price_per_gpu_hour = Some(0.001);
+        assert!(!node_accepts_price(&node, &job(usdc(5), Some(1.0))));
+
+        node.token_price_floors.insert(token, 4.0);
+        assert!(node_accepts_price(&node, &job(usdc(5), Some(1.0))));
+        assert!(!node_accepts_price(&node, &job(usdc(5), Some(2.0))));
+
+        node.min_price_per_gpu_hour = None;
+        node.token_price_floors = [(Address::repeat_byte(2), 1.0)].into();
+        assert!(!node_accepts_price(&node, &job(usdc(5), Some(1.0))));
+    }
+}
@@ -0,0 +1,346 @@
+use crate::allocation;
+use crate::config::AppConfig;
+use crate::fairshare::FairShare;
+use crate::images;
+use crate::models::{Job, Node, Priority};
+use crate::pricing;
+use crate::state::AppState;
+use crate::validation::parse_deadline;
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+
+/// A queued job that fits a node once `victims` are evicted from it.
+pub struct Preemption {
+    pub job_id: String,
+    pub victims: Vec<String>,
+}
+
+/// Native-currency bounty per hour between submission and the deadline.
+/// Token bounties have no rate, as they cannot be compared with native ones.
+pub fn bounty_rate(job: &Job) -> Option<f64> {
+    if !job.bounty.token.is_native() {
+        return None;
+    }
+    let created = DateTime::parse_from_rfc3339(&job.created_at)
+        .ok()?
+        .with_timezone(&Utc);
+    let deadline = parse_deadline(&job.deadline).ok()?;
+    let hours = (deadline - created).num_seconds() as f64 / 3600.0;
+    Some(job.bounty.to_f64() / hours.max(1.0 / 60.0))
+}
+
+pub fn effective_priority(job: &Job, cfg: &AppConfig) -> Priority {
+    if let Some(priority) = job.priority {
+        return priority;
+    }
+    match bounty_rate(job) {
+        Some(rate) if rate >= cfg.priority_high_rate => Priority::High,
+        Some(rate) if rate < cfg.priority_low_rate => Priority::Low,
+        _ => Priority::Normal,
+    }
+}
+
+/// Pending jobs in dispatch order: highest priority first, then by
+/// submission time pushed back by the owner's fair-share usage.
+pub fn pending_queue<'a>(jobs: &'a [Job], fairshare: &FairShare, cfg: &AppConfig) -> Vec<&'a Job> {
+    let mut queue: Vec<&Job> = jobs.iter().filter(|j| j.status == "pending").collect();
+    queue.sort_by(|a, b| {
+        effective_priority(b, cfg)
+            .cmp(&effective_priority(a, cfg))
+            .then_with(|| {
+                fairshare
+                    .adjusted_created_at(a)
+                    .cmp(&fairshare.adjusted_created_at(b))
+            })
+            .then_with(|| a.created_at.cmp(&b.created_at))
+    });
+    queue
+}
+
+/// The first queued job matching the node's specs that fits its free GPUs and
+/// whose owner is under their concurrency limit.
+pub fn next_for_node(jobs: &[Job], node: &Node, cfg: &AppConfig) -> Option<String> {
+    let fairshare = FairShare::compute(jobs, cfg);
+    pending_queue(jobs, &fairshare, cfg)
+        .into_iter()
+        .find(|j| {
+            j.required_specs == node.gpu_specs
+                && !fairshare.at_limit(&j.owner)
+                && images::node_accepts(node, j)
+                && pricing::node_accepts_price(node, j)
+                && allocation::fits(node, j)
+        })
+        .map(|j| j.job_id.clone())
+}
+
+/// Finds the first queued job that would fit `node` after evicting jobs of
+/// strictly lower priority from it, lowest priority and newest first. Jobs
+/// still being assigned on-chain are never evicted.
+pub fn plan_preemption(jobs: &[Job], node: &Node, cfg: &AppConfig) -> Option<Preemption> {
+    let placed: Vec<&Job> = jobs
+        .iter()
+        .filter(|j| {
+            matches!(j.status.as_str(), "assigned" | "running")
+                && j.assigned_node.as_deref() == Some(node.node_id.as_str())
+        })
+        .collect();
+    if placed.is_empty() {
+        return None;
+    }
+
+    let fairshare = FairShare::compute(jobs, cfg);
+    for candidate in pending_queue(jobs, &fairshare, cfg)
+        .into_iter()
+        .filter(|j| {
+            j.required_specs == node.gpu_specs
+                && !fairshare.at_limit(&j.owner)
+                && images::node_accepts(node, j)
+                && pricing::node_accepts_price(node, j)
+        })
+    {
+        let priority = effective_priority(candidate, cfg);
+        let mut victims: Vec<&Job> = placed
+            .iter()
+            .filter(|j| effective_priority(j, cfg) < priority)
+            .copied()
+            .collect();
+        victims.sort_by(|a, b| {
+            effective_priority(a, cfg)
+                .cmp(&effective_priority(b, cfg))
+                .then_with(|| b.created_at.cmp(&a.created_at))
+        });
+
+        let mut trial = node.clone();
+        let mut evicted = Vec::new();
+        for victim in victims {
+            if allocation::fits(&trial, candidate) {
+                break;
+            }
+            allocation::release(&mut trial, &victim.job_id);
+            evicted.push(victim.job_id.clone());
+        }
+        if !evicted.is_empty() && allocation::fits(&trial, candidate) {
+            return Some(Preemption {
+                job_id: candidate.job_id.clone(),
+                victims: evicted,
+            });
+        }
+    }
+    None
+}
+
+async fn get_queue(data: web::Data<AppState>) -> impl Responder {
+    let jobs = data.jobs.lock().unwrap();
+    let fairshare = FairShare::compute(&jobs, &data.cfg);
+    let entries: Vec<_> = pending_queue(&jobs, &fairshare, &data.cfg)
+        .into_iter()
+        .enumerate()
+        .map(|(i, job)| {
+            json!({
+                "position": i + 1,
+                "priority": effective_priority(job, &data.cfg),
+                "priority_source": if job.priority.is_some() { "submitter" } else { "bounty_rate" },
+                "bounty_per_hour": bounty_rate(job),
+                "fairshare_delay_hours": fairshare.delay_hours(&job.owner),
+                "owner_at_limit": fairshare.at_limit(&job.owner),
+                "job": job,
+            })
+        })
+        .collect();
+
+    HttpResponse::Ok().json(json!({
+        "queue": entries,
+        "preemption_enabled": data.cfg.preemption_enabled,
+    }))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.route("/queue", web::get().to(get_queue));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::{Token, TokenAmount};
+    use chrono::TimeZone;
+    use ethers::types::{Address, U256};
+
+    fn at(minutes: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap() + chrono::Duration::minutes(minutes)
+    }
+
+    fn job(job_id: &str, owner: &str, priority: Option<Priority>, created_minutes: i64) -> Job {
+        let mut job = Job::new(
+            job_id.to_string(),
+            owner.to_string(),
+            None,
+            "train:1".to_string(),
+            TokenAmount::native(U256::exp10(16)),
+            at(created_minutes + 60).to_rfc3339(),
+            "A100".to_string(),
+            16,
+        );
+        job.created_at = at(created_minutes).to_rfc3339();
+        job.priority = priority;
+        job
+    }
+
+    fn placed(node: &mut Node, mut job: Job) -> Job {
+        job.status = "running".to_string();
+        job.assigned_node = Some(node.node_id.clone());
+        assert!(allocation::allocate(node, &job));
+        job
+    }
+
+    fn node(gpus: usize) -> Node {
+        serde_json::from_value(json!({
+            "nodeId": "node-1",
+            "gpuName": null,
+            "gpuSpecs": "A100",
+            "owner": null,
+            "memoryAvailable": 40 * gpus,
+            "devices": (0..gpus).map(|i| json!({ "index": i, "memory": 40 })).collect::<Vec<_>>(),
+        }))
+        .unwrap()
+    }
+
+    fn ids(queue: &[&Job]) -> Vec<String> {
+        queue.iter().map(|j| j.job_id.clone()).collect()
+    }
+
+    #[test]
+    fn bounty_rate_sets_priority_unless_the_submitter_did() {
+        let cfg = AppConfig::for_tests();
+        let mut rich = job("rich", "a", None, 0);
+        rich.bounty = TokenAmount::native(U256::exp10(18) * 2);
+        let normal = job("normal", "a", None, 0);
+        let mut cheap = job("cheap", "a", None, 0);
+        cheap.bounty = TokenAmount::native(U256::exp10(15));
+        assert_eq!(bounty_rate(&rich), Some(2.0));
+        assert_eq!(effective_priority(&rich, &cfg), Priority::High);
+        assert_eq!(effective_priority(&normal, &cfg), Priority::Normal);
+        assert_eq!(effective_priority(&cheap, &cfg), Priority::Low);
+
+        rich.priority = Some(Priority::Low);
+        assert_eq!(effective_priority(&rich, &cfg), Priority::Low);
+    }
+
+    #[test]
+    fn token_bounties_have_no_rate() {
+        let cfg = AppConfig::for_tests();
+        let mut job = job("token", "a", None, 0);
+        let token = Token {
+            symbol: "USDC".to_string(),
+            decimals: 6,
+            address: Some(Address::repeat_byte(1)),
+        };
+        job.bounty = TokenAmount::new(U256::exp10(12), &token);
+        assert_eq!(bounty_rate(&job), None);
+        assert_eq!(effective_priority(&job, &cfg), Priority::Normal);
+    }
+
+    #[test]
+    fn queue_orders_by_priority_then_submission() {
+        let cfg = AppConfig::for_tests();
+        let mut done = job("done", "a", Some(Priority::Urgent), 0);
+        done.status = "completed".to_string();
+        let jobs = vec![
+            job("normal-late", "a", Some(Priority::Normal), 5),
+            job("low", "a", Some(Priority::Low), 0),
+            done,
+            job("normal-early", "a", Some(Priority::Normal), 1),
+            job("high", "a", Some(Priority::High), 9),
+        ];
+        let fairshare = FairShare::compute(&jobs, &cfg);
+        assert_eq!(
+            ids(&pending_queue(&jobs, &fairshare, &cfg)),
+            vec!["high", "normal-early", "normal-late", "low"]
+        );
+    }
+
+    #[test]
+    fn recent_usage_pushes_an_owner_back() {
+        let cfg = AppConfig::for_tests();
+        let mut history = job("history", "heavy", Some(Priority::Normal), 0);
+        history.status = "completed".to_string();
+        history.completed_at = Some(Utc::now());
+        history.gpu_hours = Some(2.0);
+        let jobs = vec![
+            history,
+            job("heavy-first", "heavy", Some(Priority::Normal), 0),
+            job("light-later", "light", Some(Priority::Normal), 60),
+        ];
+        let fairshare = FairShare::compute(&jobs, &cfg);
+        assert_eq!(
+            ids(&pending_queue(&jobs, &fairshare, &cfg)),
+            vec!["light-later", "heavy-first"]
+        );
+    }
+
+    #[test]
+    fn next_for_node_skips_mismatched_full_and_throttled_jobs() {
+        let mut cfg = AppConfig::for_tests();
+        cfg.fairshare_max_running = 1;
+        let mut node = node(2);
+        let running = placed(&mut node, job("running", "busy", Some(Priority::Low), 0));
+        let mut other_specs = job("other-specs", "a", Some(Priority::Urgent), 0);
+        other_specs.required_specs = "H100".to_string();
+        let mut too_big = job("too-big", "a", Some(Priority::Urgent), 1);
+        too_big.gpu_count = 2;
+        let jobs = vec![
+            running,
+            other_specs,
+            too_big,
+            job("throttled", "busy", Some(Priority::High), 0),
+            job("fits", "a", Some(Priority::Low), 0),
+        ];
+        assert_eq!(next_for_node(&jobs, &node, &cfg).as_deref(), Some("fits"));
+    }
+
+    #[test]
+    fn preemption_evicts_the_lowest_and_newest_first() {
+        let cfg = AppConfig::for_tests();
+        let mut node = node(2);
+        let jobs = vec![
+            placed(&mut node, job("low-old", "a", Some(Priority::Low), 0)),
+            placed(&mut node, job("low-new", "a", Some(Priority::Low), 5)),
+            job("high", "b", Some(Priority::High), 10),
+        ];
+        let preemption = plan_preemption(&jobs, &node, &cfg).unwrap();
+        assert_eq!(preemption.job_id, "high");
+        assert_eq!(preemption.victims, vec!["low-new"]);
+    }
+
+    #[test]
+    fn preemption_evicts_as_many_as_the_job_needs() {
+        let cfg = AppConfig::for_tests();
+        let mut node = node(2);
+        let mut urgent = job("urgent", "b", Some(Priority::Urgent), 10);
+        urgent.gpu_count = 2;
+        let jobs = vec![
+            placed(&mut node, job("normal", "a", Some(Priority::Normal), 0)),
+            placed(&mut node, job("low", "a", Some(Priority::Low), 5)),
+            urgent,
+        ];
+        let preemption = plan_preemption(&jobs, &node, &cfg).unwrap();
+        assert_eq!(preemption.victims, vec!["low", "normal"]);
+    }
+
+    #[test]
+    fn preemption_never_evicts_equal_priority_or_assigning_jobs() {
+        let cfg = AppConfig::for_tests();
+        let mut node = node(1);
+        let jobs = vec![
+            placed(&mut node, job("normal", "a", Some(Priority::Normal), 0)),
+            job("peer", "b", Some(Priority::Normal), 10),
+        ];
+        assert!(plan_preemption(&jobs, &node, &cfg).is_none());
+
+        let mut node = self::node(1);
+        let mut assigning = placed(&mut node, job("assigning", "a", Some(Priority::Low), 0));
+        assigning.status = "assigning".to_string();
+        let jobs = vec![assigning, job("high", "b", Some(Priority::High), 10)];
+        assert!(plan_preemption(&jobs, &node, &cfg).is_none());
+    }
+}
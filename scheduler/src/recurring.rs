@@ -0,0 +1,631 @@
+use crate::auth::verify_owner;
+use crate::dataset::DatasetRef;
+use crate::erc20;
+use crate::helper::{save_jobs, save_schedules};
+use crate::history::JobEvent;
+use crate::images;
+use crate::jobs::accept_job;
+use crate::models::{CreateJobRequest, Job};
+use crate::nodes::receipt_status;
+use crate::state::AppState;
+use crate::validation::{self, ValidationErrors};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use ethers::types::U256;
+use ethers::utils::to_checksum;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::{error, field, info, instrument, warn, Span};
+
+const TIMER_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_DEADLINE_HOURS: u64 = 24;
+
+/// A job template materialised at every cron firing, or once at `not_before`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Schedule {
+    pub schedule_id: String,
+    pub owner: String,
+    pub template: CreateJobRequest,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cron: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<DateTime<Utc>>,
+    /// Deadline of each instance, counted from its firing.
+    pub deadline_hours: u64,
+    #[serde(default)]
+    pub paused: bool,
+    /// None once a one-shot schedule has fired.
+    pub next_run: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_run: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub runs: u32,
+    #[serde(default)]
+    pub job_ids: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Body accepted by `POST /schedules`. The template's `jobId` and `deadline`
+/// are replaced for every instance.
+#[derive(Debug, Deserialize)]
+pub struct CreateScheduleRequest {
+    #[serde(rename = "scheduleId")]
+    pub schedule_id: Option<String>,
+    pub template: CreateJobRequest,
+    #[serde(default)]
+    pub cron: Option<String>,
+    #[serde(rename = "notBefore", default)]
+    pub not_before: Option<String>,
+    #[serde(rename = "deadlineHours", default)]
+    pub deadline_hours: Option<i64>,
+}
+
+/// Accepts standard five-field expressions as well as the crate's
+/// six/seven-field form with seconds and years. Five-field expressions keep
+/// their usual day-of-week numbering, Sunday as 0 or 7.
+fn parse_cron(expr: &str) -> Result<cron::Schedule, String> {
+    let fields: Vec<&str> = expr.split_whitespace().collect();
+    let expr = if fields.len() == 5 {
+        format!(
+            "0 {} {}",
+            fields[..4].join(" "),
+            standard_day_of_week(fields[4])
+        )
+    } else {
+        expr.trim().to_string()
+    };
+    cron::Schedule::from_str(&expr).map_err(|e| {
+        let reason = e.to_string();
+        format!(
+            "is not a valid cron expression: {}",
+            reason.lines().last().unwrap_or_default()
+        )
+    })
+}
+
+const WEEKDAYS: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// Rewrites a standard day-of-week field (Sunday = 0 or 7) into the cron
+/// crate's numbering (Sunday = 1), expanding ranges and steps into lists.
+/// Elements it cannot read are passed through for the crate to reject.
+fn standard_day_of_week(field: &str) -> String {
+    field
+        .split(',')
+        .map(|element| standard_day_element(element).unwrap_or_else(|| element.to_string()))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn standard_day_element(element: &str) -> Option<String> {
+    let day = |s: &str| match s.parse::<u32>() {
+        Ok(n) => (n <= 7).then_some(n),
+        Err(_) => WEEKDAYS
+            .iter()
+            .position(|d| d.eq_ignore_ascii_case(s))
+            .map(|i| i as u32),
+    };
+    let (range, step) = match element.split_once('/') {
+        Some((range, step)) => (range, step.parse::<usize>().ok().filter(|s| *s > 0)?),
+        None if element == "*" || element == "?" => return Some(element.to_string()),
+        None => (element, 1),
+    };
+    let (first, last) = match range.split_once('-') {
+        Some((first, last)) => (day(first)?, day(last)?),
+        None if range == "*" || range == "?" => (0, 6),
+        None if range.len() < element.len() => (day(range)?, 6),
+        None => (day(range)?, day(range)?),
+    };
+    if first > last {
+        return None;
+    }
+    let mut days: Vec<u32> = (first..=last).step_by(step).map(|d| d % 7 + 1).collect();
+    days.sort_unstable();
+    days.dedup();
+    Some(
+        days.iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>()
+            .join(","),
+    )
+}
+
+fn next_cron_fire(expr: &str, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    parse_cron(expr).ok()?.after(&after).next()
+}
+
+fn instance_request(schedule: &Schedule, fired_at: DateTime<Utc>) -> CreateJobRequest {
+    let mut request = schedule.template.clone();
+    request.job_id = Some(format!("{}-{}", schedule.schedule_id, schedule.runs));
+    request.deadline = Some(
+        (fired_at + ChronoDuration::hours(schedule.deadline_hours as i64)).to_rfc3339(),
+    );
+    request
+}
+
+impl CreateScheduleRequest {
+    fn into_schedule(
+        self,
+        existing: &[Schedule],
+        jobs: &[Job],
+        escrow_wallet: &str,
+    ) -> Result<Schedule, ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        let now = Utc::now();
+
+        let schedule_id = errors
+            .check("scheduleId", validation::required(&self.schedule_id))
+            .map(str::to_string);
+        if let Some(id) = &schedule_id {
+            errors.check("scheduleId", validation::identifier(id));
+            if existing.iter().any(|s| &s.schedule_id == id) {
+                errors.add("scheduleId", "already exists");
+            }
+        }
+
+        let cron = self.cron.map(|c| c.trim().to_string());
+        if let Some(expr) = &cron {
+            errors.check("cron", parse_cron(expr));
+        }
+        let not_before = self
+            .not_before
+            .as_deref()
+            .and_then(|t| errors.check("notBefore", validation::parse_deadline(t)));
+        if cron.is_none() && self.not_before.is_none() {
+            errors.add("cron", "provide a cron expression, notBefore, or both");
+        }
+
+        let deadline_hours = match self.deadline_hours {
+            Some(h) if !(1..=24 * 365).contains(&h) => {
+                errors.add("deadlineHours", "must be between 1 and 8760");
+                None
+            }
+            Some(h) => Some(h as u64),
+            None => Some(DEFAULT_DEADLINE_HOURS),
+        };
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let mut schedule = Schedule {
+            schedule_id: schedule_id.unwrap(),
+            owner: String::new(),
+            template: self.template,
+            next_run: match &cron {
+                Some(expr) => next_cron_fire(expr, not_before.map_or(now, |nb| nb.max(now))),
+                None => not_before,
+            },
+            cron,
+            not_before,
+            deadline_hours: deadline_hours.unwrap(),
+            paused: false,
+            last_run: None,
+            last_error: None,
+            runs: 0,
+            job_ids: Vec::new(),
+            created_at: now,
+        };
+
+        // Validate the template the way its first instance will be.
+        let job = instance_request(&schedule, now)
+            .into_job(jobs)
+            .map_err(|e| e.prefixed("template"))?;
+        if !job.owner.eq_ignore_ascii_case(escrow_wallet) {
+            let mut errors = ValidationErrors::default();
+            errors.add(
+                "template.owner",
+                format!(
+                    "must be the scheduler's escrow wallet {}, which funds every instance",
+                    escrow_wallet
+                ),
+            );
+            return Err(errors);
+        }
+        schedule.owner = job.owner;
+        Ok(schedule)
+    }
+}
+
+/// Creates the instance on-chain from the scheduler's owner wallet, escrowing
+/// its bounty as `msg.value`, or through `createTokenJob` for token bounties.
+#[instrument(skip_all, fields(job_id = %job.job_id, tx_hash = field::Empty))]
+async fn escrow_on_chain(data: &web::Data<AppState>, job: &Job) -> Result<(), String> {
+    let deadline = validation::parse_deadline(&job.deadline)?.timestamp().max(0) as u64;
+    let dataset = job.dataset.as_ref().map(DatasetRef::uri).unwrap_or_default();
+    let contract = &data.cfg.owner_contract;
+    let (method, call) = match job.bounty.token.address {
+        None => (
+            "createJob",
+            contract
+                .method::<_, ()>(
+                    "createJob",
+                    (
+                        job.job_id.clone(),
+                        dataset,
+                        job.container_cid.clone(),
+                        U256::from(deadline),
+                        job.required_specs.clone(),
+                        U256::from(job.min_memory),
+                    ),
+                )
+                .map(|call| call.value(job.bounty.units)),
+        ),
+        Some(token) => {
+            erc20::check_funding(&data.cfg, contract.client().address(), &job.bounty).await?;
+            (
+                "createTokenJob",
+                contract.method::<_, ()>(
+                    "createTokenJob",
+                    (
+                        job.job_id.clone(),
+                        dataset,
+                        job.container_cid.clone(),
+                        U256::from(deadline),
+                        job.required_specs.clone(),
+                        U256::from(job.min_memory),
+                        token,
+                        job.bounty.units,
+                    ),
+                ),
+            )
+        }
+    };
+    let call = call.map_err(|e| format!("Failed to prepare {} call: {}", method, e))?;
+    let timer = data.metrics.contract_call(method);
+
+    let pending = match call.send().await {
+        Ok(pending) => pending,
+        Err(e) => {
+            data.history
+                .record(JobEvent::transaction(&job.job_id, method, None, "failed").amount(&job.bounty));
+            return Err(format!("{} send failed: {}", method, e));
+        }
+    };
+    let hash = Some(format!("{:?}", pending.tx_hash()));
+    Span::current().record("tx_hash", hash.as_deref());
+    data.history.record(
+        JobEvent::transaction(&job.job_id, method, hash.clone(), "submitted")
+            .amount(&job.bounty),
+    );
+
+    let receipt = pending.await;
+    let status = match &receipt {
+        Ok(r) => receipt_status(r.as_ref()),
+        Err(_) => "failed",
+    };
+    data.history
+        .record(JobEvent::transaction(&job.job_id, method, hash, status).amount(&job.bounty));
+    match receipt {
+        Ok(_) if status == "confirmed" => {
+            timer.succeeded();
+            Ok(())
+        }
+        Ok(_) => Err(format!("{} transaction {}", method, status)),
+        Err(e) => Err(format!("Awaiting {} receipt failed: {}", method, e)),
+    }
+}
+
+fn record_outcome(data: &web::Data<AppState>, schedule_id: &str, result: Result<String, String>) {
+    let mut schedules = data.schedules.lock().unwrap();
+    if let Some(schedule) = schedules.iter_mut().find(|s| s.schedule_id == schedule_id) {
+        match result {
+            Ok(job_id) => {
+                schedule.job_ids.push(job_id);
+                schedule.last_error = None;
+            }
+            Err(error) => {
+                error!("Schedule {} failed to fire: {}", schedule_id, error);
+                schedule.last_error = Some(error);
+            }
+        }
+        save_schedules(&schedules);
+    }
+}
+
+#[instrument(skip_all, fields(schedule_id = %schedule_id, job_id = field::Empty))]
+async fn materialise(
+    data: &web::Data<AppState>,
+    schedule_id: &str,
+    mut request: CreateJobRequest,
+) {
+    // The policy may have changed since the schedule was created.
+    let image_size = match images::admit(data, request.container_cid.as_deref()).await {
+        Ok(size) => size,
+        Err(errors) => {
+            record_outcome(
+                data,
+                schedule_id,
+                Err(format!(
+                    "Template image is rejected by the image policy: {}",
+                    errors.summary()
+                )),
+            );
+            return;
+        }
+    };
+    if let Err(errors) = erc20::admit(data, &mut request, 1).await {
+        record_outcome(
+            data,
+            schedule_id,
+            Err(format!("Template bounty cannot be funded: {}", errors.summary())),
+        );
+        return;
+    }
+    let job = {
+        let jobs = data.jobs.lock().unwrap();
+        request.into_job(&jobs)
+    };
+    let mut job = match job {
+        Ok(job) => job,
+        Err(errors) => {
+            record_outcome(
+                data,
+                schedule_id,
+                Err(format!("Template no longer validates: {}", errors.summary())),
+            );
+            return;
+        }
+    };
+
+    job.image_size = image_size;
+    Span::current().record("job_id", job.job_id.as_str());
+
+    if let Err(e) = escrow_on_chain(data, &job).await {
+        record_outcome(data, schedule_id, Err(e));
+        return;
+    }
+
+    let job_id = {
+        let mut jobs = data.jobs.lock().unwrap();
+        let message = format!("Materialised from schedule {}", schedule_id);
+        let job = accept_job(data, &mut jobs, job, message);
+        save_jobs(&jobs);
+        job.job_id
+    };
+    info!("Schedule {} created job {}", schedule_id, job_id);
+    record_outcome(data, schedule_id, Ok(job_id));
+}
+
+/// Fires every due schedule once. Firings missed while the scheduler was down
+/// collapse into a single catch-up run.
+pub async fn fire_due_schedules(data: &web::Data<AppState>) {
+    let now = Utc::now();
+    let due: Vec<(String, CreateJobRequest)> = {
+        let mut schedules = data.schedules.lock().unwrap();
+        let mut due = Vec::new();
+        for schedule in schedules
+            .iter_mut()
+            .filter(|s| !s.paused && s.next_run.is_some_and(|t| t <= now))
+        {
+            due.push((schedule.schedule_id.clone(), instance_request(schedule, now)));
+            schedule.runs += 1;
+            schedule.last_run = Some(now);
+            schedule.next_run = schedule
+                .cron
+                .as_deref()
+                .and_then(|expr| next_cron_fire(expr, now));
+        }
+        if !due.is_empty() {
+            save_schedules(&schedules);
+        }
+        due
+    };
+
+    for (schedule_id, request) in due {
+        info!("Firing schedule {}", schedule_id);
+        materialise(data, &schedule_id, request).await;
+    }
+}
+
+pub async fn run_schedule_timer(data: web::Data<AppState>) {
+    let mut interval = tokio::time::interval(TIMER_INTERVAL);
+    loop {
+        interval.tick().await;
+        fire_due_schedules(&data).await;
+    }
+}
+
+/// Instances are escrowed on-chain by the scheduler's owner wallet, the only
+/// one it can sign `createJob` with, so a template's owner must be that wallet
+/// and only the operator holding it can create schedules. Owners paying from
+/// their own wallets submit each job themselves.
+async fn create_schedule(
+    http_req: HttpRequest,
+    req: web::Json<CreateScheduleRequest>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let mut req = req.into_inner();
+    if let Err(errors) = images::admit(&data, req.template.container_cid.as_deref()).await {
+        warn!("Rejected schedule: image policy");
+        return errors.prefixed("template").into_response();
+    }
+    if let Err(errors) = erc20::admit(&data, &mut req.template, 1).await {
+        warn!("Rejected schedule: token bounty");
+        return errors.prefixed("template").into_response();
+    }
+    let escrow_wallet = to_checksum(&data.cfg.owner_contract.client().address(), None);
+    let schedule = {
+        let jobs = data.jobs.lock().unwrap();
+        let schedules = data.schedules.lock().unwrap();
+        req.into_schedule(&schedules, &jobs, &escrow_wallet)
+    };
+    let schedule = match schedule {
+        Ok(s) => s,
+        Err(errors) => {
+            warn!("Rejected schedule: validation failed");
+            return errors.into_response();
+        }
+    };
+    if let Err(resp) = verify_owner(&http_req, &schedule.owner) {
+        return *resp;
+    }
+
+    let mut schedules = data.schedules.lock().unwrap();
+    if schedules.iter().any(|s| s.schedule_id == schedule.schedule_id) {
+        return HttpResponse::Conflict().json(json!({ "error": "Schedule already exists" }));
+    }
+    schedules.push(schedule.clone());
+    save_schedules(&schedules);
+    info!(
+        "Created schedule {} (next run {:?})",
+        schedule.schedule_id, schedule.next_run
+    );
+    HttpResponse::Created().json(schedule)
+}
+
+async fn get_schedules(data: web::Data<AppState>) -> impl Responder {
+    let schedules = data.schedules.lock().unwrap();
+    HttpResponse::Ok().json(&*schedules)
+}
+
+async fn get_schedule(
+    schedule_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let schedules = data.schedules.lock().unwrap();
+    match schedules.iter().find(|s| s.schedule_id == *schedule_id) {
+        Some(s) => HttpResponse::Ok().json(s),
+        None => HttpResponse::NotFound().json(json!({ "error": "Schedule not found" })),
+    }
+}
+
+/// Applies `change` to an owner-authenticated schedule and persists it.
+fn update_schedule(
+    http_req: &HttpRequest,
+    schedule_id: &str,
+    data: &web::Data<AppState>,
+    change: impl FnOnce(&mut Schedule),
+) -> HttpResponse {
+    let mut schedules = data.schedules.lock().unwrap();
+    let schedule = match schedules.iter_mut().find(|s| s.schedule_id == schedule_id) {
+        Some(s) => s,
+        None => return HttpResponse::NotFound().json(json!({ "error": "Schedule not found" })),
+    };
+    if let Err(resp) = verify_owner(http_req, &schedule.owner) {
+        return *resp;
+    }
+    change(schedule);
+    let updated = schedule.clone();
+    save_schedules(&schedules);
+    HttpResponse::Ok().json(updated)
+}
+
+async fn pause_schedule(
+    http_req: HttpRequest,
+    schedule_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    info!("Pausing schedule {}", schedule_id);
+    update_schedule(&http_req, &schedule_id, &data, |s| s.paused = true)
+}
+
+async fn resume_schedule(
+    http_req: HttpRequest,
+    schedule_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    info!("Resuming schedule {}", schedule_id);
+    update_schedule(&http_req, &schedule_id, &data, |s| {
+        s.paused = false;
+        // Skip the firings that fell inside the pause.
+        if let Some(expr) = &s.cron {
+            s.next_run = next_cron_fire(expr, Utc::now());
+        }
+    })
+}
+
+async fn delete_schedule(
+    http_req: HttpRequest,
+    schedule_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let mut schedules = data.schedules.lock().unwrap();
+    let idx = match schedules.iter().position(|s| s.schedule_id == *schedule_id) {
+        Some(i) => i,
+        None => return HttpResponse::NotFound().json(json!({ "error": "Schedule not found" })),
+    };
+    if let Err(resp) = verify_owner(&http_req, &schedules[idx].owner) {
+        return *resp;
+    }
+    let removed = schedules.remove(idx);
+    save_schedules(&schedules);
+    info!("Deleted schedule {}", removed.schedule_id);
+    HttpResponse::Ok().json(json!({ "success": true, "schedule": removed }))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/schedules")
+            .route("", web::get().to(get_schedules))
+            .route("", web::post().to(create_schedule))
+            .route("/{id}", web::get().to(get_schedule))
+            .route("/{id}", web::delete().to(delete_schedule))
+            .route("/{id}/pause", web::post().to(pause_schedule))
+            .route("/{id}/resume", web::post().to(resume_schedule)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Datelike, TimeZone, Weekday};
+
+    fn weekdays(expr: &str) -> Vec<Weekday> {
+        // A Monday.
+        let start = Utc.with_ymd_and_hms(2026, 10, 19, 0, 0, 0).unwrap();
+        let mut days: Vec<Weekday> = parse_cron(expr)
+            .unwrap()
+            .after(&start)
+            .take(7)
+            .map(|t| t.weekday())
+            .collect();
+        days.sort_by_key(|d| d.num_days_from_sunday());
+        days.dedup();
+        days
+    }
+
+    #[test]
+    fn sunday_is_zero_or_seven() {
+        assert_eq!(weekdays("0 9 * * 0"), vec![Weekday::Sun]);
+        assert_eq!(weekdays("0 9 * * 7"), vec![Weekday::Sun]);
+    }
+
+    #[test]
+    fn numbered_days_keep_their_standard_meaning() {
+        assert_eq!(weekdays("0 9 * * 1"), vec![Weekday::Mon]);
+        assert_eq!(weekdays("0 9 * * 6"), vec![Weekday::Sat]);
+    }
+
+    #[test]
+    fn ranges_and_lists_are_converted() {
+        use Weekday::*;
+        assert_eq!(weekdays("0 9 * * 1-5"), vec![Mon, Tue, Wed, Thu, Fri]);
+        assert_eq!(weekdays("0 9 * * 5-7"), vec![Sun, Fri, Sat]);
+        assert_eq!(weekdays("0 9 * * 0,3"), vec![Sun, Wed]);
+        assert_eq!(weekdays("0 9 * * 1-5/2"), vec![Mon, Wed, Fri]);
+        assert_eq!(weekdays("0 9 * * */3"), vec![Sun, Wed, Sat]);
+    }
+
+    #[test]
+    fn named_days_are_accepted() {
+        use Weekday::*;
+        assert_eq!(weekdays("0 9 * * MON-FRI"), vec![Mon, Tue, Wed, Thu, Fri]);
+        assert_eq!(weekdays("0 9 * * sun"), vec![Sun]);
+        assert_eq!(weekdays("0 9 * * *").len(), 7);
+    }
+
+    #[test]
+    fn six_field_expressions_use_the_crate_numbering() {
+        assert_eq!(weekdays("0 0 9 * * 1"), vec![Weekday::Sun]);
+    }
+
+    #[test]
+    fn out_of_range_days_are_rejected() {
+        assert!(parse_cron("0 9 * * 8").is_err());
+        assert!(parse_cron("0 9 * * 5-2").is_err());
+    }
+}
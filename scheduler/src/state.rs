@@ -1,34 +1,43 @@
-use crate::config::AppConfig;
-use crate::models::{Job, Node};
-use std::fs;
-use std::sync::{Arc, Mutex};
+use crate::allocation;
+use crate::config::{AppConfig, Settings};
+use crate::events::EventBus;
+use crate::history::JobHistory;
+use crate::metrics::{Metrics, TimedMutex};
+use crate::models::{Job, Lease, Node};
+use crate::recurring::Schedule;
+use crate::store::Store;
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct AppState {
-    pub jobs: Arc<Mutex<Vec<Job>>>,
-    pub nodes: Arc<Mutex<Vec<Node>>>,
+    pub jobs: Arc<TimedMutex<Vec<Job>>>,
+    pub nodes: Arc<TimedMutex<Vec<Node>>>,
+    pub leases: Arc<TimedMutex<Vec<Lease>>>,
+    pub schedules: Arc<TimedMutex<Vec<Schedule>>>,
     pub cfg: Arc<AppConfig>,
+    pub events: EventBus,
+    pub history: Arc<JobHistory>,
+    pub metrics: Arc<Metrics>,
 }
 
 impl AppState {
-    pub async fn new() -> anyhow::Result<Self> {
-        let cfg = AppConfig::new().await?; 
-        let jobs_file_path = "jobs.json";
-        let nodes_file_path = "nodes.json";
-        println!("[INFO] Attempting to load jobs from: {}", jobs_file_path);
-        let jobs_content = fs::read_to_string(jobs_file_path)?;
-        let loaded_jobs: Vec<Job> = serde_json::from_str(&jobs_content)?;
-        println!("[INFO] Successfully loaded {} initial jobs.", loaded_jobs.len());
+    pub async fn new(settings: &Settings) -> anyhow::Result<Self> {
+        let cfg = AppConfig::new(settings).await?;
+        let mut store = Store::load()?;
+        allocation::reconcile(&store.jobs, &mut store.nodes);
 
-        println!("[INFO] Attempting to load nodes from: {}", nodes_file_path);
-        let nodes_content = fs::read_to_string(nodes_file_path)?;
-        let loaded_nodes: Vec<Node> = serde_json::from_str(&nodes_content)?;
-        println!("[INFO] Successfully loaded {} initial nodes.", loaded_nodes.len());
+        let history = JobHistory::load()?;
+        let metrics = Metrics::new()?;
 
         Ok(Self {
-            jobs: Arc::new(Mutex::new(loaded_jobs)),
-            nodes: Arc::new(Mutex::new(loaded_nodes)),
+            jobs: Arc::new(metrics.timed_mutex("jobs", store.jobs)),
+            nodes: Arc::new(metrics.timed_mutex("nodes", store.nodes)),
+            leases: Arc::new(metrics.timed_mutex("leases", store.leases)),
+            schedules: Arc::new(metrics.timed_mutex("schedules", store.schedules)),
             cfg: Arc::new(cfg),
+            events: EventBus::new(),
+            history: Arc::new(history),
+            metrics: Arc::new(metrics),
         })
     }
 }
@@ -0,0 +1,135 @@
+use crate::config::storage;
+use crate::helper::{save_jobs, save_leases, save_schedules};
+use crate::history::JobEvent;
+use crate::models::{Job, Lease, Node};
+use crate::nodes::save_nodes;
+use crate::recurring::Schedule;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use tracing::info;
+
+/// Format version written into exports.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Everything the scheduler persists, as held in the storage directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Store {
+    pub jobs: Vec<Job>,
+    pub nodes: Vec<Node>,
+    #[serde(default)]
+    pub leases: Vec<Lease>,
+    #[serde(default)]
+    pub schedules: Vec<Schedule>,
+}
+
+/// A `Store` as exported by `gpu-scheduler export` or `GET /admin/export`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    #[serde(rename = "exportedAt")]
+    pub exported_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub store: Store,
+    #[serde(default)]
+    pub history: Vec<JobEvent>,
+}
+
+fn read_required<T: DeserializeOwned>(path: &Path) -> anyhow::Result<Vec<T>> {
+    info!("Attempting to load {}", path.display());
+    let content = fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("cannot read {}: {}", path.display(), e))?;
+    serde_json::from_str(&content)
+        .map_err(|e| anyhow::anyhow!("cannot parse {}: {}", path.display(), e))
+}
+
+fn read_optional<T: DeserializeOwned>(path: &Path) -> anyhow::Result<Vec<T>> {
+    match fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("cannot parse {}: {}", path.display(), e)),
+        Err(_) => Ok(Vec::new()),
+    }
+}
+
+impl Store {
+    /// Reads the configured storage files. Jobs and nodes must exist; leases
+    /// and schedules start empty when missing. Stored formats from older
+    /// versions are migrated as they are read.
+    pub fn load() -> anyhow::Result<Self> {
+        let paths = storage();
+        let store = Store {
+            jobs: read_required(&paths.jobs_path())?,
+            nodes: read_required(&paths.nodes_path())?,
+            leases: read_optional(&paths.leases_path())?,
+            schedules: read_optional(&paths.schedules_path())?,
+        };
+        info!(
+            "Loaded {} jobs, {} nodes, {} leases and {} schedules",
+            store.jobs.len(),
+            store.nodes.len(),
+            store.leases.len(),
+            store.schedules.len()
+        );
+        Ok(store)
+    }
+
+    /// Whether the storage directory already holds a job or node list.
+    pub fn exists() -> bool {
+        let paths = storage();
+        paths.jobs_path().exists() || paths.nodes_path().exists()
+    }
+
+    pub fn save(&self) {
+        save_jobs(&self.jobs);
+        save_nodes(&self.nodes);
+        save_leases(&self.leases);
+        save_schedules(&self.schedules);
+    }
+
+    pub fn snapshot(self, history: Vec<JobEvent>) -> Snapshot {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            exported_at: Utc::now(),
+            store: self,
+            history,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty() && self.nodes.is_empty()
+    }
+
+    /// Rejects duplicate ids, which every lookup by id assumes away.
+    pub fn check_unique(&self) -> Result<(), String> {
+        fn first_duplicate<'a>(ids: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+            let mut seen = HashSet::new();
+            ids.into_iter().find(|id| !seen.insert(*id))
+        }
+        if let Some(id) = first_duplicate(self.jobs.iter().map(|j| j.job_id.as_str())) {
+            return Err(format!("job {} appears more than once", id));
+        }
+        if let Some(id) = first_duplicate(self.nodes.iter().map(|n| n.node_id.as_str())) {
+            return Err(format!("node {} appears more than once", id));
+        }
+        if let Some(id) = first_duplicate(self.schedules.iter().map(|s| s.schedule_id.as_str())) {
+            return Err(format!("schedule {} appears more than once", id));
+        }
+        Ok(())
+    }
+}
+
+impl Snapshot {
+    /// Whether this scheduler can restore the snapshot as is.
+    pub fn check(&self) -> Result<(), String> {
+        if self.version > SNAPSHOT_VERSION {
+            return Err(format!(
+                "snapshot version {} is newer than this scheduler supports ({})",
+                self.version, SNAPSHOT_VERSION
+            ));
+        }
+        self.store.check_unique()
+    }
+}
@@ -0,0 +1,144 @@
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use serde::{Deserialize, Serialize};
+use std::io::IsTerminal;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter};
+
+/// How log lines are written to stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable, for a terminal.
+    Pretty,
+    /// One JSON object per event, with the fields of every enclosing span.
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            _ => Err("must be pretty or json".to_string()),
+        }
+    }
+}
+
+/// The `[logging]` settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    /// An `EnvFilter` directive such as `info` or `gpu_scheduler=debug`.
+    pub filter: String,
+    /// OTLP/HTTP collector base URL; spans are only exported when set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            format: LogFormat::Pretty,
+            filter: "info".to_string(),
+            otlp_endpoint: None,
+            service_name: "gpu-scheduler".to_string(),
+        }
+    }
+}
+
+/// Flushes exported spans when dropped; keep it alive for the whole run.
+pub struct Telemetry {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Failed to flush spans: {}", e);
+            }
+        }
+    }
+}
+
+fn tracer_provider(cfg: &LogConfig, endpoint: &str) -> Result<SdkTracerProvider, String> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let resource = Resource::builder()
+        .with_service_name(cfg.service_name.clone())
+        .with_attribute(KeyValue::new("service.version", env!("CARGO_PKG_VERSION")))
+        .build();
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource)
+        .build())
+}
+
+/// Installs the global subscriber: formatted events on stdout and, when an
+/// OTLP endpoint is configured, every span exported to it.
+pub fn init(cfg: &LogConfig) -> Telemetry {
+    let filter = EnvFilter::try_new(&cfg.filter).unwrap_or_else(|e| {
+        eprintln!("Invalid logging.filter {:?} ({}); using info", cfg.filter, e);
+        EnvFilter::new("info")
+    });
+    let (fmt_pretty, fmt_json) = match cfg.format {
+        LogFormat::Pretty => (Some(fmt::layer()), None),
+        LogFormat::Json => (
+            None,
+            Some(fmt::layer().json().flatten_event(true).with_current_span(false)),
+        ),
+    };
+
+    let mut export_error = None;
+    let provider = cfg
+        .otlp_endpoint
+        .as_deref()
+        .and_then(|endpoint| match tracer_provider(cfg, endpoint) {
+            Ok(provider) => Some(provider),
+            Err(e) => {
+                export_error = Some(format!("Span export to {} disabled: {}", endpoint, e));
+                None
+            }
+        });
+    let otel = provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer().with_tracer(provider.tracer(cfg.service_name.clone()))
+    });
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt_pretty)
+        .with(fmt_json)
+        .with(otel)
+        .init();
+    if let Some(e) = export_error {
+        tracing::error!("{}", e);
+    }
+    Telemetry { provider }
+}
+
+/// Warnings and errors on stderr, for the operator subcommands, whose
+/// output goes to stdout.
+pub fn init_cli() {
+    let filter = EnvFilter::try_from_env("RUST_LOG").unwrap_or_else(|_| EnvFilter::new("warn"));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(
+            fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_ansi(std::io::stderr().is_terminal())
+                .without_time(),
+        )
+        .init();
+}
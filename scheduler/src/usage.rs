@@ -0,0 +1,293 @@
+use crate::allocation;
+use crate::helper::save_jobs;
+use crate::history::{JobEvent, JobEventKind};
+use crate::leases::release_job_leases;
+use crate::models::Job;
+use crate::nodes::{save_nodes, update_job_state_to_failed, update_node_status};
+use crate::state::AppState;
+use crate::validation::{self, ValidationErrors};
+use crate::workflow;
+use actix_web::{web, HttpResponse, Responder};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use tracing::{info_span, warn};
+
+const MAX_CPU_CORES: f64 = 1024.0;
+const MAX_DISK_GB: f64 = 100_000.0;
+const MAX_RUNTIME_SECS: f64 = 30.0 * 24.0 * 3600.0;
+
+/// CPU, memory, disk and wall-clock figures. A job's `limits` caps each one;
+/// the `usage` its node reports holds the peaks actually reached.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Resources {
+    #[serde(rename = "cpuCores", alias = "cpu_cores", default, skip_serializing_if = "Option::is_none")]
+    pub cpu_cores: Option<f64>,
+    /// System RAM.
+    #[serde(rename = "memoryGb", alias = "memory_gb", default, skip_serializing_if = "Option::is_none")]
+    pub memory_gb: Option<f64>,
+    #[serde(rename = "diskGb", alias = "disk_gb", default, skip_serializing_if = "Option::is_none")]
+    pub disk_gb: Option<f64>,
+    #[serde(rename = "gpuMemoryGb", alias = "gpu_memory_gb", default, skip_serializing_if = "Option::is_none")]
+    pub gpu_memory_gb: Option<f64>,
+    #[serde(rename = "runtimeSecs", alias = "runtime_secs", default, skip_serializing_if = "Option::is_none")]
+    pub runtime_secs: Option<f64>,
+}
+
+/// What happens to a job whose usage exceeds its limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitEnforcement {
+    /// Record the overrun and keep the job's outcome.
+    Flag,
+    /// Fail the job, withholding its result from the contract.
+    Fail,
+}
+
+impl std::str::FromStr for LimitEnforcement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "flag" => Ok(LimitEnforcement::Flag),
+            "fail" => Ok(LimitEnforcement::Fail),
+            _ => Err("must be flag or fail".to_string()),
+        }
+    }
+}
+
+impl Resources {
+    pub fn is_empty(&self) -> bool {
+        *self == Resources::default()
+    }
+
+    fn fields(&self) -> [(&'static str, Option<f64>, f64); 5] {
+        [
+            ("cpuCores", self.cpu_cores, MAX_CPU_CORES),
+            ("memoryGb", self.memory_gb, validation::MAX_MEMORY_GB as f64),
+            ("diskGb", self.disk_gb, MAX_DISK_GB),
+            ("gpuMemoryGb", self.gpu_memory_gb, validation::MAX_MEMORY_GB as f64),
+            ("runtimeSecs", self.runtime_secs, MAX_RUNTIME_SECS),
+        ]
+    }
+
+    /// Limits must be positive and within what any node could offer.
+    pub fn validate_limits(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        for (field, value, max) in self.fields() {
+            if let Some(v) = value {
+                if !v.is_finite() || v <= 0.0 || v > max {
+                    errors.add(field, format!("must be greater than 0 and at most {}", max));
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn validate_usage(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        for (field, value, _) in self.fields() {
+            if value.is_some_and(|v| !v.is_finite() || v < 0.0) {
+                errors.add(field, "must be a non-negative number");
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Describes every limit `usage` went over. The wall-clock time measured by
+/// the scheduler stands in when the node did not report one.
+pub fn overruns(limits: &Resources, usage: &Resources, measured_secs: Option<f64>) -> Vec<String> {
+    let used = Resources {
+        runtime_secs: usage.runtime_secs.or(measured_secs),
+        ..usage.clone()
+    };
+    limits
+        .fields()
+        .iter()
+        .zip(used.fields())
+        .filter_map(|((field, limit, _), (_, used, _))| match (limit, used) {
+            (Some(limit), Some(used)) if used > *limit => {
+                Some(format!("{}: used {} of {}", field, used, limit))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Seconds between the job starting and now, or its completion.
+pub fn measured_runtime(job: &Job) -> Option<f64> {
+    let end = job.completed_at.unwrap_or_else(Utc::now);
+    job.started_at
+        .map(|started| (end - started).num_milliseconds().max(0) as f64 / 1000.0)
+}
+
+/// Flags or fails assigned or running jobs that have passed their wall-clock
+/// limit, without waiting for the node to report.
+pub fn enforce_runtime_limits(data: &web::Data<AppState>) {
+    let mut jobs = data.jobs.lock().unwrap();
+    let over: Vec<usize> = jobs
+        .iter()
+        .enumerate()
+        .filter(|(_, j)| {
+            matches!(j.status.as_str(), "assigned" | "running")
+                && !j.overruns.iter().any(|o| o.starts_with("runtimeSecs"))
+                && j.limits
+                    .runtime_secs
+                    .zip(measured_runtime(j))
+                    .is_some_and(|(limit, ran)| ran > limit)
+        })
+        .map(|(i, _)| i)
+        .collect();
+    if over.is_empty() {
+        return;
+    }
+
+    let mut nodes = data.nodes.lock().unwrap();
+    let mut failed = Vec::new();
+    for i in over {
+        let _span = info_span!("job", job_id = %jobs[i].job_id).entered();
+        let job = &mut jobs[i];
+        let limit = job.limits.runtime_secs.unwrap_or_default();
+        let overrun = format!(
+            "runtimeSecs: used {} of {}",
+            measured_runtime(job).unwrap_or_default().round(),
+            limit
+        );
+        job.overruns.push(overrun.clone());
+        data.history.record(JobEvent::new(
+            &job.job_id,
+            JobEventKind::LimitExceeded,
+            overrun,
+        ));
+
+        if data.cfg.limit_enforcement == LimitEnforcement::Fail {
+            let node_id = job.assigned_node.clone().unwrap_or_default();
+            let gpu_share = nodes
+                .iter()
+                .find(|n| n.node_id == node_id)
+                .map(|n| allocation::gpu_share(n, &job.job_id))
+                .filter(|share| *share > 0.0)
+                .unwrap_or(job.gpu_count as f64);
+            let error = format!("Exceeded its wall-clock limit of {}s", limit);
+            update_job_state_to_failed(job, &error, gpu_share);
+            data.history.record(
+                JobEvent::new(&job.job_id, JobEventKind::Failed, error).node(&node_id),
+            );
+            if let Some(node) = update_node_status(&mut nodes, &node_id, &job.job_id) {
+                data.events.publish_node(node);
+            }
+            failed.push(job.job_id.clone());
+        } else {
+            warn!(
+                "Job {} is past its wall-clock limit of {}s",
+                job.job_id, limit
+            );
+        }
+        data.events.publish_job(&jobs[i]);
+    }
+    for job_id in &failed {
+        workflow::cancel_dependents(data, &mut jobs, job_id);
+    }
+    save_jobs(&jobs);
+    save_nodes(&nodes);
+    drop(nodes);
+    drop(jobs);
+    for job_id in &failed {
+        let _span = info_span!("job", job_id = %job_id).entered();
+        release_job_leases(data, job_id);
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+struct OwnerUsage {
+    jobs: usize,
+    /// Jobs whose node reported usage.
+    reported: usize,
+    gpu_hours: f64,
+    cpu_core_hours: f64,
+    runtime_hours: f64,
+    peak_memory_gb: f64,
+    peak_gpu_memory_gb: f64,
+    peak_disk_gb: f64,
+    over_limit: usize,
+}
+
+impl OwnerUsage {
+    fn add(&mut self, job: &Job) {
+        self.jobs += 1;
+        self.gpu_hours += job.gpu_hours.unwrap_or(0.0);
+        if !job.overruns.is_empty() {
+            self.over_limit += 1;
+        }
+        let usage = match &job.usage {
+            Some(u) => u,
+            None => return,
+        };
+        self.reported += 1;
+        let hours = usage.runtime_secs.or(measured_runtime(job)).unwrap_or(0.0) / 3600.0;
+        self.runtime_hours += hours;
+        self.cpu_core_hours += usage.cpu_cores.unwrap_or(0.0) * hours;
+        self.peak_memory_gb = self.peak_memory_gb.max(usage.memory_gb.unwrap_or(0.0));
+        self.peak_gpu_memory_gb = self
+            .peak_gpu_memory_gb
+            .max(usage.gpu_memory_gb.unwrap_or(0.0));
+        self.peak_disk_gb = self.peak_disk_gb.max(usage.disk_gb.unwrap_or(0.0));
+    }
+}
+
+fn finished(job: &Job) -> bool {
+    matches!(job.status.as_str(), "completed" | "failed")
+}
+
+async fn get_usage(data: web::Data<AppState>) -> impl Responder {
+    let jobs = data.jobs.lock().unwrap();
+    let mut owners: BTreeMap<String, OwnerUsage> = BTreeMap::new();
+    for job in jobs.iter().filter(|j| finished(j)) {
+        owners.entry(job.owner.to_lowercase()).or_default().add(job);
+    }
+    HttpResponse::Ok().json(json!({
+        "limit_enforcement": data.cfg.limit_enforcement,
+        "owners": owners,
+    }))
+}
+
+async fn get_owner_usage(owner: web::Path<String>, data: web::Data<AppState>) -> impl Responder {
+    let jobs = data.jobs.lock().unwrap();
+    let mut totals = OwnerUsage::default();
+    let per_job: Vec<_> = jobs
+        .iter()
+        .filter(|j| j.owner.eq_ignore_ascii_case(&owner) && finished(j))
+        .inspect(|j| totals.add(j))
+        .map(|j| {
+            json!({
+                "jobId": j.job_id,
+                "status": j.status,
+                "limits": j.limits,
+                "usage": j.usage,
+                "overruns": j.overruns,
+                "gpu_hours": j.gpu_hours,
+            })
+        })
+        .collect();
+
+    HttpResponse::Ok().json(json!({
+        "owner": owner.to_lowercase(),
+        "totals": totals,
+        "jobs": per_job,
+    }))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.route("/usage", web::get().to(get_usage))
+        .route("/usage/{owner}", web::get().to(get_owner_usage));
+}
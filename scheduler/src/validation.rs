@@ -0,0 +1,237 @@
+use actix_web::HttpResponse;
+use chrono::{DateTime, NaiveDate, Utc};
+use ethers::types::Address;
+use ethers::utils::to_checksum;
+use serde::Serialize;
+use serde_json::json;
+use std::str::FromStr;
+use url::Url;
+
+pub const MAX_ID_LEN: usize = 64;
+pub const MAX_SPECS_LEN: usize = 128;
+pub const MAX_MEMORY_GB: u64 = 2048;
+pub const MAX_GPUS: usize = 16;
+pub const MAX_DEPENDENCIES: usize = 32;
+pub const MAX_ARRAY_SIZE: usize = 1000;
+
+const BASE58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ValidationErrors {
+    errors: Vec<FieldError>,
+}
+
+impl ValidationErrors {
+    pub fn add(&mut self, field: &str, message: impl Into<String>) {
+        self.errors.push(FieldError {
+            field: field.to_string(),
+            message: message.into(),
+        });
+    }
+
+    /// Records the error from `result` under `field` and passes the value through.
+    pub fn check<T>(&mut self, field: &str, result: Result<T, String>) -> Option<T> {
+        match result {
+            Ok(v) => Some(v),
+            Err(message) => {
+                self.add(field, message);
+                None
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &FieldError> {
+        self.errors.iter()
+    }
+
+    pub fn extend(&mut self, other: ValidationErrors) {
+        self.errors.extend(other.errors);
+    }
+
+    /// Nests every field under `prefix`, e.g. `jobId` becomes `template.jobId`.
+    pub fn prefixed(mut self, prefix: &str) -> Self {
+        for error in &mut self.errors {
+            error.field = format!("{}.{}", prefix, error.field);
+        }
+        self
+    }
+
+    /// One line per field, for logs and stored error messages.
+    pub fn summary(&self) -> String {
+        self.errors
+            .iter()
+            .map(|e| format!("{} {}", e.field, e.message))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    pub fn into_response(self) -> HttpResponse {
+        HttpResponse::UnprocessableEntity().json(json!({
+            "error": "Validation failed",
+            "fields": self.errors,
+        }))
+    }
+}
+
+pub fn required(value: &Option<String>) -> Result<&str, String> {
+    match value.as_deref().map(str::trim) {
+        Some(v) if !v.is_empty() => Ok(v),
+        _ => Err("is required".to_string()),
+    }
+}
+
+pub fn identifier(value: &str) -> Result<(), String> {
+    if value.len() > MAX_ID_LEN {
+        return Err(format!("must be at most {} characters", MAX_ID_LEN));
+    }
+    if !value
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err("may only contain letters, digits, '-' and '_'".to_string());
+    }
+    Ok(())
+}
+
+/// Accepts all-lowercase or all-uppercase hex, and mixed case only when it
+/// matches the EIP-55 checksum. Returns the checksummed form.
+pub fn checksummed_address(value: &str) -> Result<String, String> {
+    let hex = value
+        .strip_prefix("0x")
+        .ok_or_else(|| "must be a 0x-prefixed address".to_string())?;
+    if hex.len() != 40 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err("must be 20 bytes of hex".to_string());
+    }
+    let address = Address::from_str(value).map_err(|e| e.to_string())?;
+    let checksummed = to_checksum(&address, None);
+    let is_uniform_case = hex == hex.to_lowercase() || hex == hex.to_uppercase();
+    if !is_uniform_case && checksummed != value {
+        return Err("has an invalid EIP-55 checksum".to_string());
+    }
+    Ok(checksummed)
+}
+
+pub fn is_cid(value: &str) -> bool {
+    // CIDv0: base58btc multihash of a sha2-256 digest.
+    if value.len() == 46 && value.starts_with("Qm") {
+        return value.chars().all(|c| BASE58_ALPHABET.contains(c));
+    }
+    // CIDv1 in the default base32 multibase encoding.
+    if value.len() >= 50 && value.starts_with('b') {
+        return value[1..]
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || ('2'..='7').contains(&c));
+    }
+    false
+}
+
+pub fn http_url(value: &str) -> Result<Url, String> {
+    let url = Url::parse(value).map_err(|e| format!("is not a valid URL: {}", e))?;
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+        return Err("must be an http(s) URL with a host".to_string());
+    }
+    Ok(url)
+}
+
+fn is_name_component(part: &str) -> bool {
+    !part.is_empty()
+        && part.split(['.', '_', '-']).all(|s| {
+            !s.is_empty()
+                && s.chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        })
+}
+
+/// A container image reference (`[registry/]name[:tag][@sha256:digest]`) or a CID.
+pub fn container_ref(value: &str) -> Result<(), String> {
+    if is_cid(value) {
+        return Ok(());
+    }
+    let invalid = || "must be a container image reference or CID".to_string();
+
+    let (name_and_tag, digest) = match value.split_once('@') {
+        Some((n, d)) => (n, Some(d)),
+        None => (value, None),
+    };
+    if let Some(digest) = digest {
+        let hex = digest
+            .strip_prefix("sha256:")
+            .ok_or_else(|| "digest must be sha256:<hex>".to_string())?;
+        if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err("digest must be 64 hex characters".to_string());
+        }
+    }
+
+    // A ':' after the last '/' separates the tag; earlier ones belong to a registry port.
+    let last_slash = name_and_tag.rfind('/').map(|i| i + 1).unwrap_or(0);
+    let (name, tag) = match name_and_tag[last_slash..].rfind(':') {
+        Some(i) => (
+            &name_and_tag[..last_slash + i],
+            Some(&name_and_tag[last_slash + i + 1..]),
+        ),
+        None => (name_and_tag, None),
+    };
+    if let Some(tag) = tag {
+        let valid_tag = !tag.is_empty()
+            && tag.len() <= 128
+            && tag
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+        if !valid_tag {
+            return Err("has an invalid tag".to_string());
+        }
+    }
+
+    let mut parts: Vec<&str> = name.split('/').collect();
+    if parts.len() > 1
+        && (parts[0].contains('.') || parts[0].contains(':') || parts[0] == "localhost")
+    {
+        parts.remove(0);
+    }
+    if parts.is_empty() || !parts.iter().all(|p| is_name_component(p)) {
+        return Err(invalid());
+    }
+    Ok(())
+}
+
+/// Parses an RFC 3339 timestamp or a bare `YYYY-MM-DD` date (midnight UTC).
+pub fn parse_deadline(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Ok(dt.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|d| d.and_hms_opt(0, 0, 0).unwrap().and_utc())
+        .map_err(|_| "must be an RFC 3339 timestamp or YYYY-MM-DD date".to_string())
+}
+
+pub fn future_deadline(value: &str) -> Result<DateTime<Utc>, String> {
+    let deadline = parse_deadline(value)?;
+    if deadline <= Utc::now() {
+        return Err("must be in the future".to_string());
+    }
+    Ok(deadline)
+}
+
+pub fn memory_gb(value: i64) -> Result<u64, String> {
+    if value < 1 || value as u64 > MAX_MEMORY_GB {
+        return Err(format!("must be between 1 and {} GB", MAX_MEMORY_GB));
+    }
+    Ok(value as u64)
+}
+
+pub fn bounded_text(value: &str, max: usize) -> Result<(), String> {
+    if value.len() > max {
+        return Err(format!("must be at most {} characters", max));
+    }
+    Ok(())
+}
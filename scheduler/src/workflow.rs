@@ -0,0 +1,424 @@
+use crate::history::{JobEvent, JobEventKind};
+use crate::models::Job;
+use crate::state::AppState;
+use actix_web::{web, HttpResponse, Responder};
+use serde_json::json;
+use std::collections::BTreeMap;
+use tracing::{info, warn};
+
+pub fn parents_complete(job: &Job, jobs: &[Job]) -> bool {
+    job.depends_on.iter().all(|parent_id| {
+        jobs.iter()
+            .any(|j| &j.job_id == parent_id && j.status == "completed")
+    })
+}
+
+/// Fills in the job's inputs from its parents' result hashes. Jobs stored
+/// before intake rejected a mapped dataset fail here, as a result hash cannot
+/// be fetched in its place.
+fn resolve_inputs(job: &mut Job, jobs: &[Job]) -> Result<(), String> {
+    if let Some(parent_id) = job.input_map.get("dataset") {
+        return Err(format!(
+            "Dataset mapped from {}'s result, which is not a fetchable dataset",
+            parent_id
+        ));
+    }
+    for (input, parent_id) in &job.input_map {
+        let hash = jobs
+            .iter()
+            .find(|j| &j.job_id == parent_id)
+            .and_then(|j| j.result_hash.clone());
+        if let Some(hash) = hash {
+            job.inputs.insert(input.clone(), hash);
+        }
+    }
+    Ok(())
+}
+
+/// Places a new job in its parents' workflow (the first parent's id starts
+/// one) and blocks it until they complete. A job whose inputs cannot be
+/// resolved is cancelled, and the reason returned for the caller to record.
+pub fn attach(job: &mut Job, jobs: &mut [Job]) -> Result<(), String> {
+    if job.depends_on.is_empty() {
+        return Ok(());
+    }
+    let workflow_id = job
+        .workflow_id
+        .clone()
+        .or_else(|| {
+            jobs.iter()
+                .filter(|j| job.depends_on.contains(&j.job_id))
+                .find_map(|j| j.workflow_id.clone())
+        })
+        .unwrap_or_else(|| job.depends_on[0].clone());
+    for parent in jobs
+        .iter_mut()
+        .filter(|j| job.depends_on.contains(&j.job_id) && j.workflow_id.is_none())
+    {
+        parent.workflow_id = Some(workflow_id.clone());
+    }
+    job.workflow_id = Some(workflow_id);
+
+    if !parents_complete(job, jobs) {
+        job.status = "blocked".to_string();
+    } else if let Err(reason) = resolve_inputs(job, jobs) {
+        job.status = "cancelled".to_string();
+        return Err(reason);
+    }
+    Ok(())
+}
+
+/// Queues blocked children of `job_id` whose parents have now all completed.
+/// Children whose inputs cannot be resolved are cancelled along with their
+/// own dependents. Returns the events for the caller to record.
+pub fn unblock_dependents(jobs: &mut [Job], job_id: &str) -> Vec<JobEvent> {
+    let ready: Vec<usize> = jobs
+        .iter()
+        .enumerate()
+        .filter(|(_, j)| {
+            j.status == "blocked"
+                && j.depends_on.iter().any(|d| d == job_id)
+                && parents_complete(j, jobs)
+        })
+        .map(|(i, _)| i)
+        .collect();
+
+    let mut events = Vec::new();
+    let mut cancelled = Vec::new();
+    for i in ready {
+        let mut child = jobs[i].clone();
+        if let Err(reason) = resolve_inputs(&mut child, jobs) {
+            child.status = "cancelled".to_string();
+            warn!("Cancelled job {}: {}", child.job_id, reason);
+            events.push(JobEvent::new(
+                &child.job_id,
+                JobEventKind::Cancelled,
+                reason,
+            ));
+            cancelled.push(child.job_id.clone());
+            jobs[i] = child;
+            continue;
+        }
+        child.status = "pending".to_string();
+        events.push(JobEvent::new(
+            &child.job_id,
+            JobEventKind::Unblocked,
+            format!("Dependencies completed (last: {})", job_id),
+        ));
+        info!("Job {} unblocked by {}", child.job_id, job_id);
+        jobs[i] = child;
+    }
+    for job_id in cancelled {
+        events.extend(cascade_cancel(jobs, &job_id));
+    }
+    events
+}
+
+/// Cancels every blocked job downstream of `job_id`, which failed or was
+/// cancelled, and returns the events describing it for the caller to record.
+pub fn cascade_cancel(jobs: &mut [Job], job_id: &str) -> Vec<JobEvent> {
+    let mut events = Vec::new();
+    let mut frontier = vec![job_id.to_string()];
+    while let Some(upstream) = frontier.pop() {
+        for job in jobs
+            .iter_mut()
+            .filter(|j| j.status == "blocked" && j.depends_on.contains(&upstream))
+        {
+            job.status = "cancelled".to_string();
+            events.push(JobEvent::new(
+                &job.job_id,
+                JobEventKind::Cancelled,
+                format!("Upstream job {} did not complete", job_id),
+            ));
+            warn!(
+                "Cancelled job {} after upstream {} did not complete",
+                job.job_id, job_id
+            );
+            frontier.push(job.job_id.clone());
+        }
+    }
+    events
+}
+
+/// Records `events` and publishes the jobs they describe.
+fn publish(data: &web::Data<AppState>, jobs: &[Job], events: Vec<JobEvent>) {
+    for event in events {
+        if let Some(job) = jobs.iter().find(|j| j.job_id == event.job_id) {
+            data.events.publish_job(job);
+        }
+        data.history.record(event);
+    }
+}
+
+/// Queues the children `job_id`'s completion unblocks.
+pub fn release_dependents(data: &web::Data<AppState>, jobs: &mut [Job], job_id: &str) {
+    let events = unblock_dependents(jobs, job_id);
+    publish(data, jobs, events);
+}
+
+/// Cancels every job downstream of `job_id`, which failed or was cancelled.
+pub fn cancel_dependents(data: &web::Data<AppState>, jobs: &mut [Job], job_id: &str) {
+    let events = cascade_cancel(jobs, job_id);
+    publish(data, jobs, events);
+}
+
+/// Overall state of a group of jobs, shared by workflows and job arrays.
+pub fn group_state(jobs: &[&Job]) -> &'static str {
+    let any = |statuses: &[&str]| jobs.iter().any(|j| statuses.contains(&j.status.as_str()));
+    if any(&["failed", "cancelled"]) {
+        "failed"
+    } else if jobs.iter().all(|j| j.status == "completed") {
+        "completed"
+    } else if any(&["assigning", "assigned", "running", "completed"]) {
+        "running"
+    } else {
+        "pending"
+    }
+}
+
+async fn get_workflow(
+    workflow_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let jobs = data.jobs.lock().unwrap();
+    let members: Vec<&Job> = jobs
+        .iter()
+        .filter(|j| j.workflow_id.as_deref() == Some(workflow_id.as_str()))
+        .collect();
+    if members.is_empty() {
+        return HttpResponse::NotFound().json(json!({ "error": "Workflow not found" }));
+    }
+
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for job in &members {
+        *counts.entry(job.status.as_str()).or_default() += 1;
+    }
+    let edges: Vec<_> = members
+        .iter()
+        .flat_map(|child| {
+            child
+                .depends_on
+                .iter()
+                .map(move |parent| json!({ "from": parent, "to": child.job_id }))
+        })
+        .collect();
+    let nodes: Vec<_> = members
+        .iter()
+        .map(|j| {
+            json!({
+                "jobId": j.job_id,
+                "status": j.status,
+                "dependsOn": j.depends_on,
+                "inputMap": j.input_map,
+                "inputs": j.inputs,
+                "result_hash": j.result_hash,
+                "error": j.error,
+            })
+        })
+        .collect();
+
+    HttpResponse::Ok().json(json!({
+        "workflow_id": *workflow_id,
+        "state": group_state(&members),
+        "counts_by_status": counts,
+        "jobs": nodes,
+        "edges": edges,
+    }))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.route("/workflows/{id}", web::get().to(get_workflow));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::TokenAmount;
+    use ethers::types::U256;
+
+    fn job(job_id: &str, status: &str, depends_on: &[&str]) -> Job {
+        let mut job = Job::new(
+            job_id.to_string(),
+            "0x0".to_string(),
+            None,
+            "train:1".to_string(),
+            TokenAmount::native(U256::one()),
+            "2999-01-01".to_string(),
+            "A100".to_string(),
+            16,
+        );
+        job.status = status.to_string();
+        job.depends_on = depends_on.iter().map(|d| d.to_string()).collect();
+        job
+    }
+
+    fn completed(job_id: &str, result_hash: &str) -> Job {
+        let mut job = job(job_id, "completed", &[]);
+        job.result_hash = Some(result_hash.to_string());
+        job
+    }
+
+    fn statuses(jobs: &[Job]) -> Vec<(&str, &str)> {
+        jobs.iter()
+            .map(|j| (j.job_id.as_str(), j.status.as_str()))
+            .collect()
+    }
+
+    fn event_jobs(events: &[JobEvent]) -> Vec<&str> {
+        events.iter().map(|e| e.job_id.as_str()).collect()
+    }
+
+    #[test]
+    fn jobs_without_parents_are_left_alone() {
+        let mut child = job("child", "pending", &[]);
+        attach(&mut child, &mut []).unwrap();
+        assert_eq!(child.status, "pending");
+        assert!(child.workflow_id.is_none());
+    }
+
+    #[test]
+    fn the_first_parent_starts_the_workflow() {
+        let mut jobs = vec![job("a", "running", &[]), job("b", "running", &[])];
+        let mut child = job("child", "pending", &["a", "b"]);
+        attach(&mut child, &mut jobs).unwrap();
+        assert_eq!(child.status, "blocked");
+        assert_eq!(child.workflow_id.as_deref(), Some("a"));
+        assert!(jobs.iter().all(|j| j.workflow_id.as_deref() == Some("a")));
+    }
+
+    #[test]
+    fn children_join_a_parent_workflow_unless_they_name_one() {
+        let mut parent = job("a", "running", &[]);
+        parent.workflow_id = Some("etl".to_string());
+        let mut jobs = vec![parent];
+
+        let mut child = job("child", "pending", &["a"]);
+        attach(&mut child, &mut jobs).unwrap();
+        assert_eq!(child.workflow_id.as_deref(), Some("etl"));
+
+        let mut named = job("named", "pending", &["a"]);
+        named.workflow_id = Some("report".to_string());
+        attach(&mut named, &mut jobs).unwrap();
+        assert_eq!(named.workflow_id.as_deref(), Some("report"));
+        assert_eq!(jobs[0].workflow_id.as_deref(), Some("etl"));
+    }
+
+    #[test]
+    fn completed_parents_feed_inputs_at_once() {
+        let mut jobs = vec![completed("a", "0xabc")];
+        let mut child = job("child", "pending", &["a"]);
+        child
+            .input_map
+            .insert("weights".to_string(), "a".to_string());
+        attach(&mut child, &mut jobs).unwrap();
+        assert_eq!(child.status, "pending");
+        assert_eq!(child.inputs["weights"], "0xabc");
+    }
+
+    #[test]
+    fn a_mapped_dataset_cancels_the_child() {
+        let mut jobs = vec![completed("a", "0xabc")];
+        let mut child = job("child", "pending", &["a"]);
+        child
+            .input_map
+            .insert("dataset".to_string(), "a".to_string());
+        assert!(attach(&mut child, &mut jobs).is_err());
+        assert_eq!(child.status, "cancelled");
+    }
+
+    #[test]
+    fn a_missing_parent_keeps_the_child_blocked() {
+        let mut jobs = vec![completed("a", "0xabc")];
+        let mut child = job("child", "pending", &["a", "gone"]);
+        attach(&mut child, &mut jobs).unwrap();
+        assert_eq!(child.status, "blocked");
+
+        jobs.push(child);
+        assert!(unblock_dependents(&mut jobs, "a").is_empty());
+        assert_eq!(jobs[1].status, "blocked");
+    }
+
+    #[test]
+    fn children_wait_for_every_parent() {
+        let mut child = job("child", "blocked", &["a", "b"]);
+        child.input_map.insert("left".to_string(), "a".to_string());
+        child.input_map.insert("right".to_string(), "b".to_string());
+        let mut jobs = vec![completed("a", "0x1"), job("b", "running", &[]), child];
+
+        assert!(unblock_dependents(&mut jobs, "a").is_empty());
+        assert_eq!(jobs[2].status, "blocked");
+
+        jobs[1] = completed("b", "0x2");
+        let events = unblock_dependents(&mut jobs, "b");
+        assert_eq!(event_jobs(&events), vec!["child"]);
+        assert_eq!(jobs[2].status, "pending");
+        assert_eq!(jobs[2].inputs["left"], "0x1");
+        assert_eq!(jobs[2].inputs["right"], "0x2");
+    }
+
+    #[test]
+    fn an_unresolvable_child_is_cancelled_with_its_dependents() {
+        let mut child = job("child", "blocked", &["a"]);
+        child
+            .input_map
+            .insert("dataset".to_string(), "a".to_string());
+        let mut jobs = vec![
+            completed("a", "0x1"),
+            child,
+            job("grandchild", "blocked", &["child"]),
+        ];
+        let events = unblock_dependents(&mut jobs, "a");
+        assert_eq!(event_jobs(&events), vec!["child", "grandchild"]);
+        assert_eq!(jobs[1].status, "cancelled");
+        assert_eq!(jobs[2].status, "cancelled");
+    }
+
+    #[test]
+    fn cascade_cancels_only_blocked_descendants() {
+        let mut jobs = vec![
+            job("a", "failed", &[]),
+            job("b", "blocked", &["a"]),
+            job("c", "blocked", &["b"]),
+            job("d", "blocked", &["b", "c"]),
+            job("e", "pending", &["a"]),
+            job("other", "blocked", &["x"]),
+        ];
+        let events = cascade_cancel(&mut jobs, "a");
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            statuses(&jobs),
+            vec![
+                ("a", "failed"),
+                ("b", "cancelled"),
+                ("c", "cancelled"),
+                ("d", "cancelled"),
+                ("e", "pending"),
+                ("other", "blocked"),
+            ]
+        );
+    }
+
+    #[test]
+    fn cascade_terminates_on_a_cycle() {
+        let mut jobs = vec![
+            job("a", "failed", &[]),
+            job("b", "blocked", &["a", "c"]),
+            job("c", "blocked", &["b"]),
+        ];
+        let events = cascade_cancel(&mut jobs, "a");
+        assert_eq!(event_jobs(&events), vec!["b", "c"]);
+        assert!(jobs[1..].iter().all(|j| j.status == "cancelled"));
+    }
+
+    #[test]
+    fn group_state_summarises_members() {
+        let state = |statuses: &[&str]| {
+            let jobs: Vec<Job> = statuses.iter().map(|s| job("j", s, &[])).collect();
+            group_state(&jobs.iter().collect::<Vec<_>>())
+        };
+        assert_eq!(state(&["completed", "completed"]), "completed");
+        assert_eq!(state(&["completed", "blocked"]), "running");
+        assert_eq!(state(&["pending", "blocked"]), "pending");
+        assert_eq!(state(&["running", "cancelled"]), "failed");
+    }
+}